@@ -39,6 +39,8 @@ fn main() {
     flame::start("run");
     editor.run(keys);
     flame::end("run");
+    // the next run shouldn't ask to recover
+    let _ = std::fs::remove_file(edit_buffer::swap::swap_file_path(file));
 
     flame::dump_html(&mut std::fs::File::create("output.html").unwrap()).unwrap();
 }
\ No newline at end of file
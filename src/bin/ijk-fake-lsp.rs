@@ -0,0 +1,105 @@
+// A tiny language server for testing.
+// It keeps the documents in sync and reports the lines containing "error" or "warning".
+// The argument is the milliseconds to take to initialize.
+
+use std::collections::HashMap;
+use std::io::{self, BufReader};
+use serde_json::{json, Value};
+use ijk::lsp::jsonrpc::{read_message, write_message};
+
+fn offset(text: &str, pos: &Value) -> usize {
+    let line = pos["line"].as_u64().unwrap() as usize;
+    let character = pos["character"].as_u64().unwrap() as usize;
+    let mut off = 0;
+    for (i, l) in text.split_inclusive('\n').enumerate() {
+        if i == line {
+            // the character offset is in UTF-16 code units
+            let mut n = 0;
+            for (j, c) in l.char_indices() {
+                if n >= character {
+                    return off + j
+                }
+                n += c.len_utf16();
+            }
+            return off + l.len()
+        }
+        off += l.len();
+    }
+    off
+}
+
+fn diagnostics(uri: &str, text: &str) -> Value {
+    let mut v = vec![];
+    for (i, l) in text.lines().enumerate() {
+        for (word, severity) in &[("error", 1), ("warning", 2)] {
+            if l.contains(word) {
+                v.push(json!({
+                    "range": { "start": { "line": i, "character": 0 }, "end": { "line": i, "character": 0 } },
+                    "severity": severity,
+                    "message": format!("found {}", word),
+                }));
+            }
+        }
+    }
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": v },
+    })
+}
+
+fn main() {
+    let stdin = io::stdin();
+    let mut inp = BufReader::new(stdin.lock());
+    let mut out = io::stdout();
+    let mut docs: HashMap<String, String> = HashMap::new();
+    let init_delay = std::env::args().nth(1).and_then(|x| x.parse().ok()).unwrap_or(0);
+    let mut initialized = false;
+
+    while let Ok(Some(msg)) = read_message(&mut inp) {
+        let params = &msg["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_owned();
+        let method = msg["method"].as_str().unwrap_or("");
+        // the documents aren't known before initialized
+        if method.starts_with("textDocument/") && !initialized {
+            continue;
+        }
+        match method {
+            "initialize" => {
+                std::thread::sleep(std::time::Duration::from_millis(init_delay));
+                let res = json!({ "jsonrpc": "2.0", "id": msg["id"], "result": { "capabilities": { "textDocumentSync": 2 } } });
+                write_message(&mut out, &res).unwrap();
+            },
+            "initialized" => initialized = true,
+            "shutdown" => {
+                let res = json!({ "jsonrpc": "2.0", "id": msg["id"], "result": null });
+                write_message(&mut out, &res).unwrap();
+            },
+            "exit" => break,
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap().to_owned();
+                write_message(&mut out, &diagnostics(&uri, &text)).unwrap();
+                docs.insert(uri, text);
+            },
+            "textDocument/didChange" => {
+                let text = docs.get_mut(&uri).unwrap();
+                for change in params["contentChanges"].as_array().unwrap() {
+                    let new_text = change["text"].as_str().unwrap();
+                    match change.get("range") {
+                        Some(range) => {
+                            let start = offset(text, &range["start"]);
+                            let end = offset(text, &range["end"]);
+                            text.replace_range(start..end, new_text);
+                        },
+                        None => *text = new_text.to_owned(),
+                    }
+                }
+                write_message(&mut out, &diagnostics(&uri, text)).unwrap();
+            },
+            "textDocument/didClose" => {
+                docs.remove(&uri);
+            },
+            _ => {},
+        }
+    }
+}
@@ -6,17 +6,24 @@ use std::fs;
 use clap::{App, Arg};
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::sync::mpsc;
 use termion::input::TermRead;
 
 use ijk::directory;
 use ijk::edit_buffer;
 use ijk::navigator;
+use ijk::session;
+use ijk::watcher;
 
 fn main() {
     let matches = App::new("ijk")
         .about("A real editor for real programmers")
         .bin_name("ijk")
         .arg(Arg::with_name("path"))
+        .arg(Arg::with_name("session")
+             .long("session")
+             .takes_value(true)
+             .help("restores the session and saves it on exit (default: saved in the default session)"))
         .get_matches();
 
     let file_path: Option<&OsStr> = matches.value_of_os("path");
@@ -24,28 +31,74 @@ fn main() {
     
     let navigator = Rc::new(RefCell::new(navigator::Navigator::new()));
 
-    let page: Rc<RefCell<navigator::Page>> = match path {
+    let session_name = matches.value_of("session").unwrap_or(session::DEFAULT_SESSION);
+    let session_path = match session::session_path(session_name) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        },
+    };
+    let restored = if matches.is_present("session") {
+        match session::restore(&session_path, navigator.clone()) {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("failed to restore the session: {}", e);
+                false
+            },
+        }
+    } else {
+        false
+    };
+
+    // the path opens on top of the session
+    let page: Option<Rc<RefCell<navigator::Page>>> = match path {
         Some(path) if path.is_dir() => {
             let dir = Rc::new(RefCell::new(directory::Directory::open(path, navigator.clone())));
-            Rc::new(RefCell::new(directory::Page::new(dir, fs::canonicalize(path).unwrap())))
+            Some(Rc::new(RefCell::new(directory::Page::new(dir, fs::canonicalize(path).unwrap()))))
         },
-        Some(path) => { // existing/unexisting file
-            let eb = Rc::new(RefCell::new(edit_buffer::EditBuffer::open(path, navigator.clone())));
-            Rc::new(RefCell::new(edit_buffer::Page::new(eb)))
+        Some(path) => match ijk::large_file::open(path, 0) {
+            Some(page) => Some(page),
+            None => { // existing/unexisting file
+                let eb = Rc::new(RefCell::new(edit_buffer::EditBuffer::open(path, navigator.clone())));
+                Some(Rc::new(RefCell::new(edit_buffer::Page::new(eb))))
+            },
         },
+        None if restored => None,
         None => {
             let cur_dir = std::env::current_dir().unwrap();
             let dir = Rc::new(RefCell::new(directory::Directory::open(&cur_dir, navigator.clone())));
-            Rc::new(RefCell::new(directory::Page::new(dir, fs::canonicalize(cur_dir).unwrap())))
+            Some(Rc::new(RefCell::new(directory::Page::new(dir, fs::canonicalize(cur_dir).unwrap()))))
         }
     };
 
-    navigator.borrow_mut().push(page);
+    if let Some(page) = page {
+        navigator.borrow_mut().push(page);
+    }
     
-    let mut editor = ijk::editor::Editor::new(navigator, ijk::editor::TerminalScreen::new());
+    let mut editor = ijk::editor::Editor::new(navigator.clone(), ijk::editor::TerminalScreen::new());
+
+    // the keys and the wake-ups by the watcher
+    let (tx, rx) = mpsc::channel();
+    let wake = tx.clone();
+    editor.set_watcher(watcher::Watcher::new(move || {
+        let _ = wake.send(Ok(termion::event::Key::Null));
+    }));
+    std::thread::spawn(move || {
+        let stdin = std::io::stdin();
+        for k in stdin.keys() {
+            if tx.send(k).is_err() {
+                break
+            }
+        }
+    });
+    editor.run(rx.into_iter());
+    // restores the terminal
+    drop(editor);
+
+    if let Err(e) = session::save(&session_path, &navigator.borrow()) {
+        eprintln!("failed to save the session: {}", e);
+    }
 
-    let stdin = std::io::stdin();
-    // let stdin = termion::async_stdin();
-    let keys = stdin.keys();
-    editor.run(keys);
+    ijk::lsp::shutdown_all();
 }
\ No newline at end of file
@@ -1,14 +1,16 @@
 use super::file_parser::{FileToml, LangToml};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use super::Lang;
 
 pub struct LangConfig {
     pub indent: Option<usize>,
+    pub lsp: Option<Vec<String>>,
 }
 impl LangConfig {
     fn default() -> Self {
         Self {
              indent: None,
+             lsp: None,
         }
     }
 }
@@ -17,6 +19,14 @@ pub struct Builder {
     pub configs: HashMap<Lang, LangConfig>,
     pub filenames: HashMap<String, Lang>,
     pub extensions: HashMap<String, Lang>,
+    pub undo_limit: Option<usize>,
+    pub undo_persist: bool,
+    pub clipboard_provider: String,
+    pub clipboard_path: Option<String>,
+    pub keys: BTreeMap<(String, String), BTreeMap<String, String>>,
+    pub macro_persist: bool,
+    pub backup: bool,
+    pub large_file_threshold: u64,
 }
 impl Builder {
     pub fn new() -> Self {
@@ -24,6 +34,14 @@ impl Builder {
             configs: HashMap::new(),
             filenames: HashMap::new(),
             extensions: HashMap::new(),
+            undo_limit: None,
+            undo_persist: false,
+            clipboard_provider: "auto".to_owned(),
+            clipboard_path: None,
+            keys: BTreeMap::new(),
+            macro_persist: false,
+            backup: false,
+            large_file_threshold: 10_000_000,
         }
     }
     fn add_lang_config(&mut self, lang: String, config: LangToml) {
@@ -41,6 +59,9 @@ impl Builder {
                 c.indent = Some(i)
             }
         }
+        if let Some(command) = config.lsp {
+            self.configs.get_mut(&lang).unwrap().lsp = Some(command);
+        }
     }
     pub fn add_config_file(&mut self, config: FileToml) {
         for m in config.lang {
@@ -48,5 +69,39 @@ impl Builder {
                 self.add_lang_config(lang, lang_config)
             }
         }
+        if let Some(undo) = config.undo {
+            if let Some(n) = undo.limit {
+                // 0 for unlimited
+                self.undo_limit = if n > 0 { Some(n) } else { None };
+            }
+            if let Some(b) = undo.persist {
+                self.undo_persist = b;
+            }
+        }
+        if let Some(clipboard) = config.clipboard {
+            if let Some(name) = clipboard.provider {
+                self.clipboard_provider = name;
+            }
+            if let Some(path) = clipboard.path {
+                self.clipboard_path = Some(path);
+            }
+        }
+        if let Some(b) = config.macros.and_then(|x| x.persist) {
+            self.macro_persist = b;
+        }
+        if let Some(b) = config.save.and_then(|x| x.backup) {
+            self.backup = b;
+        }
+        if let Some(n) = config.large_file.and_then(|x| x.threshold) {
+            self.large_file_threshold = n;
+        }
+        if let Some(pages) = config.keys {
+            for (page, states) in pages {
+                for (state, bindings) in states {
+                    // the later file overrides the same key sequence
+                    self.keys.entry((page.clone(), state)).or_default().extend(bindings);
+                }
+            }
+        }
     }
 }
\ No newline at end of file
@@ -4,13 +4,50 @@ use std::collections::BTreeMap;
 #[derive(Debug, Deserialize)]
 pub struct FileToml {
     pub lang: Option<BTreeMap<String, LangToml>>,
+    pub undo: Option<UndoToml>,
+    pub clipboard: Option<ClipboardToml>,
+    pub keys: Option<BTreeMap<String, KeysToml>>,
+    pub macros: Option<MacrosToml>,
+    pub save: Option<SaveToml>,
+    pub large_file: Option<LargeFileToml>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct MacrosToml {
+    pub persist: Option<bool>,
+}
+
+// state -> key sequence -> effect
+pub type KeysToml = BTreeMap<String, BTreeMap<String, String>>;
+
 #[derive(Debug, Deserialize)]
 pub struct LangToml {
     pub extensions: Option<Vec<String>>,
     pub filenames: Option<Vec<String>>,
     pub indent: Option<usize>,
+    pub lsp: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveToml {
+    pub backup: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LargeFileToml {
+    pub threshold: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UndoToml {
+    pub limit: Option<usize>,
+    pub persist: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClipboardToml {
+    pub provider: Option<String>,
+    pub path: Option<String>,
 }
 
 #[test]
@@ -23,6 +60,18 @@ fn test_file_parser() {
     extensions = ["rb", "erb"]
     filenames = ["Rakefile"]
     indent = 2
+    lsp = ["solargraph", "stdio"]
+    [undo]
+    limit = 1000
+    persist = true
+    [clipboard]
+    provider = "file"
+    path = "/tmp/ijk-clipboard"
+    [keys.buffer.Normal]
+    "U" = "Redo"
+    "g g" = "Move G"
+    [macros]
+    persist = true
     "#;
 
     let config: FileToml = toml::from_str(&data).unwrap();
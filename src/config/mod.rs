@@ -3,7 +3,7 @@ mod builder;
 
 use std::path::{Path, PathBuf};
 use crate::edit_buffer::config::Config;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use self::builder::Builder;
 
 use crate::edit_buffer::indent::IndentType::*;
@@ -11,6 +11,11 @@ use crate::edit_buffer::indent::IndentType::*;
 const FALLBACK_CONFIG: Config = Config {
     indent_type: Tab,
     snippet: None,
+    lang: None,
+    lsp: None,
+    undo_limit: None,
+    undo_persist: false,
+    backup: false,
 };
 
 use lazy_static::lazy_static;
@@ -25,13 +30,51 @@ pub struct ConfigRepo {
     snippets: HashMap<Lang, PathBuf>,
     extensions: HashMap<String, Lang>,
     filenames: HashMap<String, Lang>,
+    undo_limit: Option<usize>,
+    undo_persist: bool,
+    clipboard_provider: String,
+    clipboard_path: Option<PathBuf>,
+    keys: BTreeMap<(String, String), BTreeMap<String, String>>,
+    macro_persist: bool,
+    backup: bool,
+    large_file_threshold: u64,
 }
 impl ConfigRepo {
+    /// The name of the clipboard provider and the path for the file provider.
+    pub fn clipboard(&self) -> (&str, Option<&Path>) {
+        (&self.clipboard_provider, self.clipboard_path.as_deref())
+    }
+    /// Macros are saved in ~/.ijk/macros.
+    pub fn macro_persist(&self) -> bool {
+        self.macro_persist
+    }
+    /// The files of this size or larger are opened read-only.
+    pub fn large_file_threshold(&self) -> u64 {
+        self.large_file_threshold
+    }
+    /// The key bindings of the page as (state, key sequence, effect).
+    pub fn keys(&self, page: &str) -> Vec<(&str, &str, &str)> {
+        let mut v = vec![];
+        for ((p, state), bindings) in &self.keys {
+            if p != page {
+                continue;
+            }
+            for (seq, eff) in bindings {
+                v.push((state.as_str(), seq.as_str(), eff.as_str()));
+            }
+        }
+        v
+    }
     pub fn get_config(&self, path: &Path) -> Config {
-        match self.infer_lang(path) {
+        let mut config = match self.infer_lang(path) {
             Some(lang) => self.do_get_config(&lang),
             None => FALLBACK_CONFIG,
-        }
+        };
+        // not language specific
+        config.undo_limit = self.undo_limit;
+        config.undo_persist = self.undo_persist;
+        config.backup = self.backup;
+        config
     }
     fn do_get_config(&self, lang: &str) -> Config {
         let indent_type = match self.configs.get(lang) {
@@ -47,7 +90,12 @@ impl ConfigRepo {
         };
         Config {
             indent_type,
-            snippet: self.snippets.get(lang).cloned()
+            snippet: self.snippets.get(lang).cloned(),
+            lang: Some(lang.to_owned()),
+            lsp: self.configs.get(lang).and_then(|lc| lc.lsp.clone()),
+            undo_limit: None,
+            undo_persist: false,
+            backup: false,
         }
     }
     fn infer_lang(&self, path: &Path) -> Option<Lang> {
@@ -67,9 +115,22 @@ impl ConfigRepo {
     }
 }
 
-fn list_snippet_files() -> HashMap<Lang, PathBuf> {
+/// ~/.ijk or $IJK_DIR if set.
+pub fn ijk_dir() -> PathBuf {
+    if let Some(x) = std::env::var_os("IJK_DIR") {
+        return PathBuf::from(x)
+    }
+    // the tests don't touch the files of the user
+    if cfg!(test) {
+        return Path::new(env!("CARGO_MANIFEST_DIR")).join("target").join("ijk")
+    }
+    #[allow(deprecated)]
     let home_dir = std::env::home_dir().unwrap();
-    let snippet_dir = home_dir.join(".ijk").join("snippets");
+    home_dir.join(".ijk")
+}
+
+fn list_snippet_files() -> HashMap<Lang, PathBuf> {
+    let snippet_dir = ijk_dir().join("snippets");
     let mut res = HashMap::new();
     let read_dir0 = std::fs::read_dir(&snippet_dir);
     if read_dir0.is_err() {
@@ -92,17 +153,31 @@ fn list_snippet_files() -> HashMap<Lang, PathBuf> {
     res
 }
 
+const DEFAULT_CONFIG: &str = include_str!("default.toml");
+
+/// The key bindings in the bundled config as (page, state, key sequence, effect).
+#[cfg(test)]
+pub fn default_keys() -> Vec<(String, String, String, String)> {
+    let mut builder = Builder::new();
+    builder.add_config_file(toml::from_str(DEFAULT_CONFIG).unwrap());
+    let mut v = vec![];
+    for ((page, state), bindings) in builder.keys {
+        for (seq, eff) in bindings {
+            v.push((page.clone(), state.clone(), seq, eff));
+        }
+    }
+    v
+}
+
 fn create_config_repo() -> ConfigRepo {
     let current_dir = std::env::current_dir().unwrap();
 
     let mut builder = Builder::new();
 
-    let default_config = include_str!("default.toml");
-    let default_config = toml::from_str(&default_config).unwrap();
+    let default_config = toml::from_str(DEFAULT_CONFIG).unwrap();
     builder.add_config_file(default_config);
 
-    let home_dir = std::env::home_dir().unwrap();
-    let home_config_path = home_dir.join(".ijk").join("config.toml");
+    let home_config_path = ijk_dir().join("config.toml");
     for s in std::fs::read_to_string(home_config_path) {
         let config = toml::from_str(&s).unwrap();
         builder.add_config_file(config);
@@ -119,5 +194,13 @@ fn create_config_repo() -> ConfigRepo {
         snippets: list_snippet_files(),
         filenames: builder.filenames,
         extensions: builder.extensions,
+        undo_limit: builder.undo_limit,
+        undo_persist: builder.undo_persist,
+        clipboard_provider: builder.clipboard_provider,
+        clipboard_path: builder.clipboard_path.map(PathBuf::from),
+        keys: builder.keys,
+        macro_persist: builder.macro_persist,
+        backup: builder.backup,
+        large_file_threshold: builder.large_file_threshold,
     }
 }
\ No newline at end of file
@@ -21,6 +21,10 @@ impl PageState {
 
 pub trait Effect {
     fn run(&self, k: Key) -> String;
+    /// The name to bind the effect from the config. Unnamed effects can't be bound.
+    fn name(&self) -> &'static str {
+        ""
+    }
 }
 
 #[macro_export]
@@ -33,6 +37,9 @@ macro_rules! def_effect {
                 self.0.borrow_mut().state.set(next_state.clone());
                 next_state
             }
+            fn name(&self) -> &'static str {
+                stringify!($eff_name)
+            }
         }
     };
 }
@@ -43,14 +50,18 @@ struct Edge {
 }
 impl Edge {
     fn matches(&self, k: &Key) -> bool {
-        match self.matcher.clone() {
-            Key::CharRange(a, b) => match *k {
-                Key::Char(c) => a <= c && c <= b,
-                _ => false,
-            },
-            Key::Otherwise => true,
-            mhr => k.clone() == mhr,
-        }
+        matches(&self.matcher, k)
+    }
+}
+
+pub fn matches(matcher: &Key, k: &Key) -> bool {
+    match matcher.clone() {
+        Key::CharRange(a, b) => match *k {
+            Key::Char(c) => a <= c && c <= b,
+            _ => false,
+        },
+        Key::Otherwise => true,
+        mhr => k.clone() == mhr,
     }
 }
 
@@ -82,6 +93,24 @@ impl Graph {
             eff: eff,
         });
     }
+    /// True if the state has any edge.
+    pub fn has_state(&self, state: &str) -> bool {
+        self.edges.contains_key(state)
+    }
+    /// The effects of the name and the matchers they are registered with.
+    pub fn find_named(&self, name: &str) -> Vec<(Key, Rc<dyn Effect>)> {
+        let mut states: Vec<&String> = self.edges.keys().collect();
+        states.sort();
+        let mut v = vec![];
+        for state in states {
+            for e in &self.edges[state] {
+                if e.eff.name() == name {
+                    v.push((e.matcher.clone(), e.eff.clone()));
+                }
+            }
+        }
+        v
+    }
     fn find_effect(&self, from: &str, k: &Key) -> Option<Rc<Effect>> {
         if !self.edges.contains_key(from) {
             return None;
@@ -95,6 +124,12 @@ impl Graph {
 
 pub trait Controller {
     fn receive(&self, k: Key);
+    /// The current state. Empty if the controller has no states.
+    fn state(&self) -> String {
+        String::new()
+    }
+    /// Moves to the state without a key. e.g. to prompt for an event.
+    fn set_state(&self, _: &str) {}
 }
 
 pub struct NullController {}
@@ -113,8 +148,17 @@ impl ControllerFSM {
             g: g,
         }
     }
+    pub fn graph(&self) -> &Graph {
+        &self.g
+    }
 }
 impl Controller for ControllerFSM {
+    fn state(&self) -> String {
+        self.cur.borrow().clone()
+    }
+    fn set_state(&self, s: &str) {
+        *self.cur.borrow_mut() = s.to_owned();
+    }
     fn receive(&self, k: Key) {
         let cur = self.cur.borrow().clone();
         let eff0 = self.g.find_effect(&cur, &k);
@@ -10,6 +10,9 @@ use std::fs;
 use crate::screen::Color;
 use crate::message_box::MessageBox;
 use crate::read_buffer::{INIT, SEARCH, JUMP};
+use crate::read_buffer::search::Mode;
+
+const GREP_INPUT: &str = "GrepInput";
 
 enum Entry {
     Parent(path::PathBuf),
@@ -22,6 +25,9 @@ pub struct Directory {
     path: path::PathBuf,
     entries: Vec<Entry>,
     evacuated_entries: Vec<Entry>,
+    show_hidden: bool,
+    grep_query: String,
+    grep_mode: Mode,
     navigator: Rc<RefCell<Navigator>>,
     state: PageState,
     message_box: MessageBox,
@@ -34,6 +40,9 @@ impl Directory {
             path: fs::canonicalize(path).unwrap(),
             entries: vec![],
             evacuated_entries: vec![],
+            show_hidden: true,
+            grep_query: String::new(),
+            grep_mode: Mode::Literal,
             rb: ReadBuffer::new(vec![], state.clone(), message_box.clone()), // not valid
             navigator: navigator,
             state,
@@ -47,7 +56,8 @@ impl Directory {
 
     }
     fn toggle_hide(&mut self) {
-        if self.evacuated_entries.is_empty() {
+        self.show_hidden = !self.show_hidden;
+        if !self.show_hidden {
             for i in (0..self.entries.len()).rev() {
                 let e = &self.entries[i];
                 match *e {
@@ -118,7 +128,7 @@ impl Directory {
         self.rb = ReadBuffer::new(v, self.state.clone(), self.message_box.clone());
     }
     pub fn refresh(&mut self) {
-        let was_hide_mode = !self.evacuated_entries.is_empty();
+        let was_hide_mode = !self.show_hidden;
 
         self.entries.clear();
         self.evacuated_entries.clear();
@@ -136,6 +146,7 @@ impl Directory {
         }
 
         if was_hide_mode {
+            self.show_hidden = true;
             self.toggle_hide();
         }
 
@@ -158,9 +169,12 @@ impl Directory {
                 let dir = Rc::new(RefCell::new(self::Directory::open(&path, self.navigator.clone())));
                 Rc::new(RefCell::new(self::Page::new(dir, path.clone())))
             },
-            Entry::File(path) => {
-                let x = Rc::new(RefCell::new(EditBuffer::open(&path, self.navigator.clone())));
-                Rc::new(RefCell::new(edit_buffer::Page::new(x)))
+            Entry::File(path) => match crate::large_file::open(path, 0) {
+                Some(page) => page,
+                None => {
+                    let x = Rc::new(RefCell::new(EditBuffer::open(&path, self.navigator.clone())));
+                    Rc::new(RefCell::new(edit_buffer::Page::new(x)))
+                },
             },
         };
         self.navigator.borrow_mut().push(page);
@@ -196,10 +210,66 @@ impl Directory {
         self.toggle_hide();
         INIT.to_owned()
     }
+    /// Refreshes the entries keeping the cursor.
+    fn reload(&mut self) {
+        let cursor = self.rb.cursor;
+        self.refresh();
+        self.rb.cursor = cursor;
+        self.rb.stabilize_cursor();
+    }
     fn eff_refresh(&mut self, _: Key) -> String {
         self.refresh();
         INIT.to_owned()
     }
+    fn eff_open_git_status(&mut self, _: Key) -> String {
+        crate::git::status::open(&self.path, self.navigator.clone(), &self.message_box);
+        INIT.to_owned()
+    }
+    fn eff_open_finder(&mut self, _: Key) -> String {
+        crate::finder::open(&self.path, self.show_hidden, self.navigator.clone());
+        INIT.to_owned()
+    }
+    fn show_grep_query(&self) {
+        let mut x = "grep: ".to_owned();
+        if self.grep_mode != Mode::Literal {
+            x.push_str(&format!("({}) ", self.grep_mode.name()));
+        }
+        x.push_str(&self.grep_query);
+        self.message_box.send(&x);
+    }
+    fn eff_enter_grep(&mut self, _: Key) -> String {
+        self.grep_query.clear();
+        self.show_grep_query();
+        GREP_INPUT.to_owned()
+    }
+    fn eff_cancel_grep(&mut self, _: Key) -> String {
+        self.message_box.send("");
+        INIT.to_owned()
+    }
+    fn eff_toggle_grep_mode(&mut self, _: Key) -> String {
+        self.grep_mode = self.grep_mode.next();
+        self.show_grep_query();
+        GREP_INPUT.to_owned()
+    }
+    fn eff_grep_input(&mut self, k: Key) -> String {
+        match k {
+            Key::Char('\n') => {
+                self.message_box.send("");
+                if !self.grep_query.is_empty() {
+                    crate::grep::open(&self.path, &self.grep_query, self.grep_mode, self.show_hidden, self.navigator.clone());
+                }
+                return INIT.to_owned()
+            },
+            Key::Backspace if self.grep_query.is_empty() => return self.eff_cancel_grep(k),
+            Key::Backspace => {
+                self.grep_query.pop();
+            },
+            Key::Char(c) => self.grep_query.push(c),
+            _ => {},
+        }
+        self.show_grep_query();
+        GREP_INPUT.to_owned()
+    }
 }
 
 use crate::controller::Effect;
@@ -211,6 +281,12 @@ def_effect!(GoDown, Directory, eff_go_down);
 def_effect!(GoUp, Directory, eff_go_up);
 def_effect!(ToggleHide, Directory, eff_toggle_hide);
 def_effect!(Refresh, Directory, eff_refresh);
+def_effect!(OpenGitStatus, Directory, eff_open_git_status);
+def_effect!(OpenFinder, Directory, eff_open_finder);
+def_effect!(EnterGrep, Directory, eff_enter_grep);
+def_effect!(CancelGrep, Directory, eff_cancel_grep);
+def_effect!(ToggleGrepMode, Directory, eff_toggle_grep_mode);
+def_effect!(GrepInput, Directory, eff_grep_input);
 
 use crate::shared::AsRefMut;
 pub fn mk_controller(x: Rc<RefCell<Directory>>) -> controller::ControllerFSM {
@@ -223,6 +299,13 @@ pub fn mk_controller(x: Rc<RefCell<Directory>>) -> controller::ControllerFSM {
     g.add_edge(INIT, Char('h'), Rc::new(GoUp(x.clone())));
     g.add_edge(INIT, Char('.'), Rc::new(ToggleHide(x.clone())));
     g.add_edge(INIT, Ctrl('r'), Rc::new(Refresh(x.clone())));
+    g.add_edge(INIT, Char('g'), Rc::new(OpenGitStatus(x.clone())));
+    g.add_edge(INIT, Char('p'), Rc::new(OpenFinder(x.clone())));
+    g.add_edge(INIT, Char('f'), Rc::new(EnterGrep(x.clone())));
+    g.add_edge(GREP_INPUT, Esc, Rc::new(CancelGrep(x.clone())));
+    g.add_edge(GREP_INPUT, Ctrl('t'), Rc::new(ToggleGrepMode(x.clone())));
+    g.add_edge(GREP_INPUT, Otherwise, Rc::new(GrepInput(x.clone())));
+    crate::keymap::apply(&mut g, navigator::PageKind::Directory);
     controller::ControllerFSM::new(INIT, Box::new(g))
 }
 
@@ -332,9 +415,11 @@ impl navigator::Page for Page {
         let state: &str = match self.x.borrow().state.get().as_str() {
             read_buffer::INIT => "*",
             read_buffer::SEARCH => "/",
+            GREP_INPUT => "f",
             _ => "*",
         };
-        format!("[Directory -{}-] {}", state, self.path.to_str().unwrap().to_owned())
+        let search = self.x.borrow().rb.search_status();
+        format!("[Directory -{}-] {}{}", state, self.path.to_str().unwrap().to_owned(), search)
     }
     fn kind(&self) -> navigator::PageKind {
         navigator::PageKind::Directory
@@ -346,4 +431,13 @@ impl navigator::Page for Page {
     fn message(&self) -> MessageBox {
         self.x.borrow().message_box.clone()
     }
+    fn session(&self) -> Option<crate::session::PageSession> {
+        Some(crate::session::PageSession::new(navigator::PageKind::Directory, &self.path, &self.x.borrow().rb))
+    }
+    fn watch_path(&self) -> Option<PathBuf> {
+        Some(self.path.clone())
+    }
+    fn changed_on_disk(&self) {
+        self.x.borrow_mut().reload();
+    }
 }
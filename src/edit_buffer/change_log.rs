@@ -1,17 +1,20 @@
 use super::{BufElem, Cursor};
-use super::undo_buffer::UndoBuffer;
-use std::time::Instant;
+use super::undo_tree::{NodeId, Step, UndoTree};
+use crate::read_buffer::Storage;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ChangeLog {
-    clock: Instant,
+    pub clock: SystemTime,
     pub at: Cursor,
     pub deleted: Vec<BufElem>,
     pub inserted: Vec<BufElem>,
 }
 impl ChangeLog {
     pub fn new(at: Cursor, deleted: Vec<BufElem>, inserted: Vec<BufElem>) -> Self {
-        let clock = Instant::now();
+        let clock = SystemTime::now();
         Self {
             clock: clock,
             at: at,
@@ -29,17 +32,113 @@ impl ChangeLog {
     }
 }
 
+fn flatten<S: Storage + ?Sized>(buf: &S, rows: std::ops::Range<usize>) -> Vec<BufElem> {
+    let mut v = vec![];
+    for row in rows {
+        v.extend_from_slice(buf.line(row));
+    }
+    // the last eol is kept so the change doesn't touch the last eol of the buffer
+    v.pop();
+    v
+}
+
+/// The change from orig to cur as a single change log of the rows between the common head and tail.
+/// None if no row is changed.
+pub fn diff_rows<S: Storage + ?Sized>(orig: &S, cur: &S) -> Option<ChangeLog> {
+    let (n, m) = (orig.len(), cur.len());
+    let mut head = 0;
+    while head < n && head < m && orig.line(head) == cur.line(head) {
+        head += 1;
+    }
+    let mut tail = 0;
+    while head + tail < n && head + tail < m && orig.line(n - 1 - tail) == cur.line(m - 1 - tail) {
+        tail += 1;
+    }
+    if head == n && n == m {
+        return None
+    }
+    // the rows are only inserted or removed.
+    // a common row is taken in so the change starts and ends in a row.
+    if head + tail == n || head + tail == m {
+        if head > 0 {
+            head -= 1;
+        } else {
+            tail -= 1;
+        }
+    }
+    Some(ChangeLog::new(Cursor { row: head, col: 0 }, flatten(orig, head..n-tail), flatten(cur, head..m-tail)))
+}
+
+/// The change replacing all the rows of orig with cur.
+pub fn replace_all<S: Storage + ?Sized, T: Storage + ?Sized>(orig: &S, cur: &T) -> ChangeLog {
+    ChangeLog::new(Cursor { row: 0, col: 0 }, flatten(orig, 0..orig.len()), flatten(cur, 0..cur.len()))
+}
+
+#[derive(Serialize, Deserialize)]
+struct UndoFile {
+    fingerprint: u64, // of the text the history leads to
+    tree: UndoTree<ChangeLog>,
+}
+
+/// A hash of the text to check the saved history still matches the file.
+pub fn fingerprint<S: Storage + ?Sized>(buf: &S) -> u64 {
+    // FNV-1a
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for row in 0..buf.len() {
+        for e in buf.line(row) {
+            let c = match *e {
+                BufElem::Char(c) => c,
+                BufElem::Eol => '\n',
+            };
+            let mut b = [0; 4];
+            for x in c.encode_utf8(&mut b).bytes() {
+                h ^= x as u64;
+                h = h.wrapping_mul(0x100_0000_01b3);
+            }
+        }
+    }
+    h
+}
+
+/// ~/.ijk/undo/%home%akira%src%main.rs.json
+pub fn undo_file_path(path: &Path) -> PathBuf {
+    let abs = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let name = abs.to_str().unwrap().replace('/', "%");
+    crate::config::ijk_dir().join("undo").join(format!("{}.json", name))
+}
+
 pub struct ChangeLogBuffer {
-    buf: UndoBuffer<ChangeLog>,
+    buf: UndoTree<ChangeLog>,
 }
 impl ChangeLogBuffer {
-    pub fn new() -> Self {
+    pub fn new(capacity: Option<usize>) -> Self {
         Self {
-            buf: UndoBuffer::new(20),
+            buf: UndoTree::new(capacity),
+        }
+    }
+    /// Restores the history saved for the text.
+    /// None if there is no history or it is saved for another text.
+    pub fn load(path: &Path, fingerprint: u64, capacity: Option<usize>) -> Option<Self> {
+        let s = std::fs::read_to_string(path).ok()?;
+        let file: UndoFile = serde_json::from_str(&s).ok()?;
+        if file.fingerprint != fingerprint {
+            return None
         }
+        let mut buf = file.tree;
+        buf.set_capacity(capacity);
+        Some(Self { buf })
     }
-    pub fn clock(&self) -> Option<Instant> {
-        self.buf.peek().map(|x| x.clock)
+    pub fn save(&self, path: &Path, fingerprint: u64) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = UndoFile { fingerprint, tree: self.buf.clone() };
+        let s = serde_json::to_string(&file)?;
+        std::fs::write(path, s)
+    }
+    /// The state of the buffer. The same state has the same id.
+    pub fn current(&self) -> NodeId {
+        self.buf.current()
     }
     pub fn peek(&self) -> Option<&ChangeLog> {
         self.buf.peek()
@@ -53,4 +152,122 @@ impl ChangeLogBuffer {
     pub fn pop_redo(&mut self) -> Option<ChangeLog> {
         self.buf.pop_redo()
     }
+    pub fn discard(&mut self) -> Option<ChangeLog> {
+        self.buf.discard()
+    }
+    /// Makes the changes after the state a single change so they are undone at once.
+    pub fn squash(&mut self, since: NodeId, x: ChangeLog) {
+        self.buf.squash(since, x)
+    }
+    pub fn earlier(&mut self, n: usize) -> Vec<Step<ChangeLog>> {
+        self.buf.earlier(n)
+    }
+    pub fn later(&mut self, n: usize) -> Vec<Step<ChangeLog>> {
+        self.buf.later(n)
+    }
+    /// Goes back to the state at the time d before the current change.
+    pub fn earlier_by(&mut self, d: Duration) -> Vec<Step<ChangeLog>> {
+        let t = match self.peek() {
+            Some(log) => log.clock.checked_sub(d),
+            None => return vec![],
+        };
+        // the original text if too far
+        let target = self.buf.find_latest(|log| t.is_some_and(|t| log.clock <= t));
+        self.buf.goto(target)
+    }
+    /// Goes forward to the state at the time d after the current change.
+    pub fn later_by(&mut self, d: Duration) -> Vec<Step<ChangeLog>> {
+        let base = match self.peek() {
+            Some(log) => log.clock,
+            // the time of the original text is unknown
+            None => match self.buf.iter().next() {
+                Some((_, log)) => log.clock,
+                None => return vec![],
+            },
+        };
+        let t = base.checked_add(d);
+        // the latest change if too far
+        let target = self.buf.find_latest(|log| t.is_none_or(|t| log.clock <= t));
+        if target < self.current() {
+            return vec![]
+        }
+        self.buf.goto(target)
+    }
+    /// The time of the current change.
+    pub fn clock(&self) -> Option<SystemTime> {
+        self.peek().map(|x| x.clock)
+    }
+}
+
+#[test]
+fn test_change_log_buffer_by_time() {
+    let t0 = SystemTime::now();
+    let mut buf = ChangeLogBuffer::new(None);
+    for i in 0..4 {
+        let mut log = ChangeLog::new(Cursor { row: i, col: 0 }, vec![], vec![BufElem::Eol]);
+        log.clock = t0 + Duration::from_secs(60 * i as u64);
+        buf.push(log);
+    }
+    // 3min -> 1min
+    assert_eq!(buf.earlier_by(Duration::from_secs(120)).len(), 2);
+    assert_eq!(buf.peek().unwrap().at.row, 1);
+    assert_eq!(buf.earlier_by(Duration::from_secs(600)).len(), 2);
+    assert!(buf.peek().is_none());
+    // from the first change
+    assert_eq!(buf.later_by(Duration::from_secs(90)).len(), 2);
+    assert_eq!(buf.peek().unwrap().at.row, 1);
+    // too far to represent
+    assert_eq!(buf.later_by(Duration::MAX).len(), 2);
+    assert_eq!(buf.peek().unwrap().at.row, 3);
+    assert_eq!(buf.earlier_by(Duration::MAX).len(), 4);
+    assert!(buf.peek().is_none());
+}
+
+#[test]
+fn test_change_log_buffer_persist() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("undo.json");
+    let mut buf = ChangeLogBuffer::new(None);
+    buf.push(ChangeLog::new(Cursor { row: 0, col: 0 }, vec![], vec![BufElem::Char('a')]));
+    buf.push(ChangeLog::new(Cursor { row: 0, col: 1 }, vec![], vec![BufElem::Char('b')]));
+    buf.pop_undo();
+    buf.save(&path, 1).unwrap();
+
+    assert!(ChangeLogBuffer::load(&path, 2, None).is_none());
+    let mut buf = ChangeLogBuffer::load(&path, 1, None).unwrap();
+    assert_eq!(buf.peek().unwrap().inserted, vec![BufElem::Char('a')]);
+    assert_eq!(buf.pop_redo().unwrap().inserted, vec![BufElem::Char('b')]);
+}
+
+#[test]
+fn test_diff_rows() {
+    use super::to_elems;
+    let orig = vec![to_elems("a\n"), to_elems("b\n"), to_elems("c\n"), to_elems("d\n")];
+    let cur = vec![to_elems("a\n"), to_elems("x\n"), to_elems("c\n"), to_elems("y\n")];
+    let log = diff_rows(&orig, &cur).unwrap();
+    assert_eq!(log.at, Cursor { row: 1, col: 0 });
+    assert_eq!(log.deleted, to_elems("b\nc\nd"));
+    assert_eq!(log.inserted, to_elems("x\nc\ny"));
+
+    // a line is joined
+    let cur = vec![to_elems("a\n"), to_elems("bc\n"), to_elems("d\n")];
+    let log = diff_rows(&orig, &cur).unwrap();
+    assert_eq!(log.deleted, to_elems("b\nc"));
+    assert_eq!(log.inserted, to_elems("bc"));
+
+    // rows are inserted
+    let cur = vec![to_elems("a\n"), to_elems("x\n"), to_elems("b\n"), to_elems("c\n"), to_elems("d\n")];
+    let log = diff_rows(&orig, &cur).unwrap();
+    assert_eq!(log.at, Cursor { row: 0, col: 0 });
+    assert_eq!(log.deleted, to_elems("a"));
+    assert_eq!(log.inserted, to_elems("a\nx"));
+
+    // the head rows are removed
+    let cur = vec![to_elems("c\n"), to_elems("d\n")];
+    let log = diff_rows(&orig, &cur).unwrap();
+    assert_eq!(log.at, Cursor { row: 0, col: 0 });
+    assert_eq!(log.deleted, to_elems("a\nb\nc"));
+    assert_eq!(log.inserted, to_elems("c"));
+
+    assert!(diff_rows(&orig, &orig).is_none());
 }
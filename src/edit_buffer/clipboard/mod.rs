@@ -0,0 +1,257 @@
+// https://github.com/hatoo/Accepted
+
+pub mod provider;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use lazy_static::lazy_static;
+
+use super::BufElem;
+use self::provider::ClipboardProvider;
+
+lazy_static! {
+    static ref SINGLETON: Clipboard = Clipboard::new();
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Type {
+    Range(Vec<BufElem>),
+    Line(Vec<BufElem>),
+}
+impl Type {
+    pub fn elems(&self) -> &[BufElem] {
+        match self {
+            Type::Range(a) => a,
+            Type::Line(a) => a,
+        }
+    }
+    fn append(self, other: Type) -> Type {
+        match (self, other) {
+            (Type::Range(mut a), Type::Range(b)) => {
+                a.extend(b);
+                Type::Range(a)
+            },
+            // a line is added as a new line
+            (a, b) => {
+                let mut v = a.elems().to_vec();
+                if v.last() != Some(&BufElem::Eol) {
+                    v.push(BufElem::Eol);
+                }
+                v.extend_from_slice(b.elems());
+                Type::Line(v)
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+    Yank,
+    Delete,
+}
+
+// "  the unnamed register. the register last written
+// 0  the last yank
+// 1-9  the last deletes. 1 is the latest
+// a-z  named. A-Z appends to a-z
+// _  the black hole. nothing is kept
+// +  the system clipboard
+pub fn is_register(c: char) -> bool {
+    c == '"' || c == '_' || c == '+' || c.is_ascii_digit() || c.is_ascii_alphabetic()
+}
+
+fn to_str(x: &[BufElem]) -> String {
+    let mut s = String::new();
+    for e in x {
+        let c = match *e {
+            BufElem::Char(c) => c,
+            BufElem::Eol => '\n',
+        };
+        s.push(c)
+    }
+    s
+}
+
+fn from_str(x: &str) -> Vec<BufElem> {
+    let mut v = vec![];
+    for c in x.chars() {
+        let e = match c {
+            '\n' => BufElem::Eol,
+            c => BufElem::Char(c),
+        };
+        v.push(e)
+    }
+    v
+}
+
+struct ClipboardImpl {
+    unnamed: Option<char>,
+    registers: BTreeMap<char, Type>,
+}
+impl ClipboardImpl {
+    fn new() -> Self {
+        Self {
+            unnamed: None,
+            registers: BTreeMap::new(),
+        }
+    }
+    /// Writes to the register. Returns false if nothing is kept.
+    fn copy(&mut self, reg: Option<char>, kind: Kind, x: Type) -> bool {
+        let reg = match reg {
+            None | Some('"') => match kind {
+                Kind::Yank => '0',
+                Kind::Delete => {
+                    for i in (1..9).rev() {
+                        let from = std::char::from_digit(i, 10).unwrap();
+                        let to = std::char::from_digit(i + 1, 10).unwrap();
+                        if let Some(x) = self.registers.remove(&from) {
+                            self.registers.insert(to, x);
+                        }
+                    }
+                    '1'
+                },
+            },
+            Some('_') => return false,
+            Some(c) if c.is_ascii_uppercase() => {
+                let c = c.to_ascii_lowercase();
+                let x = match self.registers.remove(&c) {
+                    Some(old) => old.append(x),
+                    None => x,
+                };
+                self.registers.insert(c, x);
+                self.unnamed = Some(c);
+                return true
+            },
+            Some(c) => c,
+        };
+        self.registers.insert(reg, x);
+        self.unnamed = Some(reg);
+        true
+    }
+    /// Writes to the register without changing the unnamed register.
+    fn set(&mut self, reg: char, x: Type) {
+        let x = match self.registers.remove(&reg.to_ascii_lowercase()) {
+            Some(old) if reg.is_ascii_uppercase() => old.append(x),
+            _ => x,
+        };
+        self.registers.insert(reg.to_ascii_lowercase(), x);
+    }
+    fn paste(&self, reg: Option<char>) -> Option<Type> {
+        let reg = match reg {
+            None | Some('"') => self.unnamed?,
+            Some(c) => c.to_ascii_lowercase(),
+        };
+        self.registers.get(&reg).cloned()
+    }
+    fn list(&self) -> Vec<(char, Type)> {
+        let mut v = vec![];
+        if let Some(x) = self.paste(None) {
+            v.push(('"', x));
+        }
+        for (c, x) in &self.registers {
+            v.push((*c, x.clone()));
+        }
+        v
+    }
+}
+
+pub struct Clipboard {
+    imp: Arc<Mutex<ClipboardImpl>>,
+    provider: Mutex<Result<Box<dyn ClipboardProvider>, String>>,
+}
+impl Clipboard {
+    fn new() -> Self {
+        let (name, path) = crate::config::SINGLETON.clipboard();
+        Self {
+            imp: Arc::new(Mutex::new(ClipboardImpl::new())),
+            provider: Mutex::new(provider::from_config(name, path)),
+        }
+    }
+}
+
+fn provider_copy(s: &str) -> Result<(), String> {
+    match &mut *SINGLETON.provider.lock().unwrap() {
+        Ok(p) => p.copy(s).map_err(|e| format!("{}: {}", p.name(), e)),
+        Err(e) => Err(e.clone()),
+    }
+}
+
+fn provider_paste() -> Result<String, String> {
+    match &mut *SINGLETON.provider.lock().unwrap() {
+        Ok(p) => p.paste().map_err(|e| format!("{}: {}", p.name(), e)),
+        Err(e) => Err(e.clone()),
+    }
+}
+
+/// Replaces the provider chosen by the config.
+pub fn set_provider(x: Box<dyn ClipboardProvider>) {
+    *SINGLETON.provider.lock().unwrap() = Ok(x);
+}
+
+/// Writes to the register or the unnamed register if None.
+/// Yanks are also sent to the provider. Deletes are not, to avoid a round trip for every x.
+pub fn copy(reg: Option<char>, kind: Kind, x: Type) -> Result<(), String> {
+    if reg == Some('+') {
+        return provider_copy(&to_str(x.elems()))
+    }
+    let s = to_str(x.elems());
+    let kept = SINGLETON.imp.lock().unwrap().copy(reg, kind, x);
+    if kept && kind == Kind::Yank {
+        provider_copy(&s)?;
+    }
+    Ok(())
+}
+
+/// Reads the register or the unnamed register if None.
+pub fn paste(reg: Option<char>) -> Result<Option<Type>, String> {
+    if reg == Some('+') {
+        let s = provider_paste()?;
+        let x = if s.ends_with('\n') {
+            Type::Line(from_str(&s[..s.len()-1]))
+        } else {
+            Type::Range(from_str(&s))
+        };
+        return Ok(Some(x))
+    }
+    Ok(SINGLETON.imp.lock().unwrap().paste(reg))
+}
+
+/// Writes the recorded macro to the register. Uppercase appends.
+pub fn record(reg: char, x: Type) {
+    SINGLETON.imp.lock().unwrap().set(reg, x)
+}
+
+/// The registers that have contents. The unnamed register comes first.
+pub fn list() -> Vec<(char, Type)> {
+    SINGLETON.imp.lock().unwrap().list()
+}
+
+pub fn paste_system() -> Result<Vec<BufElem>, String> {
+    provider_paste().map(|x| from_str(&x))
+}
+
+#[test]
+fn test_registers() {
+    let line = |s: &str| Type::Line(from_str(s));
+    let mut x = ClipboardImpl::new();
+    x.copy(None, Kind::Yank, line("yanked"));
+    x.copy(None, Kind::Delete, line("deleted 1"));
+    x.copy(None, Kind::Delete, line("deleted 2"));
+    // the yank survives the deletes
+    assert_eq!(x.paste(None), Some(line("deleted 2")));
+    assert_eq!(x.paste(Some('0')), Some(line("yanked")));
+    assert_eq!(x.paste(Some('2')), Some(line("deleted 1")));
+
+    assert!(!x.copy(Some('_'), Kind::Delete, line("gone")));
+    assert_eq!(x.paste(None), Some(line("deleted 2")));
+
+    x.copy(Some('a'), Kind::Yank, Type::Range(from_str("ab")));
+    x.copy(Some('A'), Kind::Yank, Type::Range(from_str("c")));
+    assert_eq!(x.paste(Some('a')), Some(Type::Range(from_str("abc"))));
+    x.copy(Some('A'), Kind::Yank, line("d"));
+    assert_eq!(x.paste(Some('a')), Some(line("abc\nd")));
+    assert_eq!(x.paste(None), x.paste(Some('a')));
+
+    let regs: Vec<char> = x.list().into_iter().map(|(c, _)| c).collect();
+    assert_eq!(regs, vec!['"', '0', '1', '2', 'a']);
+}
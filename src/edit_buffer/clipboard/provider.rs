@@ -0,0 +1,208 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+/// Where the yanked text goes out of the editor.
+pub trait ClipboardProvider: Send {
+    fn name(&self) -> &'static str;
+    fn copy(&mut self, s: &str) -> Result<(), String>;
+    fn paste(&mut self) -> Result<String, String>;
+}
+
+fn run_copy(cmd: &[&str], s: &str) -> Result<(), String> {
+    let mut p = Command::new(cmd[0])
+        .args(&cmd[1..])
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .spawn()
+        .map_err(|e| format!("{}: {}", cmd[0], e))?;
+    if let Some(mut stdin) = p.stdin.take() {
+        stdin.write_all(s.as_bytes()).map_err(|e| format!("{}: {}", cmd[0], e))?;
+    }
+    let status = p.wait().map_err(|e| format!("{}: {}", cmd[0], e))?;
+    if !status.success() {
+        return Err(format!("{}: {}", cmd[0], status))
+    }
+    Ok(())
+}
+
+fn run_paste(cmd: &[&str]) -> Result<String, String> {
+    let out = Command::new(cmd[0])
+        .args(&cmd[1..])
+        .stderr(process::Stdio::null())
+        .output()
+        .map_err(|e| format!("{}: {}", cmd[0], e))?;
+    if !out.status.success() {
+        return Err(format!("{}: {}", cmd[0], out.status))
+    }
+    String::from_utf8(out.stdout).map_err(|e| format!("{}: {}", cmd[0], e))
+}
+
+fn in_path(name: &str) -> bool {
+    match std::env::var_os("PATH") {
+        Some(paths) => std::env::split_paths(&paths).any(|dir| dir.join(name).is_file()),
+        None => false,
+    }
+}
+
+// (copy, paste)
+const COMMANDS: [(&[&str], &[&str]); 5] = [
+    (&["pbcopy"], &["pbpaste"]),
+    (&["win32yank", "-i"], &["win32yank", "-o"]),
+    (&["win32yank.exe", "-i"], &["win32yank.exe", "-o"]),
+    (&["xsel", "-bi"], &["xsel", "-bo"]),
+    (&["xclip", "-i", "-selection", "clipboard"], &["xclip", "-o", "-selection", "clipboard"]),
+];
+
+/// The clipboard command of the system. pbcopy, win32yank, xsel or xclip.
+pub struct System {
+    copy: &'static [&'static str],
+    paste: &'static [&'static str],
+}
+impl System {
+    /// The first command found in PATH. The search runs only once.
+    pub fn detect() -> Option<Self> {
+        COMMANDS.iter().find(|(copy, _)| in_path(copy[0])).map(|(copy, paste)| Self { copy, paste })
+    }
+}
+impl ClipboardProvider for System {
+    fn name(&self) -> &'static str {
+        "system"
+    }
+    fn copy(&mut self, s: &str) -> Result<(), String> {
+        run_copy(self.copy, s)
+    }
+    fn paste(&mut self) -> Result<String, String> {
+        run_paste(self.paste)
+    }
+}
+
+/// The paste buffer of tmux.
+pub struct Tmux;
+impl ClipboardProvider for Tmux {
+    fn name(&self) -> &'static str {
+        "tmux"
+    }
+    fn copy(&mut self, s: &str) -> Result<(), String> {
+        run_copy(&["tmux", "load-buffer", "-"], s)
+    }
+    fn paste(&mut self) -> Result<String, String> {
+        run_paste(&["tmux", "save-buffer", "-"])
+    }
+}
+
+/// The escape sequence to set the clipboard of the terminal.
+/// In tmux it is passed through to the outer terminal.
+pub fn osc52_sequence(s: &str, in_tmux: bool) -> String {
+    let seq = format!("\x1b]52;c;{}\x07", base64::encode(s.as_bytes()));
+    if in_tmux {
+        format!("\x1bPtmux;\x1b{}\x1b\\", seq)
+    } else {
+        seq
+    }
+}
+
+/// The clipboard of the terminal by OSC 52. Works over SSH but can't paste.
+pub struct Osc52;
+impl ClipboardProvider for Osc52 {
+    fn name(&self) -> &'static str {
+        "osc52"
+    }
+    fn copy(&mut self, s: &str) -> Result<(), String> {
+        let seq = osc52_sequence(s, std::env::var_os("TMUX").is_some());
+        let mut out = std::io::stdout();
+        out.write_all(seq.as_bytes()).and_then(|_| out.flush()).map_err(|e| e.to_string())
+    }
+    fn paste(&mut self) -> Result<String, String> {
+        Err("the terminal can't be read".to_owned())
+    }
+}
+
+/// Keeps the text in the editor. Used when nothing else is available.
+#[derive(Default)]
+pub struct Memory {
+    x: String,
+}
+impl ClipboardProvider for Memory {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+    fn copy(&mut self, s: &str) -> Result<(), String> {
+        self.x = s.to_owned();
+        Ok(())
+    }
+    fn paste(&mut self) -> Result<String, String> {
+        Ok(self.x.clone())
+    }
+}
+
+/// Writes the text to a file. Other processes (or tests) can read it.
+pub struct File {
+    path: PathBuf,
+}
+impl File {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.to_owned() }
+    }
+}
+impl ClipboardProvider for File {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+    fn copy(&mut self, s: &str) -> Result<(), String> {
+        std::fs::write(&self.path, s).map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+    fn paste(&mut self) -> Result<String, String> {
+        std::fs::read_to_string(&self.path).map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+}
+
+/// The system clipboard if found, tmux in tmux, OSC 52 over SSH and otherwise in memory.
+pub fn auto() -> Box<dyn ClipboardProvider> {
+    if let Some(x) = System::detect() {
+        return Box::new(x)
+    }
+    if std::env::var_os("TMUX").is_some() {
+        return Box::new(Tmux)
+    }
+    if std::env::var_os("SSH_TTY").is_some() || std::env::var_os("SSH_CONNECTION").is_some() {
+        return Box::new(Osc52)
+    }
+    Box::new(Memory::default())
+}
+
+/// The provider of the name in the config.
+pub fn from_config(name: &str, path: Option<&Path>) -> Result<Box<dyn ClipboardProvider>, String> {
+    let x: Box<dyn ClipboardProvider> = match name {
+        "auto" => auto(),
+        "system" => Box::new(System::detect().ok_or("no clipboard command is found")?),
+        "tmux" => Box::new(Tmux),
+        "osc52" => Box::new(Osc52),
+        "memory" => Box::new(Memory::default()),
+        "file" => {
+            let path = path.map(|p| p.to_owned()).unwrap_or_else(|| crate::config::ijk_dir().join("clipboard"));
+            Box::new(File::new(&path))
+        },
+        _ => return Err(format!("unknown clipboard provider: {}", name)),
+    };
+    Ok(x)
+}
+
+#[test]
+fn test_osc52_sequence() {
+    assert_eq!(osc52_sequence("hello", false), "\x1b]52;c;aGVsbG8=\x07");
+    assert_eq!(osc52_sequence("hello", true), "\x1bPtmux;\x1b\x1b]52;c;aGVsbG8=\x07\x1b\\");
+}
+
+#[test]
+fn test_file_provider() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut x = from_config("file", Some(&dir.path().join("clip"))).unwrap();
+    x.copy("a\nb").unwrap();
+    assert_eq!(x.paste().unwrap(), "a\nb");
+
+    let mut x = File::new(&dir.path().join("no/such/dir"));
+    assert!(x.copy("a").is_err());
+    assert!(from_config("pigeon", None).is_err());
+}
@@ -0,0 +1,409 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use regex::Regex;
+
+/// 1-origin line number as typed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Address {
+    Current,
+    Last,
+    Line(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Range {
+    All,
+    Lines(Address, Address),
+}
+impl Range {
+    /// The rows in 0-origin. None if the range is out of the buffer.
+    pub fn rows(self, cur_row: usize, n_rows: usize) -> Option<std::ops::Range<usize>> {
+        let row = |a| match a {
+            Address::Current => Some(cur_row),
+            Address::Last => Some(n_rows - 1),
+            Address::Line(0) => None,
+            Address::Line(n) => if n <= n_rows { Some(n - 1) } else { None },
+        };
+        match self {
+            Range::All => Some(0..n_rows),
+            Range::Lines(a, b) => {
+                let (a, b) = (row(a)?, row(b)?);
+                if a <= b {
+                    Some(a..b+1)
+                } else {
+                    Some(b..a+1)
+                }
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Write { path: Option<PathBuf>, force: bool },
+    WriteQuit,
+    Quit { force: bool },
+    Edit(PathBuf),
+    Set { key: String, value: Option<String> },
+    Substitute { range: Range, pattern: String, replacement: String, global: bool },
+}
+
+// (name, short name, takes a path)
+const COMMANDS: &[(&str, &str, bool)] = &[
+    ("edit", "e", true),
+    ("quit", "q", false),
+    ("set", "set", false),
+    ("substitute", "s", false),
+    ("wq", "x", false),
+    ("write", "w", true),
+];
+
+fn find_command(name: &str) -> Option<&'static str> {
+    let exact = COMMANDS.iter().find(|(long, short, _)| *long == name || *short == name);
+    // any prefix of the long name from the short name is accepted like vi
+    let prefix = || COMMANDS.iter().find(|(long, short, _)| long.starts_with(name) && name.len() >= short.len());
+    exact.or_else(prefix).map(|(long, _, _)| *long)
+}
+
+fn parse_address(s: &str) -> (Option<Address>, &str) {
+    if let Some(rest) = s.strip_prefix('.') {
+        return (Some(Address::Current), rest)
+    }
+    if let Some(rest) = s.strip_prefix('$') {
+        return (Some(Address::Last), rest)
+    }
+    let n = s.chars().take_while(|c| c.is_ascii_digit()).count();
+    if n == 0 {
+        return (None, s)
+    }
+    (Some(Address::Line(s[..n].parse().unwrap())), &s[n..])
+}
+
+fn parse_range(s: &str) -> (Option<Range>, &str) {
+    if let Some(rest) = s.strip_prefix('%') {
+        return (Some(Range::All), rest)
+    }
+    let (a, rest) = parse_address(s);
+    let a = match a {
+        Some(a) => a,
+        None => return (None, s),
+    };
+    if let Some(rest) = rest.strip_prefix(',') {
+        if let (Some(b), rest) = parse_address(rest) {
+            return (Some(Range::Lines(a, b)), rest)
+        }
+    }
+    (Some(Range::Lines(a, a)), rest)
+}
+
+/// Splits `/pattern/replacement/flags` by the first char.
+/// The delimiter can be escaped by a backslash.
+fn parse_substitute(s: &str) -> Result<(String, String, bool), String> {
+    let mut chars = s.chars();
+    let delim = match chars.next() {
+        Some(c) if !c.is_alphanumeric() && !c.is_whitespace() && c != '\\' => c,
+        _ => return Err("Usage: s/pattern/replacement/[g]".to_owned()),
+    };
+    let mut parts = vec![String::new()];
+    let mut escaped = false;
+    for c in chars {
+        if escaped {
+            if c != delim {
+                parts.last_mut().unwrap().push('\\');
+            }
+            parts.last_mut().unwrap().push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == delim && parts.len() < 3 {
+            parts.push(String::new());
+        } else {
+            parts.last_mut().unwrap().push(c);
+        }
+    }
+    if escaped {
+        parts.last_mut().unwrap().push('\\');
+    }
+    let pattern = parts[0].clone();
+    if pattern.is_empty() {
+        return Err("Empty pattern".to_owned())
+    }
+    let replacement = parts.get(1).cloned().unwrap_or_default();
+    let mut global = false;
+    for c in parts.get(2).cloned().unwrap_or_default().chars() {
+        match c {
+            'g' => global = true,
+            c => return Err(format!("Unknown flag: {}", c)),
+        }
+    }
+    Ok((pattern, replacement, global))
+}
+
+pub fn parse(s: &str) -> Result<Command, String> {
+    let s = s.trim();
+    let (range, s) = parse_range(s);
+    let n = s.chars().take_while(|c| c.is_ascii_alphabetic()).count();
+    let (name, rest) = s.split_at(n);
+    let (force, rest) = match rest.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let name = match find_command(name) {
+        Some(x) => x,
+        None => return Err(format!("Not an editor command: {}", s)),
+    };
+    if range.is_some() && name != "substitute" {
+        return Err("No range allowed".to_owned())
+    }
+    if name == "substitute" {
+        let (pattern, replacement, global) = parse_substitute(rest)?;
+        let range = range.unwrap_or(Range::Lines(Address::Current, Address::Current));
+        return Ok(Command::Substitute { range, pattern, replacement, global })
+    }
+    if force && name != "quit" && name != "write" {
+        return Err(format!("No ! allowed: {}", name))
+    }
+    let arg = rest.trim();
+    let cmd = match name {
+        "write" => Command::Write { path: if arg.is_empty() { None } else { Some(PathBuf::from(arg)) }, force },
+        "wq" => Command::WriteQuit,
+        "quit" => Command::Quit { force },
+        "edit" => {
+            if arg.is_empty() {
+                return Err("No file name".to_owned())
+            }
+            Command::Edit(PathBuf::from(arg))
+        },
+        "set" => {
+            let mut kv = arg.splitn(2, '=');
+            let key = kv.next().unwrap().trim().to_owned();
+            if key.is_empty() {
+                return Err("Usage: set key[=value]".to_owned())
+            }
+            let value = kv.next().map(|x| x.trim().to_owned());
+            Command::Set { key, value }
+        },
+        _ => unreachable!(),
+    };
+    Ok(cmd)
+}
+
+/// Replaces the regex in the line. `$1` or `${name}` in the replacement is expanded.
+/// None if not found.
+pub fn substitute(line: &str, re: &Regex, replacement: &str, global: bool) -> Option<(String, usize)> {
+    let n = re.find_iter(line).count();
+    if n == 0 {
+        return None
+    }
+    if global {
+        Some((re.replace_all(line, replacement).into_owned(), n))
+    } else {
+        Some((re.replace(line, replacement).into_owned(), 1))
+    }
+}
+
+fn common_prefix(xs: &[String]) -> String {
+    let mut res: Vec<char> = match xs.first() {
+        Some(x) => x.chars().collect(),
+        None => return String::new(),
+    };
+    for x in &xs[1..] {
+        let n = res.iter().zip(x.chars()).take_while(|(a, b)| **a == *b).count();
+        res.truncate(n);
+    }
+    res.into_iter().collect()
+}
+
+/// The paths starting with the input. Directories end with '/'.
+fn complete_path(input: &str) -> Vec<String> {
+    let (dir, prefix) = match input.rfind('/') {
+        Some(i) => input.split_at(i + 1),
+        None => ("", input),
+    };
+    let read_dir = match std::fs::read_dir(if dir.is_empty() { Path::new(".") } else { Path::new(dir) }) {
+        Ok(x) => x,
+        Err(_) => return vec![],
+    };
+    let mut res = vec![];
+    for entry in read_dir.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // hidden files only when asked for
+        if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+            continue;
+        }
+        let is_dir = entry.file_type().map(|x| x.is_dir()).unwrap_or(false);
+        res.push(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }));
+    }
+    res.sort();
+    res
+}
+
+use lazy_static::lazy_static;
+lazy_static! {
+    // shared by all the buffers
+    static ref HISTORY: Mutex<Vec<String>> = Mutex::new(vec![]);
+}
+
+/// The line typed after `:`.
+#[derive(Default)]
+pub struct CommandLine {
+    input: String,
+    history_index: Option<usize>, // browsing the history if Some
+}
+impl CommandLine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+    pub fn clear(&mut self) {
+        self.input.clear();
+        self.history_index = None;
+    }
+    pub fn push(&mut self, c: char) {
+        self.input.push(c);
+    }
+    pub fn pop(&mut self) -> Option<char> {
+        self.input.pop()
+    }
+    /// Takes the input and records it in the history.
+    pub fn commit(&mut self) -> String {
+        let s = std::mem::take(&mut self.input);
+        self.history_index = None;
+        let mut history = HISTORY.lock().unwrap();
+        if !s.trim().is_empty() && history.last() != Some(&s) {
+            history.push(s.clone());
+        }
+        s
+    }
+    pub fn history_prev(&mut self) {
+        let history = HISTORY.lock().unwrap();
+        let i = match self.history_index {
+            Some(0) => return,
+            Some(i) => i - 1,
+            None => match history.len() {
+                0 => return,
+                n => n - 1,
+            },
+        };
+        self.input = history[i].clone();
+        self.history_index = Some(i);
+    }
+    pub fn history_next(&mut self) {
+        let history = HISTORY.lock().unwrap();
+        match self.history_index {
+            Some(i) if i + 1 < history.len() => {
+                self.input = history[i + 1].clone();
+                self.history_index = Some(i + 1);
+            },
+            Some(_) => {
+                self.input.clear();
+                self.history_index = None;
+            },
+            None => {},
+        }
+    }
+    /// Completes the command name or the path argument as far as it is unique.
+    /// Returns the candidates.
+    pub fn complete(&mut self) -> Vec<String> {
+        let candidates: Vec<String> = match self.input.find(' ') {
+            None => {
+                let input = self.input.trim_start();
+                COMMANDS.iter()
+                    .filter(|(long, _, _)| long.starts_with(input))
+                    .map(|(long, _, path)| if *path { format!("{} ", long) } else { long.to_string() })
+                    .collect()
+            },
+            Some(i) => {
+                let (name, arg) = self.input.split_at(i);
+                let takes_path = find_command(name.trim()).map(|x| COMMANDS.iter().any(|c| c.0 == x && c.2)).unwrap_or(false);
+                if !takes_path {
+                    return vec![]
+                }
+                let arg = arg.trim_start();
+                complete_path(arg).into_iter().map(|x| format!("{} {}", name, x)).collect()
+            },
+        };
+        let prefix = common_prefix(&candidates);
+        if prefix.len() > self.input.len() {
+            self.input = prefix;
+        }
+        candidates
+    }
+}
+
+#[test]
+fn test_parse_command() {
+    assert_eq!(parse("w"), Ok(Command::Write { path: None, force: false }));
+    assert_eq!(parse("w!"), Ok(Command::Write { path: None, force: true }));
+    assert_eq!(parse("write other.rs"), Ok(Command::Write { path: Some(PathBuf::from("other.rs")), force: false }));
+    assert_eq!(parse("wq"), Ok(Command::WriteQuit));
+    assert_eq!(parse("q!"), Ok(Command::Quit { force: true }));
+    assert_eq!(parse("e src/main.rs"), Ok(Command::Edit(PathBuf::from("src/main.rs"))));
+    assert_eq!(parse("set indent=2"), Ok(Command::Set { key: "indent".to_owned(), value: Some("2".to_owned()) }));
+    assert_eq!(parse("%s/foo/bar/g"), Ok(Command::Substitute {
+        range: Range::All, pattern: "foo".to_owned(), replacement: "bar".to_owned(), global: true,
+    }));
+    assert_eq!(parse("2,$s#a\\#b#c"), Ok(Command::Substitute {
+        range: Range::Lines(Address::Line(2), Address::Last), pattern: "a#b".to_owned(), replacement: "c".to_owned(), global: false,
+    }));
+    assert!(parse("e").is_err());
+    assert!(parse("%w").is_err());
+    assert!(parse("wq!").is_err());
+    assert!(parse("foo").is_err());
+    assert!(parse("s/foo/bar/z").is_err());
+}
+
+#[test]
+fn test_range_rows() {
+    assert_eq!(Range::All.rows(1, 3), Some(0..3));
+    assert_eq!(Range::Lines(Address::Current, Address::Current).rows(1, 3), Some(1..2));
+    assert_eq!(Range::Lines(Address::Last, Address::Line(1)).rows(1, 3), Some(0..3));
+    assert_eq!(Range::Lines(Address::Line(4), Address::Line(4)).rows(1, 3), None);
+}
+
+#[test]
+fn test_substitute() {
+    let re = |s| Regex::new(s).unwrap();
+    assert_eq!(substitute("foo foo", &re("foo"), "bar", false), Some(("bar foo".to_owned(), 1)));
+    assert_eq!(substitute("foo foo", &re("foo"), "bar", true), Some(("bar bar".to_owned(), 2)));
+    assert_eq!(substitute("foo", &re("baz"), "bar", true), None);
+    assert_eq!(substitute("foo.* x", &re("f(o+).*"), "$1", false), Some(("oo".to_owned(), 1)));
+    assert_eq!(substitute("a.b", &re(r"\."), "-", false), Some(("a-b".to_owned(), 1)));
+}
+
+#[test]
+fn test_complete() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("src")).unwrap();
+    std::fs::write(dir.path().join("main.rs"), "").unwrap();
+    std::fs::write(dir.path().join("mod.rs"), "").unwrap();
+    std::fs::write(dir.path().join(".hidden"), "").unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    let mut x = CommandLine::new();
+    for c in "wr".chars() {
+        x.push(c);
+    }
+    x.complete();
+    assert_eq!(x.input(), "write ");
+
+    x.clear();
+    for c in format!("e {}/m", root).chars() {
+        x.push(c);
+    }
+    assert_eq!(x.complete().len(), 2);
+    x.push('a');
+    x.complete();
+    assert_eq!(x.input(), format!("e {}/main.rs", root));
+
+    x.clear();
+    for c in format!("e {}/", root).chars() {
+        x.push(c);
+    }
+    assert_eq!(x.complete().len(), 3);
+    x.push('s');
+    x.complete();
+    assert_eq!(x.input(), format!("e {}/src/", root));
+}
@@ -5,4 +5,9 @@ use std::path::PathBuf;
 pub struct Config {
     pub indent_type: IndentType,
     pub snippet: Option<PathBuf>,
+    pub lang: Option<String>,
+    pub lsp: Option<Vec<String>>, // command line to start the language server
+    pub undo_limit: Option<usize>, // unlimited if None
+    pub undo_persist: bool,
+    pub backup: bool, // keeps the old file as foo~ on save
 }
\ No newline at end of file
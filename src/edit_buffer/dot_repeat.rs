@@ -0,0 +1,153 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::controller::{Controller, ControllerFSM};
+use crate::read_buffer::{INIT, JUMP};
+use crate::Key;
+use super::{EditBuffer, OPERATOR, REGISTER};
+use super::operator::Operator;
+
+// `.` repeats the last command that changed the buffer.
+// A command is the keys from Init back to Init, so the text typed in
+// the insert mode (e.g. cw foo Esc) is a part of the command.
+// The register ("a) is a part of the command too.
+//
+// The count of the command (3dd, d3w) is recorded apart from the keys.
+// [count]. replaces it as in vi so d3w then 2. deletes 2 words.
+// A command without a count is given the new one if it's an operator (dd then 3. is 3dd)
+// or repeated count times otherwise (x, ifoo Esc).
+
+#[derive(Clone, Default)]
+struct Command {
+    keys: Vec<Key>, // without the count
+    start: usize, // the keys after the register
+    count_at: Option<usize>, // where the count was typed
+    counts: Vec<String>, // 2d3w has 2 and 3
+}
+impl Command {
+    fn count(&self) -> Option<usize> {
+        if self.counts.is_empty() {
+            return None
+        }
+        Some(self.counts.iter().map(|x| x.parse::<usize>().unwrap_or(1)).product())
+    }
+}
+
+pub struct DotRepeat {
+    fsm: ControllerFSM,
+    x: Rc<RefCell<EditBuffer>>,
+    cur: RefCell<Command>, // the command being typed
+    in_count: RefCell<bool>, // the last key was a digit of the count
+    in_register: RefCell<bool>, // the last key selected the register
+    n_changes: RefCell<usize>, // when the command started
+    last: RefCell<Command>,
+}
+impl DotRepeat {
+    pub fn new(fsm: ControllerFSM, x: Rc<RefCell<EditBuffer>>) -> Self {
+        Self {
+            fsm,
+            x,
+            cur: RefCell::new(Command::default()),
+            in_count: RefCell::new(false),
+            in_register: RefCell::new(false),
+            n_changes: RefCell::new(0),
+            last: RefCell::new(Command::default()),
+        }
+    }
+    fn send(&self, keys: &[Key]) {
+        for k in keys {
+            self.fsm.receive(k.clone());
+        }
+    }
+    fn send_count(&self, n: usize) {
+        for c in n.to_string().chars() {
+            self.fsm.receive(Key::Char(c));
+        }
+    }
+    fn repeat(&self, count: Option<usize>) {
+        let cmd = self.last.borrow().clone();
+        let takes_count = match cmd.keys.get(cmd.start) {
+            Some(Key::Char(c)) => Operator::from_char(*c).is_some(),
+            _ => false,
+        };
+        let (at, n) = match (cmd.count_at, count) {
+            (Some(at), n) => (at, n.or(cmd.count())),
+            (None, Some(n)) if takes_count => (cmd.start, Some(n)),
+            (None, n) => {
+                for _ in 0..n.unwrap_or(1) {
+                    self.send(&cmd.keys);
+                }
+                return
+            },
+        };
+        self.send(&cmd.keys[..at]);
+        if let Some(n) = n {
+            self.send_count(n);
+        }
+        self.send(&cmd.keys[at..]);
+    }
+    // the digits of the count, not the ones typed as a motion (0) or a text
+    fn is_count(&self, state: &str, k: &Key) -> bool {
+        let c = match k {
+            Key::Char(c) if c.is_ascii_digit() => *c,
+            _ => return false,
+        };
+        match state {
+            INIT => c != '0',
+            JUMP => true,
+            OPERATOR => c != '0' || self.x.borrow().rb.has_count(),
+            _ => false,
+        }
+    }
+}
+impl Controller for DotRepeat {
+    fn state(&self) -> String {
+        self.fsm.state()
+    }
+    fn set_state(&self, s: &str) {
+        self.fsm.set_state(s)
+    }
+    fn receive(&self, k: Key) {
+        let state = self.fsm.state();
+        if k == Key::Char('.') {
+            if state == INIT {
+                self.repeat(None);
+                return
+            }
+            if state == JUMP {
+                let count = self.x.borrow_mut().rb.take_count();
+                self.fsm.receive(Key::Esc);
+                self.repeat(count);
+                return
+            }
+        }
+        if state == INIT && !*self.in_register.borrow() {
+            *self.cur.borrow_mut() = Command::default();
+            *self.n_changes.borrow_mut() = self.x.borrow().n_changes;
+        }
+        let is_count = self.is_count(&state, &k);
+        {
+            let mut cur = self.cur.borrow_mut();
+            if state == INIT {
+                cur.start = cur.keys.len();
+            }
+            if is_count {
+                if !*self.in_count.borrow() {
+                    let at = cur.keys.len();
+                    cur.count_at.get_or_insert(at);
+                    cur.counts.push(String::new());
+                }
+                if let Key::Char(c) = k {
+                    cur.counts.last_mut().unwrap().push(c);
+                }
+            } else {
+                cur.keys.push(k.clone());
+            }
+        }
+        *self.in_count.borrow_mut() = is_count;
+        *self.in_register.borrow_mut() = state == REGISTER;
+        self.fsm.receive(k);
+        if self.fsm.state() == INIT && self.x.borrow().n_changes != *self.n_changes.borrow() {
+            *self.last.borrow_mut() = self.cur.replace(Command::default());
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use crate::read_buffer::BufElem;
+use crate::read_buffer::storage::Storage;
+
+// The buffer only has chars and Eols. How they are laid out in the file is detected
+// on open and kept here so saving writes the file back as it was read.
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Encoding {
+    Utf8,
+    Utf8Bom,
+    Latin1,
+}
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf8Bom => "utf-8-bom",
+            Encoding::Latin1 => "latin1",
+        }
+    }
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-8-bom" | "utf8-bom" => Some(Encoding::Utf8Bom),
+            "latin1" | "iso-8859-1" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+    /// False if the char can't be written in the encoding.
+    pub fn can_encode(&self, c: char) -> bool {
+        match self {
+            Encoding::Latin1 => (c as u32) < 0x100,
+            _ => true,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+impl LineEnding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "lf",
+            LineEnding::Crlf => "crlf",
+        }
+    }
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "lf" | "unix" => Some(LineEnding::Lf),
+            "crlf" | "dos" => Some(LineEnding::Crlf),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct FileFormat {
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+    pub eol_at_eof: bool, // false if the last line has no newline
+}
+impl Default for FileFormat {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::Utf8,
+            line_ending: LineEnding::Lf,
+            eol_at_eof: true,
+        }
+    }
+}
+impl FileFormat {
+    /// e.g. utf-8|crlf|noeol
+    pub fn status(&self) -> String {
+        let mut s = format!("{}|{}", self.encoding.name(), self.line_ending.name());
+        if !self.eol_at_eof {
+            s.push_str("|noeol");
+        }
+        s
+    }
+}
+
+fn decode(bytes: &[u8]) -> (String, Encoding) {
+    if let Some(rest) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        return (String::from_utf8_lossy(rest).into_owned(), Encoding::Utf8Bom)
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(s) => (s.to_owned(), Encoding::Utf8),
+        // every byte is a valid latin1 char
+        Err(_) => (bytes.iter().map(|&b| b as char).collect(), Encoding::Latin1),
+    }
+}
+
+/// The buffer and the format of the file. The default format if the file doesn't exist.
+pub fn read_file(path: &Path) -> (Vec<Vec<BufElem>>, FileFormat) {
+    match fs::read(path) {
+        Ok(bytes) => read_bytes(&bytes),
+        Err(_) => (vec![vec![BufElem::Eol]], FileFormat::default()),
+    }
+}
+
+pub fn read_bytes(bytes: &[u8]) -> (Vec<Vec<BufElem>>, FileFormat) {
+    let (s, encoding) = decode(bytes);
+    let mut format = FileFormat { encoding, ..FileFormat::default() };
+    if s.is_empty() {
+        // nothing to write back, not even a newline
+        format.eol_at_eof = false;
+        return (vec![vec![BufElem::Eol]], format)
+    }
+    let n_lf = s.matches('\n').count();
+    // CRLF only if all the lines end with it. Otherwise the CRs are kept as chars.
+    if n_lf > 0 && s.matches("\r\n").count() == n_lf {
+        format.line_ending = LineEnding::Crlf;
+    }
+    let body = match s.strip_suffix('\n') {
+        Some(x) => x,
+        None => {
+            format.eol_at_eof = false;
+            &s
+        },
+    };
+    let buf = body.split('\n').map(|line| {
+        let line = match format.line_ending {
+            LineEnding::Crlf => line.strip_suffix('\r').unwrap_or(line),
+            LineEnding::Lf => line,
+        };
+        let mut v: Vec<BufElem> = line.chars().map(BufElem::Char).collect();
+        v.push(BufElem::Eol);
+        v
+    }).collect();
+    (buf, format)
+}
+
+/// Writes the buffer in the format. Fails if a char can't be encoded.
+pub fn write<W: Write, S: Storage + ?Sized>(mut out: W, buf: &S, format: &FileFormat) -> io::Result<()> {
+    if format.encoding == Encoding::Utf8Bom {
+        out.write_all(b"\xEF\xBB\xBF")?;
+    }
+    let newline: &[u8] = match format.line_ending {
+        LineEnding::Lf => b"\n",
+        LineEnding::Crlf => b"\r\n",
+    };
+    let mut tmp = [0; 4];
+    for i in 0..buf.len() {
+        for e in buf.line(i) {
+            match *e {
+                BufElem::Char(c) => match format.encoding {
+                    Encoding::Latin1 => {
+                        if !format.encoding.can_encode(c) {
+                            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{:?} can't be encoded in latin1", c)))
+                        }
+                        out.write_all(&[c as u8])?
+                    },
+                    _ => out.write_all(c.encode_utf8(&mut tmp).as_bytes())?,
+                },
+                BufElem::Eol => {
+                    if i + 1 < buf.len() || format.eol_at_eof {
+                        out.write_all(newline)?
+                    }
+                },
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(bytes: &[u8]) -> FileFormat {
+        let (buf, format) = read_bytes(bytes);
+        let mut out = vec![];
+        write(&mut out, &buf, &format).unwrap();
+        assert_eq!(out, bytes);
+        format
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(round_trip(b"a\nb\n"), FileFormat::default());
+        let f = round_trip(b"a\r\nb\r\n");
+        assert_eq!(f.line_ending, LineEnding::Crlf);
+        let f = round_trip(b"\xEF\xBB\xBFa\nb");
+        assert_eq!(f.encoding, Encoding::Utf8Bom);
+        assert!(!f.eol_at_eof);
+        let f = round_trip(b"caf\xE9\n");
+        assert_eq!(f.encoding, Encoding::Latin1);
+        // mixed line endings are kept as they are
+        let f = round_trip(b"a\r\nb\n");
+        assert_eq!(f.line_ending, LineEnding::Lf);
+        round_trip(b"\n");
+        round_trip(b"");
+        round_trip(b"\xEF\xBB\xBF");
+    }
+
+    #[test]
+    fn test_read_bytes() {
+        use crate::read_buffer::BufElem::*;
+        let (buf, _) = read_bytes(b"caf\xE9\r\n");
+        assert_eq!(buf, vec![vec![Char('c'), Char('a'), Char('f'), Char('é'), Eol]]);
+    }
+
+    #[test]
+    fn test_write_unencodable() {
+        use crate::read_buffer::BufElem::*;
+        let buf = vec![vec![Char('あ'), Eol]];
+        let format = FileFormat { encoding: Encoding::Latin1, ..FileFormat::default() };
+        assert!(write(&mut vec![], &buf, &format).is_err());
+        let format = FileFormat { line_ending: LineEnding::Crlf, ..FileFormat::default() };
+        let mut out = vec![];
+        write(&mut out, &buf, &format).unwrap();
+        assert_eq!(out, "あ\r\n".as_bytes());
+    }
+}
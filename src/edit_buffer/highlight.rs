@@ -1,7 +1,6 @@
-use syntect::easy::HighlightLines;
-use syntect::parsing::SyntaxSet;
-use syntect::highlighting::{ThemeSet, Style, Color};
-use crate::read_buffer::BufElem;
+use syntect::parsing::{SyntaxSet, ParseState, ScopeStack};
+use syntect::highlighting::{self, HighlightState, HighlightIterator, Style, Color};
+use crate::read_buffer::{BufElem, Storage, Rope};
 use crate::view;
 use crate::screen;
 use crate::theme;
@@ -11,38 +10,84 @@ lazy_static! {
     static ref ps: SyntaxSet = SyntaxSet::load_defaults_newlines();
 }
 
+/// The parser and highlighter state at the head of a line.
+#[derive(Clone, PartialEq)]
+struct Checkpoint {
+    parse_state: ParseState,
+    highlight_state: HighlightState,
+}
+
+#[derive(Clone)]
+struct CacheLine {
+    start: Option<Checkpoint>,
+    // None if the line is changed (or never highlighted) since the last highlighting.
+    styles: Option<Vec<Style>>,
+}
+impl CacheLine {
+    fn new(start: Option<Checkpoint>) -> Self {
+        Self { start, styles: None }
+    }
+}
+
+/// Highlights the buffer line by line keeping the state at the head of every line.
+///
+/// Invariants:
+/// - The checkpoints of the rows < valid_until are correct.
+/// - If the styles of a row are Some, they are computed from its checkpoint and
+///   the checkpoint of the next row is the state after the row.
+///
+/// So an edit only invalidates the rows from the edited one and
+/// re-highlighting can stop as soon as the state converges to the old checkpoint.
 pub struct Highlighter {
-    cache: Vec<Vec<Style>>, // L(buf) == L(cache)
-    highlighter: HighlightLines<'static>,
+    cache: Rope<CacheLine>, // L(buf) == L(cache)
+    valid_until: usize,
+    init: Checkpoint,
+    highlighter: highlighting::Highlighter<'static>,
 }
 impl Highlighter {
     pub fn new(n_rows: usize, ext: Option<&str>) -> Self {
         // FIXME
         let syntax = ps.find_syntax_by_extension(ext.unwrap_or("rs")).unwrap_or(ps.find_syntax_plain_text());
+        let highlighter = highlighting::Highlighter::new(theme::default());
+        let init = Checkpoint {
+            parse_state: ParseState::new(syntax),
+            highlight_state: HighlightState::new(&highlighter, ScopeStack::new()),
+        };
         Self {
-            cache: vec![vec![]; n_rows],
-            highlighter: HighlightLines::new(syntax, theme::default()),
+            cache: Rope::from(vec![CacheLine::new(None); n_rows]),
+            valid_until: 0,
+            init,
+            highlighter,
         }
     }
 
-    // diff update is not implemeneted at the moment.
-    // unlike search, highlighting needs a parse state rather than the indivisual line data.
-    fn cache_insert_new_line(&mut self, row: usize) {
-        self.cache.insert(row, vec![]);
+    pub fn cache_insert_new_line(&mut self, row: usize) {
+        // the state after the previous row is unchanged
+        let start = if row < self.cache.len() {
+            self.cache[row].start.clone()
+        } else {
+            None
+        };
+        self.cache.insert(row, CacheLine::new(start));
+        self.valid_until = std::cmp::min(self.valid_until, row);
     }
-    fn cache_remove_line(&mut self, row: usize) {
+    pub fn cache_remove_line(&mut self, row: usize) {
         self.cache.remove(row);
+        if row < self.cache.len() {
+            // the checkpoint of the next row no longer follows the previous row
+            self.cache[row].styles = None;
+        }
+        self.valid_until = std::cmp::min(self.valid_until, row);
     }
-    pub fn clear_cache(&mut self, n_rows: usize) {
-        self.cache = vec![vec![]; n_rows];
+    /// The content of the row is changed.
+    pub fn cache_update_line(&mut self, row: usize) {
+        self.cache[row].styles = None;
+        self.valid_until = std::cmp::min(self.valid_until, row + 1);
     }
 
-    fn update_highlight_line(&mut self, row: usize, buf: &[Vec<BufElem>]) {
-        if !self.cache[row].is_empty() {
-            return;
-        }
+    fn highlight_line(&self, start: &Checkpoint, line: &[BufElem]) -> (Vec<Style>, Checkpoint) {
         let mut s = String::new();
-        for e in &buf[row] {
+        for e in line {
             let c = match *e {
                 BufElem::Char(c) => c,
                 BufElem::Eol => '\n'
@@ -50,19 +95,56 @@ impl Highlighter {
             s.push(c);
         }
 
-        let highlight_result = self.highlighter.highlight(&s, &ps);
+        let mut state = start.clone();
+        let ops = state.parse_state.parse_line(&s, &ps);
+        let highlight_result = HighlightIterator::new(&mut state.highlight_state, &ops, &s, &self.highlighter);
 
         let mut v = vec![];
         for (style, s) in highlight_result {
-            for _ in 0 .. s.len() {
+            for _ in s.chars() {
                 v.push(style);
             }
         }
-        self.cache[row] = v;
+        (v, state)
     }
-    pub fn update_cache(&mut self, row_range: std::ops::Range<usize>, buf: &[Vec<BufElem>]) {
-        for row in row_range {
-            self.update_highlight_line(row, &buf);
+    /// Highlights the rows in the range.
+    /// The rows above the range are walked from the last trusted checkpoint
+    /// because the state at the head of the range depends on them.
+    pub fn update_cache<S: Storage + ?Sized>(&mut self, row_range: std::ops::Range<usize>, buf: &S) {
+        let n = buf.len();
+        if n == 0 {
+            return;
+        }
+        if self.valid_until == 0 {
+            self.cache[0].start = Some(self.init.clone());
+            self.valid_until = 1;
+        }
+        let end = std::cmp::min(row_range.end, n);
+        let mut row = std::cmp::min(row_range.start, self.valid_until - 1);
+        while row < end {
+            let next_trusted = row + 1 < self.valid_until;
+            if next_trusted && self.cache[row].styles.is_some() {
+                row += 1;
+                continue;
+            }
+            let (styles, next) = self.highlight_line(self.cache[row].start.as_ref().unwrap(), buf.line(row));
+            self.cache[row].styles = Some(styles);
+            if row + 1 < n && !next_trusted {
+                let converged = self.cache[row + 1].start.as_ref() == Some(&next);
+                self.valid_until = row + 2;
+                if converged {
+                    // the checkpoints are correct up to the next changed row
+                    let mut i = row + 1;
+                    while i < end && self.cache[i].styles.is_some() {
+                        i += 1;
+                    }
+                    self.valid_until = std::cmp::min(i + 1, n);
+                } else {
+                    self.cache[row + 1].start = Some(next);
+                    self.cache[row + 1].styles = None;
+                }
+            }
+            row += 1;
         }
     }
 }
@@ -81,7 +163,12 @@ impl <'a> HighlightDiffViewRef<'a> {
 }
 impl <'a> view::View for HighlightDiffViewRef<'a> {
     fn get(&self, col: usize, row: usize) -> view::ViewElem {
-        match self.back.cache.get(row).and_then(|x| x.get(col)) {
+        let style = if row < self.back.cache.len() {
+            self.back.cache[row].styles.as_ref().and_then(|x| x.get(col))
+        } else {
+            None
+        };
+        match style {
             Some(style) => {
                 let fg = style.foreground.into();
                 let bg = style.background.into();
@@ -120,4 +207,54 @@ impl <'a> view::View for HighlightDiffViewRef<'a> {
 //             },
 //         }
 //     }
-// }
\ No newline at end of file
+// }
+#[cfg(test)]
+fn to_buf(lines: &[&str]) -> Vec<Vec<BufElem>> {
+    lines.iter().map(|s| {
+        let mut v: Vec<BufElem> = s.chars().map(BufElem::Char).collect();
+        v.push(BufElem::Eol);
+        v
+    }).collect()
+}
+
+#[test]
+fn test_highlight_block_comment_after_scroll() {
+    let buf = to_buf(&["/*", "let x = 1;", "*/", "let x = 1;"]);
+    let mut hl = Highlighter::new(buf.len(), Some("rs"));
+    // only the bottom rows are visible first
+    hl.update_cache(1..4, &buf);
+    let comment = hl.cache[1].styles.clone().unwrap();
+    let code = hl.cache[3].styles.clone().unwrap();
+    assert_ne!(comment, code);
+    hl.update_cache(0..4, &buf);
+    assert_eq!(hl.cache[1].styles.clone().unwrap(), comment);
+}
+
+#[test]
+fn test_highlight_converge_after_edit() {
+    let mut buf = to_buf(&["let x = 1;", "let y = 2;", "let z = 3;"]);
+    let mut hl = Highlighter::new(buf.len(), Some("rs"));
+    hl.update_cache(0..3, &buf);
+    let code = hl.cache[2].styles.clone().unwrap();
+
+    // open a block comment at the head
+    buf.insert(0, to_buf(&["/*"]).pop().unwrap());
+    hl.cache_insert_new_line(0);
+    hl.update_cache(0..4, &buf);
+    assert_ne!(hl.cache[3].styles.clone().unwrap(), code);
+
+    // close it again
+    buf.remove(0);
+    hl.cache_remove_line(0);
+    hl.update_cache(0..3, &buf);
+    assert_eq!(hl.cache[2].styles.clone().unwrap(), code);
+
+    // an edit that doesn't change the state stops at the edited row
+    buf[1].insert(0, BufElem::Char(' '));
+    hl.cache_update_line(1);
+    hl.update_cache(0..1, &buf);
+    assert!(hl.cache[1].styles.is_none());
+    hl.update_cache(0..3, &buf);
+    assert_eq!(hl.cache[2].styles.clone().unwrap(), code);
+    assert_eq!(hl.valid_until, 3);
+}
@@ -1,14 +1,22 @@
 pub mod change_log;
 pub mod clipboard;
+pub mod command_line;
 pub mod diff_buffer;
+pub mod file_format;
 pub mod highlight;
 pub mod indent;
-pub mod undo_buffer;
+pub mod operator;
+pub mod save;
+pub mod swap;
+pub mod undo_tree;
 mod diff_tree;
+mod dot_repeat;
 mod snippet;
 pub mod config;
 
 use self::change_log::{ChangeLog, ChangeLogBuffer};
+use self::undo_tree::{NodeId, Step};
+use self::command_line::{Command, CommandLine};
 use self::diff_buffer::DiffBuffer;
 
 pub use self::indent::IndentType;
@@ -18,24 +26,37 @@ use crate::view::{ViewGen, Area};
 use crate::navigator::Navigator;
 use crate::message_box::MessageBox;
 use crate::navigator;
+use crate::lsp;
+use crate::git;
+use crate::watcher::{self, Stamp};
+use self::swap::Swap;
+use self::file_format::{Encoding, FileFormat, LineEnding};
 use crate::read_buffer::{self, *};
 use crate::screen::{self, Color};
 use crate::read_buffer::{BufElem, Cursor, CursorRange};
 use std::fs;
 use std::path;
-use std::time::Instant;
+use std::time::{Duration, SystemTime};
 use self::snippet::SnippetElem;
 use self::diff_tree::ChildComponent;
-use crate::read_buffer::{INIT, SEARCH, JUMP};
+use crate::read_buffer::{INIT, SEARCH, JUMP, Motion, MOTIONS};
+use crate::read_buffer::text_object::TextObject;
+use self::operator::Operator;
+use self::dot_repeat::DotRepeat;
 
 const COMMAND: &str = "Command";
+const COMMAND_LINE: &str = "CommandLine";
 const REPLACE_ONCE: &str = "ReplaceOnce";
 const WARP: &str = "Warp";
-const WILL_DELETE: &str = "WillDelete";
-const WILL_YANK: &str = "WillYank";
-const WILL_CHANGE: &str = "WillChange";
+const OPERATOR: &str = "Operator"; // waiting for the motion
+const OPERATOR_OBJECT: &str = "OperatorObject"; // waiting for the text object after i or a
+const REGISTER: &str = "Register"; // waiting for the register name after "
 const INSERT: &str = "Insert";
 const SNIPPET: &str = "Snippet";
+const REPLACE_WITH: &str = "ReplaceWith";
+const REPLACE_CONFIRM: &str = "ReplaceConfirm";
+const RELOAD_CONFIRM: &str = "ReloadConfirm"; // the file changed on disk while the buffer is dirty
+const RECOVER_CONFIRM: &str = "RecoverConfirm"; // a swap file is found on open
 
 fn to_elems(x: &str) -> Vec<BufElem> {
     let mut v = vec![];
@@ -60,12 +81,26 @@ pub struct EditBuffer {
     pub rb: ReadBuffer,
     config: Config,
     visual_cursor: Option<Cursor>,
+    pending_operator: Option<(Operator, Option<usize>)>, // with the count typed before
+    object_inner: bool, // i or a
+    register: Option<char>, // the register for the next yank, delete or paste
     change_log_buffer: ChangeLogBuffer,
     edit_state: Option<EditState>,
     path: path::PathBuf,
-    sync_clock: Option<Instant>,
+    sync_node: NodeId, // the state last saved
+    disk_stamp: Option<Stamp>, // the file last read or written
+    swap: Swap,
+    recovery: Option<Vec<ChangeLog>>, // the changes in the swap file found on open
+    format: FileFormat,
+    saved_format: FileFormat, // converting the format makes the buffer dirty
+    n_changes: usize, // the change logs pushed. tells the command changed the buffer
     highlighter: highlight::Highlighter,
+    lsp: Option<lsp::Document>,
+    lsp_message: Option<String>, // the diagnostic last shown in the message box
+    git: Option<git::Gutter>,
     snippet_repo: snippet::SnippetRepo,
+    command_line: CommandLine,
+    replace_state: Option<ReplaceState>,
     navigator: Rc<RefCell<Navigator>>,
     state: PageState,
     message_box: MessageBox,
@@ -75,7 +110,19 @@ struct EditState {
     diff_buffer: DiffBuffer,
     at: Cursor,
     removed: Vec<BufElem>,
-    orig_buf: Vec<Vec<BufElem>>,
+    orig_buf: Rope<Vec<BufElem>>,
+}
+
+/// Replacing the search hits one by one.
+/// Each replacement is applied to the buffer as a preview and
+/// they are squashed into one change at the end.
+struct ReplaceState {
+    replacement: String,
+    since: NodeId,
+    orig_buf: Rope<Vec<BufElem>>,
+    preview: Option<(Cursor, Cursor)>, // the hit and the end of the replaced text
+    n_replaced: usize,
+    start: Cursor, // where the replacement started
 }
 
 fn trim_right(xs: Vec<BufElem>) -> Vec<BufElem> {
@@ -91,29 +138,80 @@ fn trim_right(xs: Vec<BufElem>) -> Vec<BufElem> {
 }
 
 pub fn read_buffer(path: &path::Path) -> Vec<Vec<BufElem>> {
-    let s = fs::read_to_string(path).ok();
-    crate::read_buffer::read_from_string(s)
+    file_format::read_file(path).0
 }
 
 impl EditBuffer {
     pub fn open(path: &path::Path, navigator: Rc<RefCell<Navigator>>) -> EditBuffer {
         let ext: Option<&str> = path.extension().map(|ext| ext.to_str().unwrap());
-        let init_buf = read_buffer(path);
+        let (init_buf, format) = file_format::read_file(path);
         let n_rows = init_buf.len();
         let state = PageState::new(INIT.to_owned());
         let message_box = MessageBox::new();
         let config = crate::config::SINGLETON.get_config(path);
+        let fingerprint = change_log::fingerprint(&init_buf);
+        let change_log_buffer = if config.undo_persist {
+            ChangeLogBuffer::load(&change_log::undo_file_path(path), fingerprint, config.undo_limit)
+        } else {
+            None
+        }.unwrap_or_else(|| ChangeLogBuffer::new(config.undo_limit));
+        let sync_node = change_log_buffer.current();
+        let swap_path = swap::swap_file_path(path);
+        let mut swap = Swap::new(swap_path.clone(), fingerprint);
+        let recovery = match swap::find(&swap_path, path, fingerprint) {
+            swap::Found::Changes(v) => {
+                message_box.send(&format!("Found the unsaved changes of {}. Recover? (y/n)", path.to_str().unwrap()));
+                state.set(RECOVER_CONFIRM.to_owned());
+                Some(v)
+            },
+            swap::Found::Mismatch => {
+                let bak = save::backup_path(&swap_path);
+                let _ = fs::rename(&swap_path, &bak);
+                message_box.send(&format!("The swap file doesn't match the file. Moved to {}", bak.to_str().unwrap()));
+                None
+            },
+            swap::Found::InUse(pid) => {
+                message_box.send(&format!("{} is being edited by another process (pid {}). No swap file is written", path.to_str().unwrap(), pid));
+                swap.disable();
+                None
+            },
+            swap::Found::None => None,
+        };
+        let lsp = match (&config.lang, &config.lsp) {
+            (Some(lang), Some(command)) => match lsp::get_client(lang, command) {
+                Ok(client) => Some(lsp::Document::open(client, path, lang, &init_buf)),
+                Err(e) => {
+                    message_box.send(&format!("Failed to start the language server: {}", e));
+                    None
+                },
+            },
+            _ => None,
+        };
 
         EditBuffer {
             rb: ReadBuffer::new(init_buf, state.clone(), message_box.clone()),
             snippet_repo: snippet::SnippetRepo::new(config.snippet.clone(), state.clone(), message_box.clone()),
             config: config,
             visual_cursor: None,
-            change_log_buffer: ChangeLogBuffer::new(),
+            pending_operator: None,
+            object_inner: false,
+            register: None,
+            change_log_buffer,
             edit_state: None,
             path: path.to_owned(),
-            sync_clock: None,
+            sync_node,
+            disk_stamp: watcher::stamp(path),
+            swap,
+            format,
+            saved_format: format,
+            recovery,
+            n_changes: 0,
             highlighter: highlight::Highlighter::new(n_rows, ext),
+            lsp,
+            lsp_message: None,
+            git: git::Gutter::open(path),
+            command_line: CommandLine::new(),
+            replace_state: None,
             navigator,
             state,
             message_box,
@@ -122,29 +220,94 @@ impl EditBuffer {
     fn insert_new_line(&mut self, row: usize) {
         self.rb.buf.insert(row, vec![]);
         self.rb.cache_insert_new_line(row);
-        // self.highlighter.cache_insert_new_line(row);
-        self.highlighter.clear_cache(self.rb.buf.len());
+        self.highlighter.cache_insert_new_line(row);
     }
     fn remove_line(&mut self, row: usize) {
         self.rb.buf.remove(row);
         self.rb.cache_remove_line(row);
-        // self.highlighter.cache_remove_line(row);
-        self.highlighter.clear_cache(self.rb.buf.len());
+        self.highlighter.cache_remove_line(row);
     }
     fn update_cache(&mut self) {
         self.rb.update_cache();
+        self.update_swap();
 
         flame::start("update highlight");
         self.highlighter
             .update_cache(self.rb.lineno_range(), &self.rb.buf);
         flame::end("update highlight");
     }
-    fn is_dirty(&self) -> bool {
-        match (self.sync_clock, self.change_log_buffer.clock()) {
-            (_, None) => false,
-            (None, Some(_)) => true,
-            (Some(x), Some(y)) => x < y,
+    fn push_change_log(&mut self, log: ChangeLog) {
+        self.n_changes += 1;
+        self.notify_change(&log);
+        self.change_log_buffer.push(log);
+    }
+    // all changes to the buffer are either pushed or undone/redone as a change log.
+    // so the language server sees the same text as ours by following the change logs.
+    fn notify_change(&mut self, log: &ChangeLog) {
+        self.swap.record(log);
+        if let Some(doc) = self.lsp.as_mut() {
+            let line_head = &self.rb.buf[log.at.row][..log.at.col];
+            doc.did_change(log, line_head);
+        }
+    }
+    fn update_lsp_message(&mut self) {
+        let diagnostic = self.lsp.as_ref().and_then(|doc| {
+            let row = self.rb.cursor.row;
+            doc.diagnostics().into_iter().filter(|d| d.row == row).min_by(|a, b| a.severity.partial_cmp(&b.severity).unwrap())
+        });
+        let message = diagnostic.map(|d| d.message);
+        if let Some(x) = message.as_ref() {
+            if message != self.lsp_message {
+                self.message_box.send(x);
+            }
+        }
+        self.lsp_message = message;
+    }
+    fn update_git_markers(&mut self) {
+        // the buffer is being rewritten in insert mode
+        if self.edit_state.is_some() {
+            return
         }
+        if let Some(gutter) = self.git.as_mut() {
+            gutter.update(&self.rb.buf, self.change_log_buffer.current());
+        }
+    }
+    fn update_swap(&mut self) {
+        if !self.is_dirty() {
+            self.swap.remove();
+            return
+        }
+        if let Err(e) = self.swap.tick() {
+            self.message_box.send(&format!("Failed to write the swap file: {}", e));
+        }
+    }
+    fn eff_recover_confirm(&mut self, k: Key) -> String {
+        match k {
+            Key::Char('y') => {
+                for mut log in self.recovery.take().unwrap() {
+                    self.push_change_log(log.clone());
+                    self.apply_log(&mut log);
+                    self.rb.cursor = log.at;
+                }
+                self.rb.stabilize_cursor();
+                // replaces the swap file found
+                if let Err(e) = self.swap.write() {
+                    self.message_box.send(&format!("Failed to write the swap file: {}", e));
+                    return INIT.to_owned()
+                }
+                self.message_box.send("Recovered. Save to keep the changes");
+            },
+            Key::Char('n') | Key::Esc => {
+                self.recovery = None;
+                let _ = fs::remove_file(swap::swap_file_path(&self.path));
+                self.message_box.send("Discarded the swap file");
+            },
+            _ => return RECOVER_CONFIRM.to_owned(),
+        }
+        INIT.to_owned()
+    }
+    fn is_dirty(&self) -> bool {
+        self.change_log_buffer.current() != self.sync_node || self.format != self.saved_format
     }
     fn apply_log(&mut self, log: &mut ChangeLog) {
         let delete_range = CursorRange {
@@ -166,40 +329,57 @@ impl EditBuffer {
             &mut b,
         );
     }
-    fn rollback_sync_clock(&mut self) {
-        match (self.sync_clock, self.change_log_buffer.clock()) {
-            (Some(a), Some(b)) => {
-                if a > b {
-                    self.sync_clock = None;
-                }
-            }
-            (Some(_), None) => {
-                self.sync_clock = None;
-            }
-            (None, _) => {}
-        }
-    }
-    fn undo(&mut self) -> bool {
-        let log = self.change_log_buffer.pop_undo();
-        if log.is_none() {
-            return false;
-        }
-        self.rollback_sync_clock();
-        let mut log = log.unwrap().swap();
+    fn undo_log(&mut self, log: ChangeLog) {
+        let mut log = log.swap();
+        self.notify_change(&log);
         self.apply_log(&mut log);
         self.rb.cursor = log.at;
-        true
     }
-    fn redo(&mut self) -> bool {
-        let log = self.change_log_buffer.pop_redo();
-        if log.is_none() {
-            return false;
-        }
-        let mut log = log.unwrap();
+    fn redo_log(&mut self, log: ChangeLog) {
+        let mut log = log;
         let n_inserted = log.inserted.len();
+        self.notify_change(&log);
         self.apply_log(&mut log);
         self.rb.cursor = self.find_cursor_pair(log.at, n_inserted);
-        true
+    }
+    fn undo(&mut self) -> bool {
+        match self.change_log_buffer.pop_undo() {
+            Some(log) => {
+                self.undo_log(log);
+                true
+            },
+            None => false,
+        }
+    }
+    fn redo(&mut self) -> bool {
+        match self.change_log_buffer.pop_redo() {
+            Some(log) => {
+                self.redo_log(log);
+                true
+            },
+            None => false,
+        }
+    }
+    // moving to another branch undoes up to the common ancestor and then redoes.
+    fn apply_steps(&mut self, steps: Vec<Step<ChangeLog>>) {
+        if steps.is_empty() {
+            self.message_box.send("Already at the oldest or newest change");
+            return
+        }
+        for step in steps {
+            match step {
+                Step::Undo(log) => self.undo_log(log),
+                Step::Redo(log) => self.redo_log(log),
+            }
+        }
+        let msg = match self.change_log_buffer.clock() {
+            Some(t) => {
+                let secs = SystemTime::now().duration_since(t).map(|d| d.as_secs()).unwrap_or(0);
+                format!("Change #{}, {}s ago", self.change_log_buffer.current(), secs)
+            },
+            None => "Original text".to_owned(),
+        };
+        self.message_box.send(&msg);
     }
     
     pub fn visual_range(&self) -> Option<CursorRange> {
@@ -246,12 +426,14 @@ impl EditBuffer {
             match e.clone() {
                 x @ BufElem::Eol => {
                     self.rb.buf[row].insert(col, x);
+                    self.highlighter.cache_update_line(row);
                     *should_insert_newline = true;
                     row += 1;
                     col = 0;
                 }
                 x @ BufElem::Char(_) => {
                     self.rb.buf[row].insert(col, x);
+                    self.highlighter.cache_update_line(row);
                     col += 1;
                 }
             }
@@ -321,15 +503,16 @@ impl EditBuffer {
         // the characters in the range will be deleted and
         // others will survive, be merged and inserted afterward
         for (row, col_range) in target_region.clone() {
-            for col in 0..self.rb.buf[row].len() {
+            let line = &self.rb.buf[row];
+            for col in 0..line.len() {
                 if col_range.start <= col && col < col_range.end {
-                    removed.push(self.rb.buf[row][col].clone())
+                    removed.push(line[col].clone())
                 } else {
                     let as_cursor = Cursor { row, col };
                     if as_cursor < range.start {
-                        pre_survivors.push(self.rb.buf[row][col].clone())
+                        pre_survivors.push(line[col].clone())
                     } else {
-                        post_survivors.push(self.rb.buf[row][col].clone())
+                        post_survivors.push(line[col].clone())
                     }
                 }
             }
@@ -375,7 +558,7 @@ impl EditBuffer {
             edit_state.diff_buffer.inserted(),
         );
         if change_log.deleted.len() > 0 || change_log.inserted.len() > 0 {
-            self.change_log_buffer.push(change_log);
+            self.push_change_log(change_log);
         }
 
         edit_state.removed
@@ -417,21 +600,447 @@ impl EditBuffer {
     fn eff_cancel_command_mode(&mut self, _: Key) -> String {
         INIT.to_owned()
     }
-    fn close_buffer(&self) {
+    fn close_buffer(&mut self) {
+        self.swap.remove();
         self.navigator.borrow_mut().pop()
     }
-    fn save_to_file(&mut self) {
-        if let Ok(file) = fs::File::create(&self.path) {
-            let buf = &self.rb.buf;
-            crate::read_buffer::write_to_file(file, &buf);
-            self.sync_clock = self.change_log_buffer.clock();
-            self.message_box.send("Saved")
+    /// Returns false if not saved. The file changed on disk since it was read is overwritten only if forced.
+    fn save_to_file(&mut self, force: bool) -> bool {
+        let path = self.path.to_str().unwrap().to_owned();
+        let cur = watcher::stamp(&self.path);
+        if !force && cur.is_some() && cur != self.disk_stamp {
+            self.message_box.send(&format!("{} changed on disk since read (add ! to override)", path));
+            return false
+        }
+        let (buf, format) = (&self.rb.buf, &self.format);
+        if let Err(e) = save::write_atomic(&self.path, self.config.backup, |out| file_format::write(out, buf, format)) {
+            self.message_box.send(&format!("Failed to save {}: {}", path, e));
+            return false
+        }
+        self.sync_node = self.change_log_buffer.current();
+        self.saved_format = self.format;
+        self.disk_stamp = watcher::stamp(&self.path);
+        self.swap.reset(change_log::fingerprint(&self.rb.buf));
+        // HEAD may have moved since opened
+        self.git = git::Gutter::open(&self.path);
+        if let Some(doc) = self.lsp.as_ref() {
+            doc.did_save();
+        }
+        if self.config.undo_persist {
+            let fingerprint = change_log::fingerprint(&self.rb.buf);
+            if let Err(e) = self.change_log_buffer.save(&change_log::undo_file_path(&self.path), fingerprint) {
+                self.message_box.send(&format!("Failed to save the undo history: {}", e));
+                return true
+            }
+        }
+        self.message_box.send("Saved");
+        true
+    }
+    /// Replaces the text with the file as a change so it can be undone. The cursor stays.
+    fn reload(&mut self) {
+        let cursor = self.rb.cursor;
+        let (buf, format) = file_format::read_file(&self.path);
+        let mut log = change_log::replace_all(&self.rb.buf, &buf);
+        self.push_change_log(log.clone());
+        self.apply_log(&mut log);
+        self.sync_node = self.change_log_buffer.current();
+        self.format = format;
+        self.saved_format = format;
+        self.swap.reset(change_log::fingerprint(&self.rb.buf));
+        self.git = git::Gutter::open(&self.path);
+        self.rb.cursor = cursor;
+        self.rb.stabilize_cursor();
+    }
+    fn check_disk(&mut self) -> String {
+        let cur = watcher::stamp(&self.path);
+        if cur == self.disk_stamp {
+            return INIT.to_owned()
+        }
+        self.disk_stamp = cur;
+        let path = self.path.to_str().unwrap().to_owned();
+        if cur.is_none() {
+            self.message_box.send(&format!("{} was removed", path));
+            return INIT.to_owned()
+        }
+        if self.is_dirty() {
+            self.message_box.send(&format!("{} changed on disk. Reload and discard the changes? (y/n)", path));
+            return RELOAD_CONFIRM.to_owned()
+        }
+        self.reload();
+        self.message_box.send(&format!("Reloaded {}", path));
+        INIT.to_owned()
+    }
+    fn eff_reload_confirm(&mut self, k: Key) -> String {
+        match k {
+            Key::Char('y') => {
+                self.reload();
+                self.message_box.send("Reloaded");
+            },
+            Key::Char('n') | Key::Esc => {
+                self.message_box.send("Kept the changes");
+            },
+            _ => return RELOAD_CONFIRM.to_owned(),
+        }
+        INIT.to_owned()
+    }
+    fn write_to_file(&mut self, path: &path::Path) {
+        let (buf, format) = (&self.rb.buf, &self.format);
+        match save::write_atomic(path, self.config.backup, |out| file_format::write(out, buf, format)) {
+            Ok(()) => self.message_box.send(&format!("Written to {}", path.to_str().unwrap())),
+            Err(e) => self.message_box.send(&format!("Failed to write {}: {}", path.to_str().unwrap(), e)),
+        }
+    }
+    fn open_buffer(&self, path: &path::Path) {
+        if let Some(page) = crate::large_file::open(path, 0) {
+            self.navigator.borrow_mut().push(page);
+            return
+        }
+        let x = Rc::new(RefCell::new(EditBuffer::open(path, self.navigator.clone())));
+        let page = Rc::new(RefCell::new(Page::new(x)));
+        self.navigator.borrow_mut().push(page);
+    }
+    fn set_option(&mut self, key: &str, value: Option<&str>) {
+        match (key, value) {
+            ("indent", None) => {
+                let n = match self.config.indent_type {
+                    IndentType::Spaces(n) => n,
+                    IndentType::Tab => 0,
+                };
+                self.message_box.send(&format!("indent={}", n))
+            },
+            ("indent", Some(v)) => match v.parse::<usize>() {
+                // 0 for tab like the config file
+                Ok(0) => self.config.indent_type = IndentType::Tab,
+                Ok(n) => self.config.indent_type = IndentType::Spaces(n),
+                Err(_) => self.message_box.send(&format!("Invalid argument: {}", v)),
+            },
+            ("encoding", None) => self.message_box.send(&format!("encoding={}", self.format.encoding.name())),
+            ("encoding", Some(v)) => match Encoding::from_name(v) {
+                Some(encoding) => self.set_encoding(encoding),
+                None => self.message_box.send(&format!("Invalid argument: {}", v)),
+            },
+            ("lineending", None) => self.message_box.send(&format!("lineending={}", self.format.line_ending.name())),
+            ("lineending", Some(v)) => match LineEnding::from_name(v) {
+                Some(line_ending) => self.format.line_ending = line_ending,
+                None => self.message_box.send(&format!("Invalid argument: {}", v)),
+            },
+            _ => self.message_box.send(&format!("Unknown option: {}", key)),
+        }
+    }
+    /// Refused if any char in the buffer can't be encoded.
+    fn set_encoding(&mut self, encoding: Encoding) {
+        for row in 0..self.rb.buf.len() {
+            for e in &self.rb.buf[row] {
+                if let BufElem::Char(c) = *e {
+                    if !encoding.can_encode(c) {
+                        self.message_box.send(&format!("{:?} at line {} can't be encoded in {}", c, row + 1, encoding.name()));
+                        return
+                    }
+                }
+            }
+        }
+        self.format.encoding = encoding;
+    }
+    /// The rows between the first and the last changed ones are replaced as a single change
+    /// so the substitution is undone at once.
+    fn substitute(&mut self, rows: std::ops::Range<usize>, pattern: &str, replacement: &str, global: bool) {
+        // a regex as in vi
+        let re = match crate::grep::pattern(pattern, search::Mode::Regex) {
+            Ok(x) => x,
+            Err(_) => {
+                self.message_box.send(&format!("Invalid pattern: {}", pattern));
+                return
+            },
+        };
+        let mut n_subst = 0;
+        let mut changed = vec![];
+        for row in rows {
+            let line = &self.rb.buf[row];
+            let s: String = line[..line.len()-1].iter().map(|e| match e {
+                BufElem::Char(c) => *c,
+                BufElem::Eol => '\n',
+            }).collect();
+            if let Some((s, n)) = command_line::substitute(&s, &re, replacement, global) {
+                n_subst += n;
+                changed.push((row, s));
+            }
+        }
+        let (first, last) = match (changed.first(), changed.last()) {
+            (Some(x), Some(y)) => (x.0, y.0),
+            _ => {
+                self.message_box.send(&format!("Pattern not found: {}", pattern));
+                return
+            },
+        };
+        let mut deleted = vec![];
+        let mut inserted = vec![];
+        let mut changed = changed.into_iter().peekable();
+        for row in first..last+1 {
+            let line = &self.rb.buf[row];
+            let n = if row == last { line.len() - 1 } else { line.len() };
+            deleted.extend_from_slice(&line[..n]);
+            match changed.peek() {
+                Some((r, _)) if *r == row => {
+                    let (_, s) = changed.next().unwrap();
+                    inserted.append(&mut to_elems(&s));
+                    if row != last {
+                        inserted.push(BufElem::Eol);
+                    }
+                },
+                _ => inserted.extend_from_slice(&line[..n]),
+            }
+        }
+        let mut log = ChangeLog::new(Cursor { row: first, col: 0 }, deleted, inserted);
+        self.push_change_log(log.clone());
+        self.apply_log(&mut log);
+        self.rb.cursor = Cursor { row: last, col: 0 };
+        self.message_box.send(&format!("{} substitutions on {} lines", n_subst, last - first + 1));
+    }
+    fn run_command(&mut self, cmd: Command) {
+        match cmd {
+            Command::Write { path: None, force } => {
+                self.save_to_file(force);
+            },
+            Command::Write { path: Some(path), .. } => self.write_to_file(&path),
+            Command::WriteQuit => {
+                if self.save_to_file(false) {
+                    self.close_buffer();
+                }
+            },
+            Command::Quit { force } => {
+                if !force && self.is_dirty() {
+                    self.message_box.send("No write since last change (add ! to override)")
+                } else {
+                    self.close_buffer()
+                }
+            },
+            Command::Edit(path) => self.open_buffer(&path),
+            Command::Set { key, value } => self.set_option(&key, value.as_deref()),
+            Command::Substitute { range, pattern, replacement, global } => {
+                match range.rows(self.rb.cursor.row, self.rb.buf.len()) {
+                    Some(rows) => self.substitute(rows, &pattern, &replacement, global),
+                    None => self.message_box.send("Invalid range"),
+                }
+            },
+        }
+    }
+    fn show_command_line(&self) {
+        self.message_box.send(&format!(":{}", self.command_line.input()));
+    }
+    fn eff_enter_command_line(&mut self, _: Key) -> String {
+        self.command_line.clear();
+        self.show_command_line();
+        COMMAND_LINE.to_owned()
+    }
+    fn eff_cancel_command_line(&mut self, _: Key) -> String {
+        self.command_line.clear();
+        self.message_box.send("");
+        INIT.to_owned()
+    }
+    fn eff_command_line_input(&mut self, k: Key) -> String {
+        match k {
+            Key::Char('\n') => {
+                let s = self.command_line.commit();
+                self.message_box.send("");
+                // like vi, an empty line does nothing
+                if s.trim().is_empty() {
+                    return INIT.to_owned()
+                }
+                match command_line::parse(&s) {
+                    Ok(cmd) => self.run_command(cmd),
+                    Err(e) => self.message_box.send(&e),
+                }
+                return INIT.to_owned()
+            },
+            Key::Char('\t') => {
+                let candidates = self.command_line.complete();
+                if candidates.len() > 1 {
+                    self.message_box.send(&candidates.join(" "));
+                    return COMMAND_LINE.to_owned()
+                }
+            },
+            // backspace on the empty line leaves
+            Key::Backspace if self.command_line.input().is_empty() => return self.eff_cancel_command_line(k),
+            Key::Backspace => {
+                self.command_line.pop();
+            },
+            Key::Up | Key::Ctrl('p') => self.command_line.history_prev(),
+            Key::Down | Key::Ctrl('n') => self.command_line.history_next(),
+            Key::Char(c) => self.command_line.push(c),
+            _ => {},
+        }
+        self.show_command_line();
+        COMMAND_LINE.to_owned()
+    }
+    fn enter_replace_with(&mut self) -> String {
+        if self.rb.search.cur_word.is_empty() {
+            self.message_box.send("No search word");
+            return INIT.to_owned()
+        }
+        self.replace_state = Some(ReplaceState {
+            replacement: String::new(),
+            since: self.change_log_buffer.current(),
+            orig_buf: self.rb.buf.clone(),
+            preview: None,
+            n_replaced: 0,
+            start: self.rb.cursor,
+        });
+        self.show_replace_with();
+        REPLACE_WITH.to_owned()
+    }
+    fn show_replace_with(&self) {
+        let word: String = self.rb.search.cur_word.iter().collect();
+        let replacement = &self.replace_state.as_ref().unwrap().replacement;
+        self.message_box.send(&format!("Replace {} with: {}", word, replacement));
+    }
+    fn eff_replace_with_input(&mut self, k: Key) -> String {
+        match k {
+            Key::Char('\n') => {
+                self.rb.search.show_search();
+                let cursor = self.rb.cursor;
+                return self.preview_next_hit(cursor, true)
+            },
+            Key::Backspace => {
+                self.replace_state.as_mut().unwrap().replacement.pop();
+            },
+            Key::Char(c) => self.replace_state.as_mut().unwrap().replacement.push(c),
+            _ => {},
+        }
+        self.show_replace_with();
+        REPLACE_WITH.to_owned()
+    }
+    fn eff_cancel_replace(&mut self, _: Key) -> String {
+        self.replace_state = None;
+        self.message_box.send("");
+        INIT.to_owned()
+    }
+    /// Replaces the hit and returns the end of the replaced text.
+    fn replace_hit(&mut self, at: Cursor, len: usize) -> Cursor {
+        let end = self.find_cursor_pair(at, len);
+        let mut hit = vec![];
+        for (row, cols) in self.expand_range(&CursorRange { start: at, end }) {
+            hit.extend_from_slice(&self.rb.buf[row][cols]);
+        }
+        let hit_str: String = hit.iter().map(|e| match e {
+            BufElem::Char(c) => *c,
+            BufElem::Eol => '\n',
+        }).collect();
+        let replacement = self.rb.search.expand(&hit_str, &self.replace_state.as_ref().unwrap().replacement);
+        let mut log = ChangeLog::new(at, hit, to_elems(&replacement));
+        let n_inserted = log.inserted.len();
+        self.push_change_log(log.clone());
+        self.apply_log(&mut log);
+        self.find_cursor_pair(at, n_inserted)
+    }
+    /// The next hit from the cursor. Doesn't wrap around the buffer.
+    fn find_hit(&mut self, from: Cursor, inclusive: bool) -> Option<(Cursor, usize)> {
+        let n_rows = self.rb.buf.len();
+        let hit = match self.rb.search.hit_at(from, &self.rb.buf) {
+            Some(len) if inclusive => Some((from, len)),
+            _ => self.rb.search.next(from, &self.rb.buf).filter(|&x| x > from).map(|x| {
+                let len = self.rb.search.hit_at(x, &self.rb.buf).unwrap();
+                (x, len)
+            }),
+        }?;
+        // the last eol can't be replaced
+        let end = self.find_cursor_pair(hit.0, hit.1);
+        if end.row == n_rows - 1 && end.col == self.rb.buf[n_rows - 1].len() {
+            return None
+        }
+        Some(hit)
+    }
+    fn preview_next_hit(&mut self, from: Cursor, inclusive: bool) -> String {
+        let (at, len) = match self.find_hit(from, inclusive) {
+            Some(x) => x,
+            None => return self.finish_replace(),
+        };
+        let end = self.replace_hit(at, len);
+        self.replace_state.as_mut().unwrap().preview = Some((at, end));
+        if end > at {
+            self.visual_cursor = Some(at);
+            self.rb.cursor = self.find_cursor_pair(at, self.distance(at, end) - 1);
+        } else {
+            self.visual_cursor = None;
+            self.rb.cursor = at;
+        }
+        self.message_box.send("Replace? (y)es (n)o (a)ll (q)uit");
+        REPLACE_CONFIRM.to_owned()
+    }
+    // the number of the elements between the cursors
+    fn distance(&self, from: Cursor, to: Cursor) -> usize {
+        self.expand_range(&CursorRange { start: from, end: to }).into_iter().map(|(_, cols)| cols.len()).sum()
+    }
+    fn undo_preview(&mut self) -> Option<Cursor> {
+        let (at, _) = self.replace_state.as_mut().unwrap().preview.take()?;
+        let log = self.change_log_buffer.discard().unwrap();
+        self.undo_log(log);
+        Some(at)
+    }
+    fn finish_replace(&mut self) -> String {
+        let state = self.replace_state.take().unwrap();
+        if self.change_log_buffer.current() != state.since {
+            if let Some(log) = change_log::diff_rows(&state.orig_buf, &self.rb.buf) {
+                self.change_log_buffer.squash(state.since, log);
+            }
+        }
+        self.visual_cursor = None;
+        self.message_box.send(&format!("{} replacements", state.n_replaced));
+        INIT.to_owned()
+    }
+    fn eff_replace_confirm(&mut self, k: Key) -> String {
+        match k {
+            Key::Char('y') => {
+                let (_, end) = self.replace_state.as_mut().unwrap().preview.take().unwrap();
+                self.replace_state.as_mut().unwrap().n_replaced += 1;
+                self.preview_next_hit(end, true)
+            },
+            Key::Char('n') => {
+                let at = self.undo_preview().unwrap();
+                self.preview_next_hit(at, false)
+            },
+            Key::Char('a') => {
+                let (_, mut end) = self.replace_state.as_mut().unwrap().preview.take().unwrap();
+                self.replace_state.as_mut().unwrap().n_replaced += 1;
+                while let Some((at, len)) = self.find_hit(end, true) {
+                    end = self.replace_hit(at, len);
+                    self.replace_state.as_mut().unwrap().n_replaced += 1;
+                }
+                // wraps around once and stops at the start
+                let mut start = self.replace_state.as_ref().unwrap().start;
+                let mut end = Cursor { row: 0, col: 0 };
+                while let Some((at, len)) = self.find_hit(end, true) {
+                    let hit_end = self.find_cursor_pair(at, len);
+                    if hit_end > start {
+                        break
+                    }
+                    end = self.replace_hit(at, len);
+                    self.replace_state.as_mut().unwrap().n_replaced += 1;
+                    // the start moves with the text before it
+                    start = if start.row == hit_end.row {
+                        Cursor { row: end.row, col: end.col + start.col - hit_end.col }
+                    } else {
+                        Cursor { row: start.row + end.row - hit_end.row, col: start.col }
+                    };
+                }
+                self.finish_replace()
+            },
+            Key::Char('q') | Key::Esc => {
+                self.undo_preview();
+                self.finish_replace()
+            },
+            _ => REPLACE_CONFIRM.to_owned(),
         }
     }
     fn eff_execute_command(&mut self, k: Key) -> String {
         match k {
-            Key::Char('w') => self.save_to_file(),
+            Key::Char('w') => {
+                self.save_to_file(false);
+            },
             Key::Char('q') => self.close_buffer(),
+            Key::Char('g') => git::status::open(&self.path, self.navigator.clone(), &self.message_box),
+            Key::Char('r') => return self.enter_replace_with(),
+            Key::Char('p') => crate::finder::open(&self.path, false, self.navigator.clone()),
+            Key::Char('"') => crate::registers::open(self.navigator.clone()),
             _ => {},
         }
         INIT.to_owned()
@@ -444,6 +1053,28 @@ impl EditBuffer {
         self.redo();
         INIT.to_owned()
     }
+    fn eff_earlier(&mut self, _: Key) -> String {
+        let steps = self.change_log_buffer.earlier(1);
+        self.apply_steps(steps);
+        INIT.to_owned()
+    }
+    fn eff_later(&mut self, _: Key) -> String {
+        let steps = self.change_log_buffer.later(1);
+        self.apply_steps(steps);
+        INIT.to_owned()
+    }
+    fn eff_earlier_minutes(&mut self, _: Key) -> String {
+        let d = (self.rb.take_num() as u64).checked_mul(60).map_or(Duration::MAX, Duration::from_secs);
+        let steps = self.change_log_buffer.earlier_by(d);
+        self.apply_steps(steps);
+        INIT.to_owned()
+    }
+    fn eff_later_minutes(&mut self, _: Key) -> String {
+        let d = (self.rb.take_num() as u64).checked_mul(60).map_or(Duration::MAX, Duration::from_secs);
+        let steps = self.change_log_buffer.later_by(d);
+        self.apply_steps(steps);
+        INIT.to_owned()
+    }
     fn eff_enter_insert_newline(&mut self, _: Key) -> String {
         let row = self.rb.cursor.row;
         let delete_range = CursorRange {
@@ -532,26 +1163,15 @@ impl EditBuffer {
         self.rb.cursor_right(); 
         self.eff_enter_insert_mode(k)
     }
-    fn eff_change_range(&mut self, _: Key) -> String {
+    fn eff_change_range(&mut self, k: Key) -> String {
         if self.visual_range().is_none() {
-            WILL_CHANGE.to_owned()
+            self.eff_enter_operator(k)
         } else {
             let delete_range = self.visual_range().unwrap();
             self.create_edit_state(&delete_range, vec![], vec![]);
             INSERT.to_owned()
         }
     }
-    fn eff_change_word(&mut self, _: Key) -> String {
-        match self.word_range() {
-            Some(range) => {
-                self.create_edit_state(&range, vec![], vec![]);
-                INSERT.to_owned()
-            },
-            None => {
-                INIT.to_owned()
-            }
-        }
-    }
     fn eff_change_line_tail(&mut self, _: Key) -> String {
         let delete_range = self.line_tail_range();
         self.create_edit_state(&delete_range, vec![], vec![]);
@@ -573,7 +1193,7 @@ impl EditBuffer {
         }
     }
     fn writeback_edit_state(&mut self) {
-        self.rb.buf = self.es_ref().orig_buf.clone(); // needless?
+        self.rb.buf = self.es_ref().orig_buf.clone(); // cheap: the rows are shared
         self.insert_new_line(self.es_ref().at.row);
 
         let mut b = false;
@@ -601,31 +1221,16 @@ impl EditBuffer {
     fn eff_delete_line_tail(&mut self, _: Key) -> String {
         let delete_range = self.line_tail_range();
         let removed = self.delete_range(delete_range);
-        clipboard::copy(clipboard::Type::Range(removed));
-        INIT.to_owned()
-    }
-    fn eff_delete_line(&mut self, _: Key) -> String {
-        let range = CursorRange {
-            start: Cursor {
-                row: self.rb.cursor.row,
-                col: 0,
-            },
-            end: Cursor {
-                row: self.rb.cursor.row,
-                col: self.rb.buf[self.rb.cursor.row].len(),
-            },
-        };
-        let removed = self.delete_range(range);
-        clipboard::copy(clipboard::Type::Line(removed));
+        self.copy_register(clipboard::Kind::Delete, clipboard::Type::Range(removed));
         INIT.to_owned()
     }
-    fn eff_delete_range(&mut self, _: Key) -> String {
+    fn eff_delete_range(&mut self, k: Key) -> String {
         if self.visual_range().is_none() {
-            WILL_DELETE.to_owned()
+            self.eff_enter_operator(k)
         } else {
             let vr = self.visual_range().unwrap();
             let removed = self.delete_range(vr);
-            clipboard::copy(clipboard::Type::Range(removed));
+            self.copy_register(clipboard::Kind::Delete, clipboard::Type::Range(removed));
             INIT.to_owned()
         }
     }
@@ -638,38 +1243,215 @@ impl EditBuffer {
             },
         });
         let removed = self.delete_range(range);
-        clipboard::copy(clipboard::Type::Range(removed));
+        self.copy_register(clipboard::Kind::Delete, clipboard::Type::Range(removed));
+
+        INIT.to_owned()
+    }
+    fn eff_enter_operator(&mut self, k: Key) -> String {
+        let op = match k {
+            Key::Char(c) => Operator::from_char(c).unwrap(),
+            _ => unreachable!(),
+        };
+        self.pending_operator = Some((op, self.rb.take_count()));
+        OPERATOR.to_owned()
+    }
+    fn eff_acc_operator_count(&mut self, k: Key) -> String {
+        self.rb.acc_jump_num(k);
+        OPERATOR.to_owned()
+    }
+    fn eff_cancel_operator(&mut self, _: Key) -> String {
+        self.pending_operator = None;
+        self.rb.cancel_jump();
+        INIT.to_owned()
+    }
+    fn eff_operator_motion(&mut self, k: Key) -> String {
+        // 0 is a part of the count if typed after a number
+        if k == Key::Char('0') && self.rb.has_count() {
+            return self.eff_acc_operator_count(k)
+        }
+        let (op, count) = self.pending_operator.take().unwrap();
+        let count = operator::mul_count(count, self.rb.take_count());
+        let m = Motion::from_key(&k).unwrap();
+
+        let orig = self.rb.cursor;
+        // cw stops at the end of the word like ce
+        let change_word = op == Operator::Change && m == Motion::WordForward && self.word_range().is_some();
+        if change_word {
+            self.rb.apply_motion(m, Some(count.unwrap_or(1) - 1));
+        } else {
+            self.rb.apply_motion(m, count);
+        }
+        let target = self.rb.cursor;
+        self.rb.cursor = orig;
+
+        if m.linewise() {
+            let (top, bottom) = if target.row < orig.row { (target.row, orig.row) } else { (orig.row, target.row) };
+            return self.operate_lines(op, top, bottom)
+        }
 
+        let (start, mut end) = if target < orig { (target, orig) } else { (orig, target) };
+        if change_word {
+            end = match self.rb.line(target.row).word_range(target.col) {
+                Some(r) => Cursor { row: target.row, col: r.end },
+                None => target,
+            };
+        } else if m == Motion::WordForward && end.row > start.row {
+            // dw on the last word of the line doesn't join the next line
+            end = Cursor { row: start.row, col: self.rb.buf[start.row].len() - 1 };
+        }
+        self.operate_range(op, CursorRange { start, end })
+    }
+    fn eff_operator_line(&mut self, k: Key) -> String {
+        let (op, count) = self.pending_operator.take().unwrap();
+        let same = match k {
+            Key::Char(c) => Operator::from_char(c) == Some(op),
+            _ => false,
+        };
+        if !same {
+            self.rb.cancel_jump();
+            return INIT.to_owned()
+        }
+        let n = operator::mul_count(count, self.rb.take_count()).unwrap_or(1);
+        let top = self.rb.cursor.row;
+        let bottom = std::cmp::min(top + n, self.rb.buf.len()) - 1;
+        self.operate_lines(op, top, bottom)
+    }
+    fn eff_enter_operator_object(&mut self, k: Key) -> String {
+        self.object_inner = k == Key::Char('i');
+        OPERATOR_OBJECT.to_owned()
+    }
+    fn eff_operator_object(&mut self, k: Key) -> String {
+        let (op, count) = self.pending_operator.take().unwrap();
+        let has_count = count.is_some() || self.rb.has_count();
+        self.rb.cancel_jump();
+        // 2diw isn't supported
+        if has_count {
+            self.message_box.send("A text object can't take a count");
+            return INIT.to_owned()
+        }
+        let obj = match k {
+            Key::Char(c) => TextObject::from_char(c),
+            _ => None,
+        };
+        let range = obj.and_then(|obj| obj.range(&self.rb.buf, self.rb.cursor, self.object_inner));
+        match (obj, range) {
+            (Some(obj), Some(range)) if obj.linewise() => self.operate_lines(op, range.start.row, range.end.row),
+            (Some(_), Some(range)) => self.operate_range(op, range),
+            _ => INIT.to_owned(),
+        }
+    }
+    /// Operates on the chars in the range. The end is exclusive.
+    fn operate_range(&mut self, op: Operator, range: CursorRange) -> String {
+        if range.start == range.end {
+            return INIT.to_owned()
+        }
+        match op {
+            Operator::Delete => {
+                let removed = self.delete_range(range);
+                self.copy_register(clipboard::Kind::Delete, clipboard::Type::Range(removed));
+            },
+            Operator::Change => {
+                self.create_edit_state(&range, vec![], vec![]);
+                return INSERT.to_owned()
+            },
+            Operator::Yank => {
+                let to_copy = self.get_buffer(range);
+                self.copy_register(clipboard::Kind::Yank, clipboard::Type::Range(to_copy));
+                self.rb.cursor = range.start;
+            },
+            Operator::IndentBack | Operator::IndentForward => {
+                return self.operate_lines(op, range.start.row, range.end.row)
+            },
+        }
         INIT.to_owned()
     }
-    fn eff_delete_word(&mut self, _: Key) -> String {
-        for range in self.word_range() {
-            let removed = self.delete_range(range);
-            clipboard::copy(clipboard::Type::Range(removed));
+    /// Operates on the lines from the top to the bottom.
+    fn operate_lines(&mut self, op: Operator, top: usize, bottom: usize) -> String {
+        let orig = self.rb.cursor;
+        let range = CursorRange {
+            start: Cursor { row: top, col: 0 },
+            end: Cursor { row: bottom, col: self.rb.buf[bottom].len() },
+        };
+        match op {
+            Operator::Delete => {
+                let removed = self.delete_range(range);
+                self.copy_register(clipboard::Kind::Delete, clipboard::Type::Line(removed));
+            },
+            Operator::Change => {
+                // the last eol is kept to type in the line
+                let range = CursorRange {
+                    start: range.start,
+                    end: Cursor { row: bottom, col: self.rb.buf[bottom].len() - 1 },
+                };
+                self.create_edit_state(&range, vec![], vec![]);
+                return INSERT.to_owned()
+            },
+            Operator::Yank => {
+                let to_copy = self.get_buffer(range);
+                self.copy_register(clipboard::Kind::Yank, clipboard::Type::Line(to_copy));
+                self.rb.cursor = if top == orig.row { orig } else { range.start };
+            },
+            Operator::IndentBack => {
+                self.indent_rows(top..bottom+1, false);
+                self.rb.cursor = orig;
+            },
+            Operator::IndentForward => {
+                self.indent_rows(top..bottom+1, true);
+                self.rb.cursor = orig;
+            },
         }
         INIT.to_owned()
     }
-    fn eff_cancel_will_mode(&mut self, _: Key) -> String {
+    fn copy_register(&mut self, kind: clipboard::Kind, x: clipboard::Type) {
+        if let Err(e) = clipboard::copy(self.register.take(), kind, x) {
+            self.message_box.send(&format!("Failed to copy to the clipboard: {}", e));
+        }
+    }
+    fn paste_register(&mut self) -> Option<clipboard::Type> {
+        match clipboard::paste(self.register.take()) {
+            Ok(Some(x)) => Some(x),
+            Ok(None) => {
+                self.message_box.send("yank not found");
+                None
+            },
+            Err(e) => {
+                self.message_box.send(&format!("Failed to paste from the clipboard: {}", e));
+                None
+            },
+        }
+    }
+    fn eff_enter_register(&mut self, _: Key) -> String {
+        REGISTER.to_owned()
+    }
+    fn eff_select_register(&mut self, k: Key) -> String {
+        match k {
+            Key::Char(c) if clipboard::is_register(c) => self.register = Some(c),
+            _ => self.register = None,
+        }
         INIT.to_owned()
     }
     fn eff_paste_system(&mut self, _ :Key) -> String {
-        let pasted = clipboard::paste_system();
-        if pasted.is_none() {
+        let pasted = match clipboard::paste_system() {
+            Ok(x) => x,
+            Err(e) => {
+                self.message_box.send(&format!("Failed to paste from the clipboard: {}", e));
+                return INIT.to_owned();
+            },
+        };
+        if pasted.is_empty() {
             self.message_box.send("nothing to paste");
             return INIT.to_owned();
         }
-        let pasted = pasted.unwrap();
 
         let mut log = ChangeLog::new(self.rb.cursor, vec![], pasted);
-        self.change_log_buffer.push(log.clone());
+        self.push_change_log(log.clone());
         self.apply_log(&mut log);
 
         INIT.to_owned()
     }
     fn eff_paste(&mut self, _: Key) -> String {
-        let pasted = clipboard::paste();
+        let pasted = self.paste_register();
         if pasted.is_none() {
-            self.message_box.send("yank not found");
             return INIT.to_owned();
         }
 
@@ -678,7 +1460,7 @@ impl EditBuffer {
             clipboard::Type::Range(v) => {
                 self.rb.cursor_right();
                 let mut log = ChangeLog::new(self.rb.cursor, vec![], v);
-                self.change_log_buffer.push(log.clone());
+                self.push_change_log(log.clone());
                 self.apply_log(&mut log);
 
                 INIT.to_owned()
@@ -712,7 +1494,7 @@ impl EditBuffer {
         }
     }
     fn eff_paste_above(&mut self, _: Key) -> String {
-        let pasted = clipboard::paste();
+        let pasted = self.paste_register();
         if pasted.is_none() {
             return INIT.to_owned();
         }
@@ -770,41 +1552,31 @@ impl EditBuffer {
     // tmp:
     // to get the yank region and restore the editor state
     // we do this trick of delete and then undo.
+    // the delete is discarded so it doesn't remain in the history.
     fn get_buffer(&mut self, range: CursorRange) -> Vec<BufElem> {
+        let before = self.change_log_buffer.current();
         self.delete_range(range);
-        let to_copy = self.change_log_buffer.peek().cloned().unwrap().deleted;
-        self.undo();
+        if self.change_log_buffer.current() == before {
+            return vec![]
+        }
+        let log = self.change_log_buffer.discard().unwrap();
+        let to_copy = log.deleted.clone();
+        self.undo_log(log);
         to_copy
     }
-    fn eff_yank_range(&mut self, _: Key) -> String {
+    fn eff_yank_range(&mut self, k: Key) -> String {
         let orig_cursor = self.rb.cursor;
         let vr = self.visual_range();
         if vr.is_none() {
-            return WILL_YANK.to_owned();
+            return self.eff_enter_operator(k);
         }
 
         let to_copy = self.get_buffer(vr.unwrap());
-        clipboard::copy(clipboard::Type::Range(to_copy));
+        self.copy_register(clipboard::Kind::Yank, clipboard::Type::Range(to_copy));
         self.rb.cursor = orig_cursor;
 
         INIT.to_owned()
     }
-    fn eff_yank_line(&mut self, _: Key) -> String {
-        let range = CursorRange {
-            start: Cursor {
-                row: self.rb.cursor.row,
-                col: 0,
-            },
-            end: Cursor {
-                row: self.rb.cursor.row,
-                col: self.rb.buf[self.rb.cursor.row].len(),
-            },
-        };
-        let to_copy = self.get_buffer(range);
-        clipboard::copy(clipboard::Type::Line(to_copy));
-
-        INIT.to_owned()
-    }
     fn indent_back_line(&mut self, row: usize, indent: &[BufElem]) {
         let mut cnt = 0;
         for i in 0..indent.len() {
@@ -823,15 +1595,13 @@ impl EditBuffer {
             self.indent_back_line(row, &indent::into_bufelems(self.config.indent_type));
         }
     }
-    pub fn eff_indent_back(&mut self, _: Key) -> String {
+    pub fn eff_indent_back(&mut self, k: Key) -> String {
         if self.visual_range().is_none() {
-            self.indent_back_range(self.rb.cursor.row..self.rb.cursor.row + 1);
-            return INIT.to_owned();
+            return self.eff_enter_operator(k);
         }
         let vr = self.visual_range().unwrap();
-        self.indent_back_range(vr.start.row..vr.end.row + 1);
+        self.indent_rows(vr.start.row..vr.end.row + 1, false);
         self.visual_cursor = None;
-        // TODO atomic change log
 
         INIT.to_owned()
     }
@@ -844,15 +1614,29 @@ impl EditBuffer {
         self.create_edit_state(&delete_range, v, vec![]);
         self.commit_edit_state();
     }
-    fn eff_indent_forward(&mut self, _: Key) -> String {
+    /// Indents the rows as a single change so they are undone at once.
+    fn indent_rows(&mut self, row_range: std::ops::Range<usize>, forward: bool) {
+        let since = self.change_log_buffer.current();
+        let orig_buf = self.rb.buf.clone();
+        if forward {
+            for row in row_range {
+                self.indent_forward(row);
+            }
+        } else {
+            self.indent_back_range(row_range);
+        }
+        if self.change_log_buffer.current() != since {
+            if let Some(log) = change_log::diff_rows(&orig_buf, &self.rb.buf) {
+                self.change_log_buffer.squash(since, log);
+            }
+        }
+    }
+    fn eff_indent_forward(&mut self, k: Key) -> String {
         if self.visual_range().is_none() {
-            self.indent_forward(self.rb.cursor.row);
-            return INIT.to_owned()
+            return self.eff_enter_operator(k);
         }
         let vr = self.visual_range().unwrap();
-        for row in vr.start.row .. vr.end.row+1 {
-            self.indent_forward(row);
-        }
+        self.indent_rows(vr.start.row..vr.end.row + 1, true);
         INIT.to_owned()
     }
     pub fn eff_enter_visual_mode(&mut self, _: Key) -> String {
@@ -861,6 +1645,7 @@ impl EditBuffer {
     }
     pub fn eff_reset(&mut self, _: Key) -> String {
         self.visual_cursor = None;
+        self.register = None;
         self.rb.reset();
         INIT.to_owned()
     }
@@ -951,11 +1736,15 @@ use crate::Key;
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use crate::controller::{PageState, Effect};
+use crate::controller::{Controller, PageState, Effect};
 use crate::def_effect;
 
 def_effect!(Undo, EditBuffer, eff_undo);
 def_effect!(Redo, EditBuffer, eff_redo);
+def_effect!(Earlier, EditBuffer, eff_earlier);
+def_effect!(Later, EditBuffer, eff_later);
+def_effect!(EarlierMinutes, EditBuffer, eff_earlier_minutes);
+def_effect!(LaterMinutes, EditBuffer, eff_later_minutes);
 def_effect!(JoinNextLine, EditBuffer, eff_join_next_line);
 def_effect!(EnterInsertNewline, EditBuffer, eff_enter_insert_newline);
 def_effect!(EnterInsertNewlineAbove, EditBuffer, eff_enter_insert_newline_above);
@@ -964,20 +1753,24 @@ def_effect!(EnterInsertModeLineLast, EditBuffer, eff_enter_insert_mode_line_last
 def_effect!(EnterAppendMode, EditBuffer, eff_enter_append_mode);
 def_effect!(ChangeLineTail, EditBuffer, eff_change_line_tail);
 def_effect!(ChangeRange, EditBuffer, eff_change_range);
-def_effect!(ChangeWord, EditBuffer, eff_change_word);
 def_effect!(EditModeInput, EditBuffer, eff_edit_mode_input);
 def_effect!(LeaveEditMode, EditBuffer, eff_leave_edit_mode);
 def_effect!(DeleteLineTail, EditBuffer, eff_delete_line_tail);
-def_effect!(DeleteLine, EditBuffer, eff_delete_line);
 def_effect!(DeleteRange, EditBuffer, eff_delete_range);
-def_effect!(DeleteWord, EditBuffer, eff_delete_word);
 def_effect!(DeleteChar, EditBuffer, eff_delete_char);
-def_effect!(CancelWillMode, EditBuffer, eff_cancel_will_mode);
+def_effect!(EnterOperator, EditBuffer, eff_enter_operator);
+def_effect!(AccOperatorCount, EditBuffer, eff_acc_operator_count);
+def_effect!(CancelOperator, EditBuffer, eff_cancel_operator);
+def_effect!(OperatorMotion, EditBuffer, eff_operator_motion);
+def_effect!(OperatorLine, EditBuffer, eff_operator_line);
+def_effect!(EnterRegister, EditBuffer, eff_enter_register);
+def_effect!(SelectRegister, EditBuffer, eff_select_register);
+def_effect!(EnterOperatorObject, EditBuffer, eff_enter_operator_object);
+def_effect!(OperatorObject, EditBuffer, eff_operator_object);
 def_effect!(Paste, EditBuffer, eff_paste);
 def_effect!(PasteAbove, EditBuffer, eff_paste_above);
 def_effect!(PasteSystem, EditBuffer, eff_paste_system);
 def_effect!(YankRange, EditBuffer, eff_yank_range);
-def_effect!(YankLine, EditBuffer, eff_yank_line);
 def_effect!(IndentBack, EditBuffer, eff_indent_back);
 def_effect!(IndentForward, EditBuffer, eff_indent_forward);
 def_effect!(EnterVisualMode, EditBuffer, eff_enter_visual_mode);
@@ -990,6 +1783,14 @@ def_effect!(CommitReplaceOnce, EditBuffer, eff_commit_replace_once);
 def_effect!(EnterCommandMode, EditBuffer, eff_enter_command_mode);
 def_effect!(CancelCommandMode, EditBuffer, eff_cancel_command_mode);
 def_effect!(ExecuteCommand, EditBuffer, eff_execute_command);
+def_effect!(EnterCommandLine, EditBuffer, eff_enter_command_line);
+def_effect!(CancelCommandLine, EditBuffer, eff_cancel_command_line);
+def_effect!(CommandLineInput, EditBuffer, eff_command_line_input);
+def_effect!(ReplaceWithInput, EditBuffer, eff_replace_with_input);
+def_effect!(CancelReplace, EditBuffer, eff_cancel_replace);
+def_effect!(ReplaceConfirm, EditBuffer, eff_replace_confirm);
+def_effect!(ReloadConfirm, EditBuffer, eff_reload_confirm);
+def_effect!(RecoverConfirm, EditBuffer, eff_recover_confirm);
 
 def_effect!(EnterSnippetMode, EditBuffer, eff_enter_snippet_mode);
 def_effect!(InsertSnippet, EditBuffer, eff_insert_snippet);
@@ -1030,15 +1831,24 @@ pub fn mk_controller(x: Rc<RefCell<EditBuffer>>) -> controller::ControllerFSM {
     g.add_edge(REPLACE_ONCE, Esc, Rc::new(CancelReplaceOnceMode(x.clone())));
     g.add_edge(REPLACE_ONCE, Otherwise, Rc::new(CommitReplaceOnce(x.clone())));
     
-    g.add_edge(WILL_YANK, Char('y'), Rc::new(YankLine(x.clone())));
-    g.add_edge(WILL_YANK, Esc, Rc::new(CancelWillMode(x.clone())));
-
-    g.add_edge(WILL_DELETE, Char('d'), Rc::new(DeleteLine(x.clone())));
-    g.add_edge(WILL_DELETE, Char('w'), Rc::new(DeleteWord(x.clone())));
-    g.add_edge(WILL_DELETE, Esc, Rc::new(CancelWillMode(x.clone())));
+    // operator x motion or text object (3dd, d2w, y$, >G, ci")
+    for c in &['d', 'c', 'y', '<', '>'] {
+        g.add_edge(JUMP, Char(*c), Rc::new(EnterOperator(x.clone())));
+        g.add_edge(OPERATOR, Char(*c), Rc::new(OperatorLine(x.clone())));
+    }
+    g.add_edge(OPERATOR, CharRange('1', '9'), Rc::new(AccOperatorCount(x.clone())));
+    for (c, _) in MOTIONS.iter() {
+        g.add_edge(OPERATOR, Char(*c), Rc::new(OperatorMotion(x.clone())));
+    }
+    g.add_edge(OPERATOR, Char('i'), Rc::new(EnterOperatorObject(x.clone())));
+    g.add_edge(OPERATOR, Char('a'), Rc::new(EnterOperatorObject(x.clone())));
+    g.add_edge(OPERATOR, Esc, Rc::new(CancelOperator(x.clone())));
+    g.add_edge(OPERATOR_OBJECT, Esc, Rc::new(CancelOperator(x.clone())));
+    g.add_edge(OPERATOR_OBJECT, Otherwise, Rc::new(OperatorObject(x.clone())));
 
-    g.add_edge(WILL_CHANGE, Char('w'), Rc::new(ChangeWord(x.clone())));
-    g.add_edge(WILL_CHANGE, Esc, Rc::new(CancelWillMode(x.clone())));
+    // "ayy "ap "_dd
+    g.add_edge(INIT, Char('"'), Rc::new(EnterRegister(x.clone())));
+    g.add_edge(REGISTER, Otherwise, Rc::new(SelectRegister(x.clone())));
 
     g.add_edge(INSERT, Ctrl('s'), Rc::new(EnterSnippetMode(x.clone())));
     g.add_edge(INSERT, Esc, Rc::new(LeaveEditMode(x.clone())));
@@ -1046,6 +1856,11 @@ pub fn mk_controller(x: Rc<RefCell<EditBuffer>>) -> controller::ControllerFSM {
 
     g.add_edge(INIT, Ctrl('r'), Rc::new(Redo(x.clone())));
     g.add_edge(INIT, Char('u'), Rc::new(Undo(x.clone())));
+    // move through the undo tree in time
+    g.add_edge(INIT, Char('-'), Rc::new(Earlier(x.clone())));
+    g.add_edge(INIT, Char('+'), Rc::new(Later(x.clone())));
+    g.add_edge(JUMP, Char('-'), Rc::new(EarlierMinutes(x.clone())));
+    g.add_edge(JUMP, Char('+'), Rc::new(LaterMinutes(x.clone())));
 
     // completion
     g.add_edge(SNIPPET, Char('k'), Rc::new(CursorUpSnippetMode(x.clone())));
@@ -1055,8 +1870,22 @@ pub fn mk_controller(x: Rc<RefCell<EditBuffer>>) -> controller::ControllerFSM {
 
     g.add_edge(INIT, Char(' '), Rc::new(EnterCommandMode(x.clone())));
     g.add_edge(COMMAND, Esc, Rc::new(CancelCommandMode(x.clone())));
+    g.add_edge(COMMAND, Char(':'), Rc::new(EnterCommandLine(x.clone())));
     g.add_edge(COMMAND, Otherwise, Rc::new(ExecuteCommand(x.clone())));
 
+    g.add_edge(INIT, Char(':'), Rc::new(EnterCommandLine(x.clone())));
+    g.add_edge(COMMAND_LINE, Esc, Rc::new(CancelCommandLine(x.clone())));
+    g.add_edge(COMMAND_LINE, Otherwise, Rc::new(CommandLineInput(x.clone())));
+
+    // replace the search hits (Space r)
+    g.add_edge(REPLACE_WITH, Esc, Rc::new(CancelReplace(x.clone())));
+    g.add_edge(REPLACE_WITH, Otherwise, Rc::new(ReplaceWithInput(x.clone())));
+    g.add_edge(REPLACE_CONFIRM, Otherwise, Rc::new(ReplaceConfirm(x.clone())));
+
+    g.add_edge(RELOAD_CONFIRM, Otherwise, Rc::new(ReloadConfirm(x.clone())));
+    g.add_edge(RECOVER_CONFIRM, Otherwise, Rc::new(RecoverConfirm(x.clone())));
+
+    crate::keymap::apply(&mut g, navigator::PageKind::Buffer);
     controller::ControllerFSM::new(INIT, Box::new(g))
 }
 
@@ -1122,12 +1951,23 @@ fn gen_impl(buf_ref: &mut EditBuffer, region: view::Area) -> Box<view::View> {
     buf_ref.rb.stabilize_cursor();
     buf_ref.rb.adjust_window(buf_reg.width, buf_reg.height);
     buf_ref.update_cache();
+    buf_ref.update_lsp_message();
+    buf_ref.update_git_markers();
 
     let lineno_range = buf_ref.rb.lineno_range();
     let lineno_view = view::LineNumber {
         from: lineno_range.start + 1,
         to: lineno_range.end,
     };
+    let diagnostics = buf_ref.lsp.as_ref().map(|doc| doc.diagnostics()).unwrap_or_default();
+    let lineno_view = view::OverlayView::new(
+        lineno_view,
+        lsp::GutterDiffView::new(&diagnostics, lineno_range.start),
+    );
+    let lineno_view = view::OverlayView::new(
+        lineno_view,
+        git::GutterDiffView::new(buf_ref.git.as_ref(), lineno_range.start),
+    );
     let lineno_view =
         view::TranslateView::new(lineno_view, lineno_reg.col as i32, lineno_reg.row as i32);
 
@@ -1210,13 +2050,20 @@ pub struct Page {
     controller: Box<controller::Controller>,
     view_gen: Box<view::ViewGen>,
     x: Rc<RefCell<EditBuffer>>,
+    // the buffer may push a page while it is borrowed. see directory::Page
+    path: path::PathBuf,
 }
 impl Page {
     pub fn new(x: Rc<RefCell<EditBuffer>>) -> Self {
+        let path = x.borrow().path.clone();
+        let controller = DotRepeat::new(mk_controller(x.clone()), x.clone());
+        // asks first if there are the changes to recover
+        controller.set_state(&x.borrow().state.get());
         Self {
-            controller: Box::new(mk_controller(x.clone())),
+            controller: Box::new(controller),
             view_gen: Box::new(EbViewGen::new(x.clone())),
             x: x,
+            path: path,
         }
     }
 }
@@ -1232,11 +2079,13 @@ impl navigator::Page for Page {
             read_buffer::INIT => "*",
             read_buffer::SEARCH => "/",
             COMMAND => ":",
+            COMMAND_LINE => ":",
+            REPLACE_WITH | REPLACE_CONFIRM => "R",
+            RELOAD_CONFIRM | RECOVER_CONFIRM => "?",
             REPLACE_ONCE => "r",
             WARP => "w",
-            WILL_DELETE => "d",
-            WILL_CHANGE => "c",
-            WILL_YANK => "y",
+            REGISTER => "\"",
+            OPERATOR | OPERATOR_OBJECT => self.x.borrow().pending_operator.map(|(op, _)| op.name()).unwrap_or("*"),
             INSERT => "i",
             SNIPPET => "s",
             _ => "*",
@@ -1247,15 +2096,40 @@ impl navigator::Page for Page {
             ""
         };
         let path = self.x.borrow().path.to_str().unwrap().to_owned();
-        format!("[Buffer -{}-] {}{}", state, dirty_mark, path)
+        let format = self.x.borrow().format.status();
+        let search = self.x.borrow().rb.search_status();
+        format!("[Buffer -{}-] {}{} [{}]{}", state, dirty_mark, path, format, search)
     }
     fn kind(&self) -> navigator::PageKind {
         navigator::PageKind::Buffer
     }
     fn id(&self) -> String {
-        self.x.borrow().path.to_str().unwrap().to_owned()
+        self.path.to_str().unwrap().to_owned()
     }
     fn message(&self) -> MessageBox {
         self.x.borrow().message_box.clone()
     }
+    fn session(&self) -> Option<crate::session::PageSession> {
+        Some(crate::session::PageSession::new(navigator::PageKind::Buffer, &self.path, &self.x.borrow().rb))
+    }
+    fn watch_path(&self) -> Option<path::PathBuf> {
+        fs::canonicalize(&self.path).ok()
+    }
+    fn dirty(&self) -> bool {
+        self.x.borrow().is_dirty()
+    }
+    fn quit(&self) {
+        let mut x = self.x.borrow_mut();
+        // the unsaved changes are left to recover
+        if x.is_dirty() {
+            let _ = x.swap.write();
+        } else {
+            x.swap.remove();
+        }
+    }
+    fn changed_on_disk(&self) {
+        let state = self.x.borrow_mut().check_disk();
+        self.x.borrow().state.set(state.clone());
+        self.controller.set_state(&state);
+    }
 }
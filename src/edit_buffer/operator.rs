@@ -0,0 +1,40 @@
+// d{motion}, c{motion}, y{motion}, <{motion} and >{motion}.
+// the doubled key (dd, cc, yy, << and >>) operates on the lines.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    IndentBack,
+    IndentForward,
+}
+impl Operator {
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            'd' => Some(Operator::Delete),
+            'c' => Some(Operator::Change),
+            'y' => Some(Operator::Yank),
+            '<' => Some(Operator::IndentBack),
+            '>' => Some(Operator::IndentForward),
+            _ => None,
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Operator::Delete => "d",
+            Operator::Change => "c",
+            Operator::Yank => "y",
+            Operator::IndentBack => "<",
+            Operator::IndentForward => ">",
+        }
+    }
+}
+
+/// The count of the operator times the count of the motion (2d3w deletes 6 words).
+pub fn mul_count(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (None, None) => None,
+        (a, b) => Some(a.unwrap_or(1) * b.unwrap_or(1)),
+    }
+}
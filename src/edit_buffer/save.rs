@@ -0,0 +1,91 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// Saving writes a temporary file next to the file and renames it into place
+// so a crash or a full disk never leaves the file half written.
+
+/// foo.rs~
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push("~");
+    PathBuf::from(s)
+}
+
+// .foo.rs.ijk-<pid> in the same directory to rename in the same filesystem
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|x| x.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!(".{}.ijk-{}", name, std::process::id()))
+}
+
+/// Writes the file by the function through a temporary file.
+/// The permissions of the existing file are kept and the old one is kept as the backup if asked.
+pub fn write_atomic<F>(path: &Path, backup: bool, f: F) -> io::Result<()>
+where F: FnOnce(&mut dyn Write) -> io::Result<()> {
+    // writes through the symlink
+    let path = match fs::canonicalize(path) {
+        Ok(x) => x,
+        Err(_) => path.to_owned(),
+    };
+    let old = fs::metadata(&path).ok();
+    let tmp = temp_path(&path);
+    let res = (|| {
+        let file = fs::OpenOptions::new().write(true).create_new(true).open(&tmp)?;
+        let mut out = BufWriter::new(file);
+        f(&mut out)?;
+        let file = out.into_inner().map_err(|e| e.into_error())?;
+        if let Some(meta) = old.as_ref() {
+            file.set_permissions(meta.permissions())?;
+        }
+        file.sync_all()?;
+        if backup && old.is_some() {
+            let bak = backup_path(&path);
+            let _ = fs::remove_file(&bak);
+            // the link keeps the old content after the rename
+            if fs::hard_link(&path, &bak).is_err() {
+                fs::copy(&path, &bak)?;
+            }
+        }
+        fs::rename(&tmp, &path)
+    })();
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp);
+        return res
+    }
+    // the rename is durable after the directory is synced
+    if let Some(dir) = path.parent().and_then(|x| fs::File::open(x).ok()) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.sh");
+        fs::write(&path, "old\n").unwrap();
+        #[cfg(unix)]
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        write_atomic(&path, true, |out| out.write_all(b"new\n")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        assert_eq!(fs::read_to_string(backup_path(&path)).unwrap(), "old\n");
+        #[cfg(unix)]
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o755);
+
+        // the file is untouched on error
+        let res = write_atomic(&path, false, |out| {
+            out.write_all(b"broken")?;
+            Err(io::Error::other("failed"))
+        });
+        assert!(res.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,268 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use super::change_log::ChangeLog;
+
+// The changes since the last save are appended to ~/.ijk/swap/<path>.swp
+// so the unsaved edits survive a crash. The first line tells which text the changes apply to.
+//
+// The changes are written at most every SWAP_INTERVAL by a timer thread
+// so the last edits before an idle crash are kept too. The file is removed
+// when the buffer gets clean or is closed. Quitting the editor leaves it if the buffer is dirty.
+
+const SWAP_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    fingerprint: u64, // of the text saved
+    pid: u32,
+}
+
+/// ~/.ijk/swap/%home%akira%src%main.rs.swp
+pub fn swap_file_path(path: &Path) -> PathBuf {
+    let abs = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let name = abs.to_str().unwrap().replace('/', "%");
+    crate::config::ijk_dir().join("swap").join(format!("{}.swp", name))
+}
+
+pub struct Swap {
+    file: Arc<Mutex<SwapFile>>,
+    disabled: bool,
+}
+// shared with the timer thread
+struct SwapFile {
+    path: PathBuf,
+    fingerprint: u64,
+    pending: Vec<ChangeLog>,
+    created: bool,
+    last_write: Instant,
+    interval: Duration,
+    scheduled: bool, // the timer is running
+    error: Option<String>, // of the write by the timer
+}
+impl SwapFile {
+    fn write(&mut self) -> io::Result<()> {
+        self.last_write = Instant::now();
+        let mut s = String::new();
+        if !self.created {
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let header = Header { fingerprint: self.fingerprint, pid: std::process::id() };
+            s.push_str(&serde_json::to_string(&header)?);
+            s.push('\n');
+        }
+        for log in &self.pending {
+            s.push_str(&serde_json::to_string(log)?);
+            s.push('\n');
+        }
+        let mut file = if self.created {
+            OpenOptions::new().append(true).open(&self.path)?
+        } else {
+            fs::File::create(&self.path)?
+        };
+        file.write_all(s.as_bytes())?;
+        self.created = true;
+        self.pending.clear();
+        Ok(())
+    }
+    fn remove(&mut self) {
+        self.pending.clear();
+        if self.created {
+            let _ = fs::remove_file(&self.path);
+            self.created = false;
+        }
+    }
+}
+impl Swap {
+    pub fn new(path: PathBuf, fingerprint: u64) -> Self {
+        let file = SwapFile {
+            path,
+            fingerprint,
+            pending: vec![],
+            created: false,
+            last_write: Instant::now(),
+            interval: SWAP_INTERVAL,
+            scheduled: false,
+            error: None,
+        };
+        Self {
+            file: Arc::new(Mutex::new(file)),
+            disabled: false,
+        }
+    }
+    /// Stops writing e.g. the swap file is of another process.
+    pub fn disable(&mut self) {
+        self.disabled = true;
+    }
+    /// The change is written when the interval passes even if the editor is idle.
+    pub fn record(&mut self, log: &ChangeLog) {
+        if self.disabled {
+            return
+        }
+        let mut f = self.file.lock().unwrap();
+        f.pending.push(log.clone());
+        if f.scheduled {
+            return
+        }
+        f.scheduled = true;
+        let wait = f.interval.saturating_sub(f.last_write.elapsed());
+        let file = self.file.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(wait);
+            let mut f = file.lock().unwrap();
+            f.scheduled = false;
+            // may be written or removed in the meantime
+            if f.pending.is_empty() {
+                return
+            }
+            if let Err(e) = f.write() {
+                f.error = Some(e.to_string());
+            }
+        });
+    }
+    /// Writes the pending changes if the interval passed.
+    /// Returns the error of the write by the timer too.
+    pub fn tick(&mut self) -> Result<(), String> {
+        let mut f = self.file.lock().unwrap();
+        if let Some(e) = f.error.take() {
+            return Err(e)
+        }
+        if f.pending.is_empty() || f.last_write.elapsed() < f.interval {
+            return Ok(())
+        }
+        f.write().map_err(|e| e.to_string())
+    }
+    pub fn write(&mut self) -> io::Result<()> {
+        if self.disabled {
+            return Ok(())
+        }
+        self.file.lock().unwrap().write()
+    }
+    /// Starts over from the text saved.
+    pub fn reset(&mut self, fingerprint: u64) {
+        let mut f = self.file.lock().unwrap();
+        f.remove();
+        f.fingerprint = fingerprint;
+    }
+    /// Removes the file if it's written by this.
+    pub fn remove(&mut self) {
+        self.file.lock().unwrap().remove();
+    }
+}
+
+pub enum Found {
+    None,
+    /// The changes to recover.
+    Changes(Vec<ChangeLog>),
+    /// The file changed since the swap file was written.
+    Mismatch,
+    /// The swap file of another process still running.
+    InUse(u32),
+}
+
+#[cfg(unix)]
+fn is_alive(pid: u32) -> bool {
+    // the signal 0 only checks the process exists
+    pid != 0 && (unsafe { libc::kill(pid as libc::pid_t, 0) } == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM))
+}
+
+#[cfg(not(unix))]
+fn is_alive(_: u32) -> bool {
+    false
+}
+
+/// Looks for the swap file left newer than the file.
+/// The swap file of this process (e.g. the same file opened twice) isn't counted.
+pub fn find(swap_path: &Path, path: &Path, fingerprint: u64) -> Found {
+    let swap_time = match fs::metadata(swap_path).and_then(|x| x.modified()) {
+        Ok(x) => x,
+        Err(_) => return Found::None,
+    };
+    if let Ok(t) = fs::metadata(path).and_then(|x| x.modified()) {
+        if t > swap_time {
+            return Found::None
+        }
+    }
+    let s = match fs::read_to_string(swap_path) {
+        Ok(x) => x,
+        Err(_) => return Found::None,
+    };
+    let mut lines = s.lines();
+    let header: Header = match lines.next().and_then(|x| serde_json::from_str(x).ok()) {
+        Some(x) => x,
+        None => return Found::None,
+    };
+    if header.pid == std::process::id() {
+        return Found::None
+    }
+    if is_alive(header.pid) {
+        return Found::InUse(header.pid)
+    }
+    if header.fingerprint != fingerprint {
+        return Found::Mismatch
+    }
+    // the last line may be broken by the crash
+    let v: Vec<ChangeLog> = lines.map_while(|x| serde_json::from_str(x).ok()).collect();
+    if v.is_empty() {
+        return Found::None
+    }
+    Found::Changes(v)
+}
+
+#[test]
+fn test_swap() {
+    use crate::read_buffer::{BufElem, Cursor};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.txt");
+    fs::write(&path, "a\n").unwrap();
+    let swap_path = dir.path().join("a.swp");
+
+    let mut swap = Swap::new(swap_path.clone(), 1);
+    swap.record(&ChangeLog::new(Cursor { row: 0, col: 0 }, vec![], vec![BufElem::Char('x')]));
+    swap.tick().unwrap();
+    assert!(!swap_path.exists());
+    swap.write().unwrap();
+    swap.record(&ChangeLog::new(Cursor { row: 0, col: 0 }, vec![BufElem::Char('x')], vec![]));
+    swap.write().unwrap();
+    // written by this process
+    assert!(matches!(find(&swap_path, &path, 1), Found::None));
+
+    // as if left by another process
+    let s = fs::read_to_string(&swap_path).unwrap();
+    let s = s.replacen(&format!("\"pid\":{}", std::process::id()), "\"pid\":0", 1);
+    fs::write(&swap_path, s + "{broken").unwrap();
+    match find(&swap_path, &path, 1) {
+        Found::Changes(v) => assert_eq!(v.len(), 2),
+        _ => panic!(),
+    }
+    assert!(matches!(find(&swap_path, &path, 2), Found::Mismatch));
+
+    // another ijk is editing the file
+    let s = fs::read_to_string(&swap_path).unwrap();
+    let s = s.replacen("\"pid\":0", &format!("\"pid\":{}", std::os::unix::process::parent_id()), 1);
+    fs::write(&swap_path, s).unwrap();
+    assert!(matches!(find(&swap_path, &path, 1), Found::InUse(_)));
+
+    swap.remove();
+    assert!(!swap_path.exists());
+}
+
+#[test]
+fn test_swap_timer() {
+    use crate::read_buffer::{BufElem, Cursor};
+
+    let dir = tempfile::tempdir().unwrap();
+    let swap_path = dir.path().join("a.swp");
+    let mut swap = Swap::new(swap_path.clone(), 1);
+    swap.file.lock().unwrap().interval = Duration::from_millis(100);
+    swap.record(&ChangeLog::new(Cursor { row: 0, col: 0 }, vec![], vec![BufElem::Char('x')]));
+    assert!(!swap_path.exists());
+    // written without tick
+    std::thread::sleep(Duration::from_millis(500));
+    assert_eq!(fs::read_to_string(&swap_path).unwrap().lines().count(), 2);
+    swap.remove();
+}
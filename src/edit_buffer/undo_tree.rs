@@ -0,0 +1,301 @@
+use std::collections::BTreeMap;
+
+// A history that keeps every branch.
+//
+// save 1,2,3
+// undo 3,2
+// save 4
+//
+// 0 - 1 - 2 - 3
+//      \
+//       4 (current)
+//
+// Undo and redo move along the current branch while
+// earlier/later move through the states in the order they are created.
+
+pub type NodeId = usize;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Node<T> {
+    x: Option<T>, // None for the root
+    parent: NodeId,
+    children: Vec<NodeId>,
+    redo: Option<NodeId>, // the child to redo. the latest child if None
+}
+
+pub enum Step<T> {
+    Undo(T),
+    Redo(T),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UndoTree<T> {
+    #[serde(skip)]
+    capacity: Option<usize>,
+    // node ids increase as the nodes are created so the order is chronological
+    nodes: BTreeMap<NodeId, Node<T>>,
+    root: NodeId,
+    current: NodeId,
+    next_node_id: NodeId,
+}
+
+impl <T: Clone> UndoTree<T> {
+    /// The number of nodes is unlimited if the capacity is None.
+    pub fn new(capacity: Option<usize>) -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(0, Node { x: None, parent: 0, children: vec![], redo: None });
+        Self {
+            capacity,
+            nodes,
+            root: 0,
+            current: 0,
+            next_node_id: 1,
+        }
+    }
+    pub fn set_capacity(&mut self, capacity: Option<usize>) {
+        self.capacity = capacity;
+        self.prune();
+    }
+    fn node(&self, i: NodeId) -> &Node<T> {
+        self.nodes.get(&i).unwrap()
+    }
+    fn node_mut(&mut self, i: NodeId) -> &mut Node<T> {
+        self.nodes.get_mut(&i).unwrap()
+    }
+    pub fn current(&self) -> NodeId {
+        self.current
+    }
+    pub fn len(&self) -> usize {
+        self.nodes.len() - 1
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn peek(&self) -> Option<&T> {
+        self.node(self.current).x.as_ref()
+    }
+    pub fn get(&self, i: NodeId) -> Option<&T> {
+        self.nodes.get(&i).and_then(|n| n.x.as_ref())
+    }
+    /// Iterates the nodes from the oldest.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &T)> {
+        self.nodes.iter().filter_map(|(&i, n)| n.x.as_ref().map(|x| (i, x)))
+    }
+    pub fn push(&mut self, x: T) {
+        let id = self.next_node_id;
+        self.next_node_id += 1;
+        let parent = self.current;
+        self.nodes.insert(id, Node { x: Some(x), parent, children: vec![], redo: None });
+        let p = self.node_mut(parent);
+        p.children.push(id);
+        p.redo = Some(id);
+        self.current = id;
+        self.prune();
+    }
+    pub fn pop_undo(&mut self) -> Option<T> {
+        if self.current == self.root {
+            return None
+        }
+        let cur = self.current;
+        let parent = self.node(cur).parent;
+        self.node_mut(parent).redo = Some(cur);
+        self.current = parent;
+        self.node(cur).x.clone()
+    }
+    pub fn pop_redo(&mut self) -> Option<T> {
+        let node = self.node(self.current);
+        let child = node.redo.or_else(|| node.children.last().cloned())?;
+        self.current = child;
+        self.node(child).x.clone()
+    }
+    /// Undoes the current change and forgets it. The change must be a leaf.
+    pub fn discard(&mut self) -> Option<T> {
+        let cur = self.current;
+        if cur == self.root || !self.node(cur).children.is_empty() {
+            return None
+        }
+        let node = self.nodes.remove(&cur).unwrap();
+        let parent = self.node_mut(node.parent);
+        parent.children.retain(|&i| i != cur);
+        parent.redo = None;
+        self.current = node.parent;
+        node.x
+    }
+    /// Replaces the changes after the ancestor with a single change.
+    pub fn squash(&mut self, since: NodeId, x: T) {
+        let path = self.ancestors(self.current);
+        let top = match path.iter().position(|&i| i == since) {
+            Some(0) | None => return,
+            Some(k) => path[k - 1],
+        };
+        self.remove_subtree(top);
+        let node = self.node_mut(since);
+        node.children.retain(|&i| i != top);
+        node.redo = None;
+        self.current = since;
+        self.push(x);
+    }
+    fn ancestors(&self, i: NodeId) -> Vec<NodeId> {
+        let mut v = vec![i];
+        let mut i = i;
+        while i != self.root {
+            i = self.node(i).parent;
+            v.push(i);
+        }
+        v
+    }
+    /// Moves to the node and returns the changes to get there.
+    pub fn goto(&mut self, target: NodeId) -> Vec<Step<T>> {
+        if !self.nodes.contains_key(&target) {
+            return vec![]
+        }
+        let up = self.ancestors(self.current);
+        let down = self.ancestors(target);
+        let common = *up.iter().find(|i| down.contains(i)).unwrap();
+
+        let mut steps = vec![];
+        for &i in up.iter().take_while(|&&i| i != common) {
+            steps.push(Step::Undo(self.node(i).x.clone().unwrap()));
+        }
+        let down: Vec<NodeId> = down.into_iter().take_while(|&i| i != common).collect();
+        for &i in down.iter().rev() {
+            let parent = self.node(i).parent;
+            self.node_mut(parent).redo = Some(i);
+            steps.push(Step::Redo(self.node(i).x.clone().unwrap()));
+        }
+        self.current = target;
+        steps
+    }
+    /// Goes back n states in time.
+    pub fn earlier(&mut self, n: usize) -> Vec<Step<T>> {
+        let target = self.nodes.range(..self.current).rev().map(|(&i, _)| i).take(n).last();
+        match target {
+            Some(i) => self.goto(i),
+            None => vec![],
+        }
+    }
+    /// Goes forward n states in time.
+    pub fn later(&mut self, n: usize) -> Vec<Step<T>> {
+        let target = self.nodes.range(self.current+1..).map(|(&i, _)| i).take(n).last();
+        match target {
+            Some(i) => self.goto(i),
+            None => vec![],
+        }
+    }
+    /// The latest node that satisfies the predicate or the root.
+    pub fn find_latest<F: Fn(&T) -> bool>(&self, pred: F) -> NodeId {
+        self.iter().filter(|(_, x)| pred(x)).map(|(i, _)| i).last().unwrap_or(self.root)
+    }
+    fn remove_subtree(&mut self, i: NodeId) {
+        let node = self.nodes.remove(&i).unwrap();
+        for c in node.children {
+            self.remove_subtree(c);
+        }
+    }
+    /// Drops the oldest changes beyond the capacity.
+    fn prune(&mut self) {
+        let cap = match self.capacity {
+            Some(cap) => cap,
+            None => return,
+        };
+        while self.len() > cap {
+            let oldest = *self.node(self.root).children.iter().min().unwrap();
+            if self.ancestors(self.current).contains(&oldest) {
+                // the oldest change on the current branch is forgotten.
+                // the state after it becomes the new root.
+                let siblings: Vec<NodeId> = self.node(self.root).children.iter().cloned().filter(|&i| i != oldest).collect();
+                for i in siblings {
+                    self.remove_subtree(i);
+                }
+                self.nodes.remove(&self.root);
+                self.root = oldest;
+                let root = self.node_mut(oldest);
+                root.x = None;
+                root.parent = oldest;
+            } else {
+                self.remove_subtree(oldest);
+                let root = self.root;
+                let root = self.node_mut(root);
+                root.children.retain(|&i| i != oldest);
+                if root.redo == Some(oldest) {
+                    root.redo = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn apply(v: &mut Vec<i32>, steps: Vec<Step<i32>>) {
+    for step in steps {
+        match step {
+            Step::Undo(x) => assert_eq!(v.pop(), Some(x)),
+            Step::Redo(x) => v.push(x),
+        }
+    }
+}
+
+#[test]
+fn test_undo_tree() {
+    let mut t = UndoTree::new(None);
+    t.push(1);
+    t.push(2);
+    t.push(3);
+    assert!(t.pop_redo().is_none());
+    assert_eq!(t.pop_undo(), Some(3));
+    assert_eq!(t.pop_undo(), Some(2));
+    assert_eq!(t.pop_redo(), Some(2));
+    // the branch [1,2,3] is kept
+    assert_eq!(t.pop_undo(), Some(2));
+    t.push(4);
+    assert_eq!(t.len(), 4);
+    t.push(5);
+    assert_eq!(t.discard(), Some(5));
+    assert_eq!(t.len(), 4);
+    t.push(6);
+    t.push(7);
+    t.squash(4, 67);
+    assert_eq!(t.len(), 5);
+    assert_eq!(t.pop_undo(), Some(67));
+    assert_eq!(t.pop_redo(), Some(67));
+    assert_eq!(t.pop_undo(), Some(67));
+    assert_eq!(t.pop_undo(), Some(4));
+    assert_eq!(t.pop_redo(), Some(4));
+
+    // back in time: [1,4] -> [1,2,3] -> [1,2] -> [1]
+    let mut v = vec![1, 4];
+    apply(&mut v, t.earlier(1));
+    assert_eq!(v, vec![1, 2, 3]);
+    apply(&mut v, t.earlier(2));
+    assert_eq!(v, vec![1]);
+    apply(&mut v, t.later(3));
+    assert_eq!(v, vec![1, 4]);
+    apply(&mut v, t.earlier(10));
+    assert!(v.is_empty());
+    assert!(t.pop_undo().is_none());
+    // redo follows the branch last visited
+    assert_eq!(t.pop_redo(), Some(1));
+    assert_eq!(t.pop_redo(), Some(4));
+}
+
+#[test]
+fn test_undo_tree_capacity() {
+    let mut t = UndoTree::new(Some(3));
+    for i in 0..5 {
+        t.push(i);
+    }
+    assert_eq!(t.len(), 3);
+    assert_eq!(t.pop_undo(), Some(4));
+    assert_eq!(t.pop_undo(), Some(3));
+    assert_eq!(t.pop_undo(), Some(2));
+    assert!(t.pop_undo().is_none());
+
+    // the old branch is dropped first
+    t.push(10); // [] -> 2 -> 3 -> 4, [] -> 10
+    assert_eq!(t.len(), 1);
+    assert_eq!(t.pop_undo(), Some(10));
+    assert_eq!(t.pop_redo(), Some(10));
+    let mut v = vec![10];
+    apply(&mut v, t.earlier(1));
+    assert!(v.is_empty());
+}
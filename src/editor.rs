@@ -1,36 +1,48 @@
 extern crate flame;
 
 use crate::message_box;
+use crate::keymap::{self, Global, GlobalMatch};
+use crate::layout::{self, Layout, Split};
+use crate::macros::{self, Macros};
 use crate::navigator::{self, Page};
 use crate::screen::*;
 use crate::view;
 use crate::view::View;
 use crate::Key;
-use crate::read_buffer::Cursor;
+use crate::read_buffer::{Cursor, INIT};
+use crate::watcher::Watcher;
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::collections::{BTreeSet, VecDeque};
+use std::path::PathBuf;
 use std::{thread, time};
 use termion::event::Key as TermKey;
 
 struct StatusView {
     x: Vec<char>,
+    focused: bool,
 }
 impl StatusView {
-    fn new(x: &str) -> Self {
+    fn new(x: &str, focused: bool) -> Self {
         let mut v = vec![];
         for c in x.chars() {
             v.push(c);
         }
-        Self { x: v }
+        Self { x: v, focused }
     }
 }
 impl view::View for StatusView {
     fn get(&self, col: usize, row: usize) -> view::ViewElem {
+        let (fg, bg) = if self.focused {
+            (Color::Black, Color::White)
+        } else {
+            (Color::White, Color::LightBlack)
+        };
         if row == 0 && col < self.x.len() {
             let c = self.x[col];
-            (Some(c), Some(Color::Black), Some(Color::White))
+            (Some(c), Some(fg), Some(bg))
         } else {
-            (Some(' '), Some(Color::Black), Some(Color::White))
+            (Some(' '), Some(fg), Some(bg))
         }
     }
     fn get_cursor_pos(&self) -> Option<Cursor> {
@@ -105,34 +117,107 @@ pub struct Editor<D> {
     navigator: Rc<RefCell<navigator::Navigator>>,
     navi_page: Rc<RefCell<Page>>,
     drawable: D,
+    macros: Macros,
+    // the keys to replay before the next input
+    pending: VecDeque<Key>,
+    replayed: usize,
+    layout: Layout,
+    area: view::Area, // the screen last drawn
+    global_keys: Vec<Key>, // the editor-level sequence typed so far
+    watcher: Option<Watcher>,
+    disk_changes: BTreeSet<PathBuf>, // not told to the pages yet
+}
+
+pub fn to_key(k: TermKey) -> Option<Key> {
+    let x = match k {
+        TermKey::Esc => Key::Esc,
+        TermKey::Ctrl('c') => Key::Esc,
+        TermKey::Backspace => Key::Backspace,
+        TermKey::Ctrl(c) => Key::Ctrl(c),
+        TermKey::Char(c) => Key::Char(c),
+        _ => return None,
+    };
+    Some(x)
 }
 
 impl <D: Drawable> Editor<D> {
     pub fn new(navigator: Rc<RefCell<navigator::Navigator>>, drawable: D) -> Self {
         Self {
             navi_page: Rc::new(RefCell::new(navigator::NavigatorPage::new(navigator.clone()))),
-            navigator: navigator,
+            navigator: navigator.clone(),
             drawable: drawable,
+            macros: Macros::new(crate::config::SINGLETON.macro_persist()),
+            pending: VecDeque::new(),
+            replayed: 0,
+            layout: Layout::new(navigator.borrow().current_page()),
+            area: view::Area { col: 0, row: 0, width: 0, height: 0 },
+            global_keys: vec![],
+            watcher: None,
+            disk_changes: BTreeSet::new(),
+        }
+    }
+    /// Watches the files and directories shown in the pages.
+    /// The wake of the watcher should put a key like Null to the input
+    /// so the changes are handled before the next key typed.
+    pub fn set_watcher(&mut self, watcher: Watcher) {
+        self.watcher = Some(watcher);
+    }
+    // the pages are told in the initial state so a prompt doesn't break the edit
+    fn handle_disk_changes(&mut self) {
+        let watcher = match self.watcher.as_ref() {
+            Some(x) => x,
+            None => return,
+        };
+        let (workspaces, _) = self.navigator.borrow().workspace_pages();
+        let pages: Vec<_> = workspaces.into_iter().flat_map(|(_, v)| v).collect();
+        let paths: Vec<Option<PathBuf>> = pages.iter().map(|p| p.borrow().watch_path()).collect();
+        watcher.set_paths(paths.iter().flatten().cloned().collect());
+        self.disk_changes.extend(watcher.changes());
+
+        let mut rest = BTreeSet::new();
+        for path in std::mem::take(&mut self.disk_changes) {
+            for (page, _) in pages.iter().zip(&paths).filter(|(_, p)| p.as_ref() == Some(&path)) {
+                if page.borrow().controller().state() == INIT {
+                    page.borrow().changed_on_disk();
+                } else {
+                    rest.insert(path.clone());
+                }
+            }
         }
+        self.disk_changes = rest;
     }
     fn view_gen(&self, area: view::Area) -> Box<View> {
         let _flame_guard = flame::start_guard("editor.view_gen");
 
-        let (page_area, common_area) = area.split_vertical(area.height - 2);
-        let (status_area, message_area) = common_area.split_vertical(1);
-        let page = self.navigator.borrow().current_page();
-        let page_view = page.borrow_mut().view_gen().gen(page_area);
-        let view = page_view;
+        let (windows_area, message_area) = area.split_vertical(area.height - 1);
+        let mut views = vec![];
+        let mut cursor = None;
+        for (i, (window_area, page)) in self.layout.windows(windows_area).into_iter().enumerate() {
+            let focused = i == self.layout.focus();
+            let (page_area, status_area) = window_area.split_vertical(window_area.height - 1);
+            let page_view = page.borrow_mut().view_gen().gen(page_area);
 
-        let status_view = StatusView::new(&page.borrow().status());
-        let status_view =
-            view::TranslateView::new(status_view, status_area.col as i32, status_area.row as i32);
-        let view = view::MergeVertical {
-            top: view,
-            bottom: status_view,
-            row_offset: status_area.row,
-        };
+            let mut status = page.borrow().status();
+            if let (true, Some(c)) = (focused, self.macros.recording()) {
+                status.push_str(&format!(" recording @{}", c.to_ascii_lowercase()));
+            }
+            let status_view = StatusView::new(&status, focused);
+            let status_view =
+                view::TranslateView::new(status_view, status_area.col as i32, status_area.row as i32);
+            let view = view::MergeVertical {
+                top: page_view,
+                bottom: status_view,
+                row_offset: status_area.row,
+            };
+            let view = view::CloneView::new(view, window_area);
+            if focused {
+                cursor = view.get_cursor_pos();
+            }
+            views.push((window_area, Box::new(view) as Box<dyn View>));
+        }
+        let view = layout::Tiled { views, cursor };
 
+        let page = self.navigator.borrow().current_page();
         let message = page.borrow().message();
         let message_view = message_box::View::new(&message);
         let message_view = view::TranslateView::new(
@@ -152,42 +237,115 @@ impl <D: Drawable> Editor<D> {
     fn draw<V: View>(&mut self, view: V) {
         self.drawable.draw(view)
     }
+    fn run_global(&mut self, x: Global) {
+        let message = self.navigator.borrow().current_page().borrow().message();
+        let (windows_area, _) = self.area.split_vertical(self.area.height.saturating_sub(1));
+        match x {
+            Global::Quit => {},
+            Global::Navigator => {
+                self.navigator.borrow_mut().set(self.navi_page.clone());
+            },
+            Global::Split | Global::VSplit => {
+                let split = if x == Global::Split { Split::Vertical } else { Split::Horizontal };
+                if !self.layout.split(split, windows_area) {
+                    message.send("No room to split the window");
+                }
+            },
+            Global::FocusNext => {
+                self.layout.focus_next();
+                self.navigator.borrow_mut().set(self.layout.page());
+            },
+            Global::CloseWindow => {
+                if self.layout.close() {
+                    self.navigator.borrow_mut().set(self.layout.page());
+                } else {
+                    message.send("The last window can't be closed");
+                }
+            },
+            Global::Grow => self.layout.resize(10),
+            Global::Shrink => self.layout.resize(-10),
+        }
+    }
     pub fn run<I: Iterator<Item = Result<termion::event::Key, std::io::Error>>>(
         &mut self,
         mut keys: I,
     ) {
         loop {
-            let (w, h) = self.drawable.dimension();
-            let area = view::Area {
-                col: 0,
-                row: 0,
-                width: w,
-                height: h,
-            };
-            let view = self.view_gen(area);
-            self.draw(view);
+            self.handle_disk_changes();
+
+            // the errors in the [keys] config of the pages opened
+            for e in keymap::take_errors() {
+                let page = self.navigator.borrow().current_page();
+                page.borrow().message().send(&e);
+            }
+
+            // the focused window follows the navigator
+            self.layout.set_page(self.navigator.borrow().current_page());
+
+            // no drawing while replaying
+            if self.pending.is_empty() {
+                let (w, h) = self.drawable.dimension();
+                self.area = view::Area {
+                    col: 0,
+                    row: 0,
+                    width: w,
+                    height: h,
+                };
+                let view = self.view_gen(self.area);
+                self.draw(view);
+            }
 
-            match keys.next() {
-                Some(Ok(TermKey::Ctrl('z'))) => break,
-                Some(Ok(TermKey::Ctrl('w'))) => {
-                    self.navigator.borrow_mut().set(self.navi_page.clone());
+            let (kk, replaying) = match self.pending.pop_front() {
+                Some(k) => (k, true),
+                None => {
+                    self.replayed = 0;
+                    match keys.next() {
+                        Some(Ok(k)) => match to_key(k) {
+                            Some(k) => (k, false),
+                            None => continue,
+                        },
+                        _ => continue,
+                    }
                 }
-                other_key => {
-                    let kk = match other_key {
-                        Some(Ok(TermKey::Esc)) => Key::Esc,
-                        Some(Ok(TermKey::Ctrl('c'))) => Key::Esc,
-                        Some(Ok(TermKey::Backspace)) => Key::Backspace,
-                        Some(Ok(TermKey::Ctrl(c))) => Key::Ctrl(c),
-                        Some(Ok(TermKey::Char(c))) => Key::Char(c),
-                        _ => {
-                            continue;
+            };
+            self.global_keys.push(kk.clone());
+            match keymap::global(&self.global_keys) {
+                GlobalMatch::Prefix => continue,
+                GlobalMatch::Action(x) => {
+                    self.global_keys.clear();
+                    if x == Global::Quit {
+                        let (workspaces, _) = self.navigator.borrow().workspace_pages();
+                        for (_, pages) in workspaces {
+                            for page in pages {
+                                page.borrow().quit();
+                            }
                         }
-                    };
+                        break;
+                    }
+                    self.run_global(x);
+                }
+                // the rest of an unknown sequence is dropped
+                GlobalMatch::None if self.global_keys.len() > 1 => {
+                    self.global_keys.clear();
+                }
+                GlobalMatch::None => {
+                    self.global_keys.clear();
                     let page = self.navigator.borrow().current_page();
 
                     flame::start("editor.receive");
-                    page.borrow().controller().receive(kk);
+                    let replay = self.macros.receive(kk, &*page.borrow(), replaying);
                     flame::end("editor.receive");
+
+                    self.replayed += replay.len();
+                    if self.replayed > macros::MAX_REPLAY {
+                        self.pending.clear();
+                        page.borrow().message().send("Stopped the macro. too many keys");
+                        continue;
+                    }
+                    // the keys of the inner macro come first
+                    for k in replay.into_iter().rev() {
+                        self.pending.push_front(k);
+                    }
                 }
             }
         }
@@ -234,8 +392,31 @@ mod tests {
         let mut editor = Editor::new(navigator, NullScreen::new(10,10));
 
         editor.run(keys);
-        let actual: Vec<Vec<BufElem>> = normalize(eb.borrow().rb.buf.clone());
+        // quitting dirty leaves the swap file
+        let _ = std::fs::remove_file(edit_buffer::swap::swap_file_path(&input));
+        let actual: Vec<Vec<BufElem>> = normalize(eb.borrow().rb.buf.to_vec());
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_split_windows() {
+        let navigator = Rc::new(RefCell::new(navigator::Navigator::new()));
+        let eb = Rc::new(RefCell::new(edit_buffer::EditBuffer::open(Path::new("behavior/count_dd/input"), navigator.clone())));
+        let page = Rc::new(RefCell::new(edit_buffer::Page::new(eb.clone())));
+        navigator.borrow_mut().push(page);
+        let mut editor = Editor::new(navigator.clone(), NullScreen::new(60, 20));
+
+        // side by side, the right into top and bottom, then close the bottom
+        let keys = "C-x v C-x o C-x s C-x o C-x c C-x -";
+        let keys: Vec<_> = keys.split(' ').map(|x| match x {
+            "C-x" => Ok(TermKey::Ctrl('x')),
+            c => Ok(TermKey::Char(c.chars().next().unwrap())),
+        }).chain(vec![Ok(TermKey::Ctrl('z'))]).collect();
+        editor.run(keys.into_iter());
+        assert_eq!(editor.layout.count(), 2);
+        assert_eq!(editor.layout.focus(), 1);
+        let v: Vec<_> = editor.layout.windows(editor.area).into_iter().map(|(a, _)| (a.col, a.width)).collect();
+        assert_eq!(v, vec![(0, 35), (36, 24)]);
+    }
 }
@@ -0,0 +1,425 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use crate::edit_buffer::{self, EditBuffer};
+use crate::controller::{self, PageState};
+use crate::view;
+use crate::navigator::{self, Navigator};
+use crate::read_buffer::{self, BufElem, ReadBuffer};
+use crate::screen::Color;
+use crate::message_box::MessageBox;
+use crate::read_buffer::INIT;
+
+const INPUT: &str = "FinderInput";
+
+const MATCH: i32 = 16;
+const SEGMENT_HEAD: i32 = 12; // after '/' or the first char
+const WORD_HEAD: i32 = 8; // after '_', '-', '.' or ' '
+const CAMEL_HEAD: i32 = 8; // the upper case letter after a lower case one
+const CONSECUTIVE: i32 = 6;
+const GAP: i32 = 3;
+
+fn bonus(path: &[char], j: usize) -> i32 {
+    if j == 0 {
+        return SEGMENT_HEAD
+    }
+    let (prev, cur) = (path[j-1], path[j]);
+    if prev == '/' {
+        SEGMENT_HEAD
+    } else if "_-. ".contains(prev) {
+        WORD_HEAD
+    } else if prev.is_lowercase() && cur.is_uppercase() {
+        CAMEL_HEAD
+    } else {
+        0
+    }
+}
+
+fn eq(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// Matches the query against the path as a subsequence ignoring case.
+/// Returns the score and the positions of the matched chars. Higher is better.
+pub fn score(query: &str, path: &str) -> Option<(i32, Vec<usize>)> {
+    let q: Vec<char> = query.chars().collect();
+    let p: Vec<char> = path.chars().collect();
+    let (m, n) = (q.len(), p.len());
+    if m == 0 {
+        return Some((0, vec![]))
+    }
+    // quick check that the query is a subsequence at all
+    let mut k = 0;
+    for &c in &p {
+        if k < m && eq(q[k], c) {
+            k += 1;
+        }
+    }
+    if k < m {
+        return None
+    }
+
+    // dp[i][j]: the best score where q[i] is matched at p[j]
+    const NONE: i32 = i32::MIN / 2;
+    let mut dp = vec![vec![NONE; n]; m];
+    let mut from = vec![vec![0; n]; m];
+    for j in 0..n {
+        if eq(q[0], p[j]) {
+            dp[0][j] = MATCH + bonus(&p, j);
+        }
+    }
+    for i in 1..m {
+        // the best of dp[i-1][..j-1] and where it is
+        let mut best = (NONE, 0);
+        for j in 1..n {
+            if j >= 2 && dp[i-1][j-2] > best.0 {
+                best = (dp[i-1][j-2], j-2);
+            }
+            if !eq(q[i], p[j]) {
+                continue
+            }
+            let base = MATCH + bonus(&p, j);
+            let consecutive = dp[i-1][j-1] + CONSECUTIVE;
+            let jump = best.0 - GAP;
+            if dp[i-1][j-1] > NONE && consecutive >= jump {
+                dp[i][j] = base + consecutive;
+                from[i][j] = j-1;
+            } else if best.0 > NONE {
+                dp[i][j] = base + jump;
+                from[i][j] = best.1;
+            }
+        }
+    }
+    let (mut j, s) = (0..n).map(|j| (j, dp[m-1][j])).max_by_key(|&(j, s)| (s, std::cmp::Reverse(j)))?;
+    if s <= NONE {
+        return None
+    }
+    let mut positions = vec![0; m];
+    for i in (0..m).rev() {
+        positions[i] = j;
+        j = from[i][j];
+    }
+    Some((s, positions))
+}
+
+/// The files under the root relative to it. Files ignored by .gitignore are skipped.
+pub fn index_files(root: &Path, show_hidden: bool) -> Vec<String> {
+    let walker = ignore::WalkBuilder::new(root)
+        .hidden(!show_hidden)
+        .require_git(false)
+        .sort_by_file_path(|a, b| a.cmp(b))
+        .build();
+    let mut v = vec![];
+    for entry in walker.filter_map(|x| x.ok()) {
+        if !entry.file_type().map(|t| t.is_file()).unwrap_or(false) {
+            continue
+        }
+        let path = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        if let Some(s) = path.to_str() {
+            v.push(s.to_owned());
+        }
+    }
+    v
+}
+
+/// Filters the files in the project as the query is typed.
+pub struct Finder {
+    pub rb: ReadBuffer,
+    root: PathBuf,
+    show_hidden: bool,
+    files: Vec<String>,
+    query: String,
+    matches: Vec<(usize, Vec<usize>)>, // the index of the file and the matched positions
+    navigator: Rc<RefCell<Navigator>>,
+    state: PageState,
+    message_box: MessageBox,
+}
+impl Finder {
+    pub fn open(root: &Path, show_hidden: bool, navigator: Rc<RefCell<Navigator>>) -> Self {
+        let state = PageState::new(INPUT.to_owned());
+        let message_box = MessageBox::new();
+        let mut r = Self {
+            rb: ReadBuffer::new(vec![], state.clone(), message_box.clone()), // not valid
+            root: root.to_owned(),
+            show_hidden,
+            files: vec![],
+            query: String::new(),
+            matches: vec![],
+            navigator,
+            state,
+            message_box,
+        };
+        r.refresh();
+        r
+    }
+    pub fn root(&self) -> PathBuf {
+        self.root.clone()
+    }
+    /// The paths of the matching files from the best.
+    pub fn matches(&self) -> Vec<&str> {
+        self.matches.iter().map(|(i, _)| self.files[*i].as_str()).collect()
+    }
+    fn update_cache(&mut self) {
+
+    }
+    pub fn refresh(&mut self) {
+        self.files = index_files(&self.root, self.show_hidden);
+        self.filter();
+    }
+    fn filter(&mut self) {
+        let mut v = vec![];
+        for (i, f) in self.files.iter().enumerate() {
+            if let Some((s, positions)) = score(&self.query, f) {
+                v.push((s, i, positions));
+            }
+        }
+        // shorter paths first on the same score
+        v.sort_by_key(|(s, i, _)| (-s, self.files[*i].len(), *i));
+        self.matches = v.into_iter().map(|(_, i, positions)| (i, positions)).collect();
+        self.refresh_memory();
+    }
+    fn refresh_memory(&mut self) {
+        let mut v = vec![];
+        for (i, _) in &self.matches {
+            let mut vv: Vec<BufElem> = self.files[*i].chars().map(BufElem::Char).collect();
+            vv.push(BufElem::Eol);
+            v.push(vv);
+        }
+        if v.is_empty() {
+            v.push(vec![BufElem::Eol]);
+        }
+        self.rb = ReadBuffer::new(v, self.state.clone(), self.message_box.clone());
+    }
+    fn show_query(&self) {
+        self.message_box.send(&format!("find: {}", self.query));
+    }
+
+    //
+    // eff functions
+    //
+
+    fn eff_select(&mut self, _: Key) -> String {
+        let i = match self.matches.get(self.rb.cursor.row) {
+            Some((i, _)) => *i,
+            None => return self.state.get(),
+        };
+        self.message_box.send("");
+        let path = self.root.join(&self.files[i]);
+        if let Some(page) = crate::large_file::open(&path, 0) {
+            self.navigator.borrow_mut().push(page);
+            return INIT.to_owned()
+        }
+        let x = Rc::new(RefCell::new(EditBuffer::open(&path, self.navigator.clone())));
+        let page = Rc::new(RefCell::new(edit_buffer::Page::new(x)));
+        self.navigator.borrow_mut().push(page);
+        INIT.to_owned()
+    }
+    fn eff_enter_input(&mut self, _: Key) -> String {
+        self.show_query();
+        INPUT.to_owned()
+    }
+    fn eff_leave_input(&mut self, _: Key) -> String {
+        self.message_box.send("");
+        INIT.to_owned()
+    }
+    fn eff_select_up(&mut self, _: Key) -> String {
+        if self.rb.cursor.row > 0 {
+            self.rb.cursor.row -= 1;
+        }
+        INPUT.to_owned()
+    }
+    fn eff_select_down(&mut self, _: Key) -> String {
+        if self.rb.cursor.row + 1 < self.matches.len() {
+            self.rb.cursor.row += 1;
+        }
+        INPUT.to_owned()
+    }
+    fn eff_input(&mut self, k: Key) -> String {
+        match k {
+            Key::Backspace => {
+                self.query.pop();
+            },
+            Key::Char(c) => self.query.push(c),
+            _ => return INPUT.to_owned(),
+        }
+        self.filter();
+        self.show_query();
+        INPUT.to_owned()
+    }
+    fn eff_refresh(&mut self, _: Key) -> String {
+        self.refresh();
+        INIT.to_owned()
+    }
+}
+
+use crate::controller::Effect;
+use crate::def_effect;
+use crate::Key;
+
+def_effect!(Select, Finder, eff_select);
+def_effect!(EnterInput, Finder, eff_enter_input);
+def_effect!(LeaveInput, Finder, eff_leave_input);
+def_effect!(SelectUp, Finder, eff_select_up);
+def_effect!(SelectDown, Finder, eff_select_down);
+def_effect!(Input, Finder, eff_input);
+def_effect!(Refresh, Finder, eff_refresh);
+
+use crate::shared::AsRefMut;
+pub fn mk_controller(x: Rc<RefCell<Finder>>) -> controller::ControllerFSM {
+    use crate::Key::*;
+    let mut g = controller::Graph::new();
+    read_buffer::add_edges(&mut g, x.clone().map(|x| &mut x.rb));
+
+    g.add_edge(INIT, Char('\n'), Rc::new(Select(x.clone())));
+    g.add_edge(INIT, Char('i'), Rc::new(EnterInput(x.clone())));
+    g.add_edge(INIT, Ctrl('r'), Rc::new(Refresh(x.clone())));
+
+    g.add_edge(INPUT, Char('\n'), Rc::new(Select(x.clone())));
+    g.add_edge(INPUT, Esc, Rc::new(LeaveInput(x.clone())));
+    g.add_edge(INPUT, Up, Rc::new(SelectUp(x.clone())));
+    g.add_edge(INPUT, Ctrl('p'), Rc::new(SelectUp(x.clone())));
+    g.add_edge(INPUT, Down, Rc::new(SelectDown(x.clone())));
+    g.add_edge(INPUT, Ctrl('n'), Rc::new(SelectDown(x.clone())));
+    g.add_edge(INPUT, Otherwise, Rc::new(Input(x.clone())));
+    crate::keymap::apply(&mut g, navigator::PageKind::Finder);
+    controller::ControllerFSM::new(INPUT, Box::new(g))
+}
+
+struct AddColor<'a> {
+    x: &'a Finder,
+}
+impl <'a> view::View for AddColor<'a> {
+    fn get(&self, col: usize, row: usize) -> view::ViewElem {
+        match self.x.matches.get(row) {
+            Some((_, positions)) if positions.contains(&col) => (None, Some(Color::LightRed), None),
+            _ => (None, None, None),
+        }
+    }
+}
+
+struct ViewGen {
+    x: Rc<RefCell<Finder>>,
+}
+fn gen_impl(x: &mut Finder, region: view::Area) -> Box<dyn view::View> {
+    x.rb.stabilize_cursor();
+    x.rb.adjust_window(region.width, region.height);
+    x.update_cache();
+
+    let (_, finder_area) = region.split_horizontal(view::LINE_NUMBER_W);
+
+    let finder_view = view::ToView::new(&x.rb.buf);
+    let finder_view = view::OverlayView::new(finder_view, AddColor { x });
+
+    let add_cursor = view::AddCursor::new(x.rb.cursor);
+    let finder_view = view::OverlayView::new(finder_view, add_cursor);
+
+    let finder_view = view::TranslateView::new(
+        finder_view,
+        finder_area.col as i32 - x.rb.window.col() as i32,
+        finder_area.row as i32 - x.rb.window.row() as i32,
+    );
+
+    let lineno_range = x.rb.lineno_range();
+    let lineno_view = view::LineNumber {
+        from: lineno_range.start+1,
+        to: lineno_range.end,
+    };
+
+    let view = view::MergeHorizontal {
+        left: lineno_view,
+        right: finder_view,
+        col_offset: finder_area.col,
+    };
+
+    let view = view::CloneView::new(view, region);
+    Box::new(view)
+}
+impl view::ViewGen for ViewGen {
+    fn gen(&mut self, region: view::Area) -> Box<dyn view::View> {
+        gen_impl(&mut self.x.borrow_mut(), region)
+    }
+}
+
+pub struct Page {
+    controller: Box<dyn controller::Controller>,
+    view_gen: Box<dyn view::ViewGen>,
+    x: Rc<RefCell<Finder>>,
+    root: PathBuf, // cached for id(). see directory::Page
+}
+impl Page {
+    pub fn new(x: Rc<RefCell<Finder>>) -> Self {
+        let root = x.borrow().root();
+        Self {
+            controller: Box::new(mk_controller(x.clone())),
+            view_gen: Box::new(ViewGen { x: x.clone() }),
+            x,
+            root,
+        }
+    }
+}
+impl navigator::Page for Page {
+    fn controller(&self) -> &Box<dyn controller::Controller> {
+        &self.controller
+    }
+    fn view_gen(&mut self) -> &mut Box<dyn view::ViewGen> {
+        &mut self.view_gen
+    }
+    fn status(&self) -> String {
+        let x = self.x.borrow();
+        let state: &str = match x.state.get().as_str() {
+            read_buffer::INIT => "*",
+            read_buffer::SEARCH => "/",
+            INPUT => "i",
+            _ => "*",
+        };
+        format!("[Finder -{}-] {} in {} ({}/{})", state, x.query, self.root.to_str().unwrap(), x.matches.len(), x.files.len())
+    }
+    fn kind(&self) -> navigator::PageKind {
+        navigator::PageKind::Finder
+    }
+    fn id(&self) -> String {
+        format!("finder:{}", self.root.to_str().unwrap())
+    }
+    fn message(&self) -> MessageBox {
+        self.x.borrow().message_box.clone()
+    }
+}
+
+/// The root of the project containing the path. The root of the git repository if any.
+pub fn project_root(path: &Path) -> PathBuf {
+    if let Some(repo) = crate::git::Repo::discover(path) {
+        return repo.root().to_owned()
+    }
+    let dir = if path.is_dir() {
+        path
+    } else {
+        path.parent().unwrap_or(path)
+    };
+    std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_owned())
+}
+
+/// Opens the finder of the files in the project containing the path.
+pub fn open(path: &Path, show_hidden: bool, navigator: Rc<RefCell<Navigator>>) {
+    let root = project_root(path);
+    let x = Rc::new(RefCell::new(Finder::open(&root, show_hidden, navigator.clone())));
+    x.borrow().show_query();
+    let page = Rc::new(RefCell::new(Page::new(x)));
+    navigator.borrow_mut().push(page);
+}
+
+#[test]
+fn test_score() {
+    assert!(score("abc", "src/acb.rs").is_none());
+    assert_eq!(score("", "a").unwrap().0, 0);
+    assert_eq!(score("ED", "src/edit_buffer/mod.rs").unwrap().1, vec![4, 5]);
+
+    // the head of a segment is preferred
+    let (_, positions) = score("eb", "src/edit_buffer/mod.rs").unwrap();
+    assert_eq!(positions, vec![4, 9]);
+    assert!(score("eb", "src/edit_buffer/mod.rs").unwrap().0 > score("eb", "src/web.rs").unwrap().0);
+    // camel case
+    let (_, positions) = score("rb", "ReadBuffer.java").unwrap();
+    assert_eq!(positions, vec![0, 4]);
+    // consecutive chars
+    assert!(score("mod", "src/mod.rs").unwrap().0 > score("mod", "src/main_old_data.rs").unwrap().0);
+}
@@ -0,0 +1,138 @@
+use std::ops::Range;
+
+/// A changed region. `old` is replaced by `new`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+#[derive(PartialEq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Myers' O(ND) difference algorithm.
+/// The common head and tail are skipped beforehand since most of the edits are local.
+pub fn diff<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Hunk> {
+    let head = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let tail = a[head..].iter().rev().zip(b[head..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let ops = shortest_edit(&a[head..a.len()-tail], &b[head..b.len()-tail]);
+
+    let mut hunks = vec![];
+    let (mut i, mut j) = (head, head);
+    let mut cur: Option<Hunk> = None;
+    for op in ops {
+        match op {
+            Op::Equal => {
+                if let Some(h) = cur.take() {
+                    hunks.push(h);
+                }
+                i += 1;
+                j += 1;
+            },
+            Op::Delete => {
+                let h = cur.get_or_insert(Hunk { old: i..i, new: j..j });
+                i += 1;
+                h.old.end = i;
+            },
+            Op::Insert => {
+                let h = cur.get_or_insert(Hunk { old: i..i, new: j..j });
+                j += 1;
+                h.new.end = j;
+            },
+        }
+    }
+    if let Some(h) = cur {
+        hunks.push(h);
+    }
+    hunks
+}
+
+fn shortest_edit<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Op> {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max = n + m;
+    let idx = |k: isize| (k + max + 1) as usize;
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    // v[-d-1..=d+1] before the d-th round. the rest isn't read when backtracking
+    let mut trace = vec![];
+    'outer: for d in 0..=max {
+        trace.push(v[idx(-d-1)..=idx(d+1)].to_vec());
+        let mut k = -d;
+        while k <= d {
+            let mut x = if k == -d || (k != d && v[idx(k-1)] < v[idx(k+1)]) {
+                v[idx(k+1)]
+            } else {
+                v[idx(k-1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx(k)] = x;
+            if x >= n && y >= m {
+                break 'outer;
+            }
+            k += 2;
+        }
+    }
+
+    let mut ops = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k-1) < at(k+1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            ops.push(Op::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            ops.push(if x == prev_x { Op::Insert } else { Op::Delete });
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    ops.reverse();
+    ops
+}
+
+#[test]
+fn test_diff() {
+    let a = ["a", "b", "c", "d", "e"];
+    assert_eq!(diff(&a, &a), vec![]);
+    assert_eq!(diff(&a, &["a", "b", "x", "c", "d", "e"]), vec![Hunk { old: 2..2, new: 2..3 }]);
+    assert_eq!(diff(&a, &["a", "c", "d", "e"]), vec![Hunk { old: 1..2, new: 1..1 }]);
+    assert_eq!(diff(&a, &["a", "x", "c", "y", "e", "z"]), vec![
+        Hunk { old: 1..2, new: 1..2 },
+        Hunk { old: 3..4, new: 3..4 },
+        Hunk { old: 5..5, new: 5..6 },
+    ]);
+    assert_eq!(diff(&[], &["a"]), vec![Hunk { old: 0..0, new: 0..1 }]);
+    assert_eq!(diff(&["a"], &[]), vec![Hunk { old: 0..1, new: 0..0 }]);
+
+    // many differences: applying the hunks to a gives b
+    let a: Vec<usize> = (0..100).collect();
+    let b: Vec<usize> = (0..120).filter(|i| i % 3 != 0).map(|i| if i % 7 == 0 { i + 1000 } else { i }).collect();
+    let mut c = vec![];
+    let mut i = 0;
+    for h in diff(&a, &b) {
+        c.extend_from_slice(&a[i..h.old.start]);
+        c.extend_from_slice(&b[h.new.clone()]);
+        i = h.old.end;
+    }
+    c.extend_from_slice(&a[i..]);
+    assert_eq!(c, b);
+}
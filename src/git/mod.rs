@@ -0,0 +1,311 @@
+pub mod diff;
+pub mod status;
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::read_buffer::{BufElem, Storage};
+use crate::screen::Color;
+use crate::view;
+use self::diff::Hunk;
+
+// git is driven by its command line.
+fn run(dir: &Path, args: &[&str], input: Option<&str>) -> io::Result<String> {
+    let mut child = Command::new("git")
+        .arg("-C").arg(dir)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    {
+        let mut stdin = child.stdin.take().unwrap();
+        if let Some(input) = input {
+            stdin.write_all(input.as_bytes())?;
+        }
+    }
+    let out = child.wait_with_output()?;
+    if out.status.success() {
+        Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+    } else {
+        let msg = String::from_utf8_lossy(&out.stderr).trim().to_owned();
+        Err(io::Error::other(msg))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileStatus {
+    pub index: char,
+    pub worktree: char,
+    pub path: String, // relative to the root
+}
+impl FileStatus {
+    pub fn is_untracked(&self) -> bool {
+        self.index == '?'
+    }
+}
+
+/// The diff of a file split into hunks so each of them can be applied separately.
+#[derive(Clone, Debug)]
+pub struct FilePatch {
+    header: String,
+    pub hunks: Vec<String>,
+}
+impl FilePatch {
+    fn parse(s: &str) -> Self {
+        let mut header = String::new();
+        let mut hunks: Vec<String> = vec![];
+        for line in s.split_inclusive('\n') {
+            if line.starts_with("@@") {
+                hunks.push(line.to_owned());
+            } else if let Some(h) = hunks.last_mut() {
+                h.push_str(line);
+            } else {
+                header.push_str(line);
+            }
+        }
+        Self { header, hunks }
+    }
+    fn patch(&self, i: usize) -> String {
+        format!("{}{}", self.header, self.hunks[i])
+    }
+}
+
+/// Returns the first line of the hunk in the new file.
+pub fn hunk_start(hunk: &str) -> usize {
+    // @@ -a,b +c,d @@
+    let new = hunk.split(' ').nth(2).unwrap_or("+1");
+    let c = new.trim_start_matches('+').split(',').next().unwrap();
+    c.parse().unwrap_or(1)
+}
+
+pub struct Repo {
+    root: PathBuf,
+}
+impl Repo {
+    /// Finds the repository that contains the path.
+    pub fn discover(path: &Path) -> Option<Repo> {
+        let dir = if path.is_dir() {
+            path.to_owned()
+        } else {
+            match path.parent() {
+                Some(p) if !p.as_os_str().is_empty() => p.to_owned(),
+                _ => PathBuf::from("."),
+            }
+        };
+        let root = run(&dir, &["rev-parse", "--show-toplevel"], None).ok()?;
+        Some(Repo { root: PathBuf::from(root.trim_end()) })
+    }
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+    fn relative(&self, path: &Path) -> Option<String> {
+        let abs = match std::fs::canonicalize(path) {
+            Ok(p) => p,
+            Err(_) => {
+                let parent = std::fs::canonicalize(path.parent()?).ok()?;
+                parent.join(path.file_name()?)
+            }
+        };
+        let root = std::fs::canonicalize(&self.root).ok()?;
+        abs.strip_prefix(root).ok().map(|p| p.to_str().unwrap().to_owned())
+    }
+    /// The content of the file in HEAD.
+    pub fn head_blob(&self, path: &Path) -> Option<String> {
+        let rel = self.relative(path)?;
+        run(&self.root, &["show", &format!("HEAD:{}", rel)], None).ok()
+    }
+    pub fn status(&self) -> io::Result<Vec<FileStatus>> {
+        let out = run(&self.root, &["status", "--porcelain", "-z", "--untracked-files=all"], None)?;
+        let mut res = vec![];
+        let mut entries = out.split('\0');
+        while let Some(e) = entries.next() {
+            if e.len() < 4 {
+                continue;
+            }
+            let mut cs = e.chars();
+            let index = cs.next().unwrap();
+            let worktree = cs.next().unwrap();
+            if index == 'R' || index == 'C' {
+                // followed by the original path
+                entries.next();
+            }
+            res.push(FileStatus { index, worktree, path: e[3..].to_owned() });
+        }
+        Ok(res)
+    }
+    /// The unstaged (or staged if cached) changes of the file.
+    pub fn diff(&self, path: &str, cached: bool) -> io::Result<FilePatch> {
+        let mut args = vec!["diff", "--no-color", "--no-ext-diff", "-U0"];
+        if cached {
+            args.push("--cached");
+        }
+        args.push("--");
+        args.push(path);
+        run(&self.root, &args, None).map(|s| FilePatch::parse(&s))
+    }
+    pub fn stage_hunk(&self, patch: &FilePatch, i: usize) -> io::Result<()> {
+        run(&self.root, &["apply", "--cached", "--unidiff-zero", "-"], Some(&patch.patch(i))).map(|_| ())
+    }
+    pub fn unstage_hunk(&self, patch: &FilePatch, i: usize) -> io::Result<()> {
+        run(&self.root, &["apply", "--cached", "--unidiff-zero", "--reverse", "-"], Some(&patch.patch(i))).map(|_| ())
+    }
+    pub fn stage_file(&self, path: &str) -> io::Result<()> {
+        run(&self.root, &["add", "--", path], None).map(|_| ())
+    }
+    pub fn unstage_file(&self, path: &str) -> io::Result<()> {
+        run(&self.root, &["reset", "-q", "--", path], None).map(|_| ())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Marker {
+    Added,
+    Modified,
+    Deleted, // some lines are deleted below this line
+}
+impl Marker {
+    fn mark(self) -> (char, Color) {
+        match self {
+            Marker::Added => ('+', Color::Green),
+            Marker::Modified => ('~', Color::Yellow),
+            Marker::Deleted => ('-', Color::Red),
+        }
+    }
+}
+
+pub fn markers(hunks: &[Hunk]) -> HashMap<usize, Marker> {
+    let mut res = HashMap::new();
+    for h in hunks {
+        if h.new.start == h.new.end {
+            res.insert(h.new.start.saturating_sub(1), Marker::Deleted);
+            continue;
+        }
+        let n_modified = h.old.end - h.old.start;
+        for row in h.new.clone() {
+            let m = if row - h.new.start < n_modified {
+                Marker::Modified
+            } else {
+                Marker::Added
+            };
+            res.insert(row, m);
+        }
+    }
+    res
+}
+
+fn to_lines(s: &str) -> Vec<String> {
+    // same as read_buffer::read_from_string
+    if s.is_empty() {
+        vec!["".to_owned()]
+    } else {
+        s.lines().map(|x| x.to_owned()).collect()
+    }
+}
+
+// counts the times HEAD may have moved
+static HEAD_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// Tells the gutters to read HEAD again e.g. after a commit.
+pub fn head_changed() {
+    HEAD_GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The change markers of a buffer against HEAD.
+pub struct Gutter {
+    repo: Repo,
+    path: PathBuf,
+    head: Vec<String>,
+    generation: usize, // of HEAD read
+    version: Option<usize>, // the undo node of the buffer when the markers are computed
+    markers: HashMap<usize, Marker>,
+}
+impl Gutter {
+    /// None if the file isn't in HEAD.
+    pub fn open(path: &Path) -> Option<Gutter> {
+        let repo = Repo::discover(path)?;
+        let generation = HEAD_GENERATION.load(Ordering::Relaxed);
+        let head = repo.head_blob(path)?;
+        Some(Gutter {
+            repo,
+            path: path.to_owned(),
+            head: to_lines(&head),
+            generation,
+            version: None,
+            markers: HashMap::new(),
+        })
+    }
+    /// Diffs the buffer against HEAD only if the buffer or HEAD is changed since the last time.
+    pub fn update<S: Storage + ?Sized>(&mut self, buf: &S, version: usize) {
+        let generation = HEAD_GENERATION.load(Ordering::Relaxed);
+        if self.generation != generation {
+            // removed from HEAD: all the lines are added
+            self.head = self.repo.head_blob(&self.path).map(|x| to_lines(&x)).unwrap_or_default();
+            self.generation = generation;
+            self.version = None;
+        }
+        if self.version == Some(version) {
+            return
+        }
+        let mut lines = vec![];
+        for row in 0..buf.len() {
+            let line: String = buf.line(row).iter().filter_map(|e| match e {
+                BufElem::Char(c) => Some(*c),
+                BufElem::Eol => None,
+            }).collect();
+            lines.push(line);
+        }
+        self.markers = markers(&diff::diff(&self.head, &lines));
+        self.version = Some(version);
+    }
+    pub fn marker(&self, row: usize) -> Option<Marker> {
+        self.markers.get(&row).cloned()
+    }
+}
+
+pub struct GutterDiffView<'a> {
+    x: Option<&'a Gutter>,
+    from: usize,
+}
+impl <'a> GutterDiffView<'a> {
+    pub fn new(x: Option<&'a Gutter>, from: usize) -> Self {
+        Self { x, from }
+    }
+}
+impl <'a> view::View for GutterDiffView<'a> {
+    fn get(&self, col: usize, row: usize) -> view::ViewElem {
+        if col != view::LINE_NUMBER_W - 2 {
+            return (None, None, None)
+        }
+        match self.x.and_then(|x| x.markers.get(&(self.from + row))) {
+            Some(m) => {
+                let (c, fg) = m.mark();
+                (Some(c), Some(fg), None)
+            },
+            None => (None, None, None),
+        }
+    }
+}
+
+#[test]
+fn test_markers() {
+    let hunks = vec![
+        Hunk { old: 0..0, new: 0..1 },
+        Hunk { old: 2..3, new: 3..5 },
+        Hunk { old: 6..8, new: 8..8 },
+    ];
+    let m = markers(&hunks);
+    assert_eq!(m.get(&0), Some(&Marker::Added));
+    assert_eq!(m.get(&3), Some(&Marker::Modified));
+    assert_eq!(m.get(&4), Some(&Marker::Added));
+    assert_eq!(m.get(&7), Some(&Marker::Deleted));
+    assert_eq!(m.len(), 4);
+}
+
+#[test]
+fn test_hunk_start() {
+    assert_eq!(hunk_start("@@ -3 +3,2 @@ fn main() {\n"), 3);
+    assert_eq!(hunk_start("@@ -10,0 +11 @@\n"), 11);
+}
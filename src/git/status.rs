@@ -0,0 +1,328 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use crate::edit_buffer::{self, EditBuffer};
+use crate::controller::{self, PageState};
+use crate::view;
+use crate::navigator::{self, Navigator};
+use crate::read_buffer::{self, BufElem, Cursor, ReadBuffer};
+use crate::screen::Color;
+use crate::message_box::MessageBox;
+use crate::read_buffer::INIT;
+use super::{FilePatch, FileStatus, Repo, hunk_start};
+
+#[derive(Clone)]
+enum Entry {
+    File(usize),
+    Hunk {
+        file: usize,
+        staged: bool,
+        i: usize,
+    },
+}
+
+struct File {
+    status: FileStatus,
+    unstaged: Option<FilePatch>,
+    staged: Option<FilePatch>,
+}
+impl File {
+    fn patch(&self, staged: bool) -> &FilePatch {
+        if staged {
+            self.staged.as_ref().unwrap()
+        } else {
+            self.unstaged.as_ref().unwrap()
+        }
+    }
+}
+
+/// Lists the changed files and their hunks.
+pub struct GitStatus {
+    pub rb: ReadBuffer,
+    repo: Repo,
+    files: Vec<File>,
+    entries: Vec<Entry>,
+    navigator: Rc<RefCell<Navigator>>,
+    state: PageState,
+    message_box: MessageBox,
+}
+impl GitStatus {
+    pub fn open(repo: Repo, navigator: Rc<RefCell<Navigator>>) -> Self {
+        let state = PageState::new(INIT.to_owned());
+        let message_box = MessageBox::new();
+        let mut r = Self {
+            rb: ReadBuffer::new(vec![], state.clone(), message_box.clone()), // not valid
+            repo,
+            files: vec![],
+            entries: vec![],
+            navigator,
+            state,
+            message_box,
+        };
+        r.refresh();
+        r
+    }
+    pub fn root(&self) -> PathBuf {
+        self.repo.root().to_owned()
+    }
+    fn update_cache(&mut self) {
+
+    }
+    pub fn refresh(&mut self) {
+        // e.g. committed in the shell
+        super::head_changed();
+        self.files.clear();
+        let status = match self.repo.status() {
+            Ok(x) => x,
+            Err(e) => {
+                self.message_box.send(&e.to_string());
+                vec![]
+            }
+        };
+        for st in status {
+            let (unstaged, staged) = if st.is_untracked() {
+                (None, None)
+            } else {
+                (self.repo.diff(&st.path, false).ok(), self.repo.diff(&st.path, true).ok())
+            };
+            self.files.push(File { status: st, unstaged, staged });
+        }
+        self.refresh_memory();
+    }
+    fn refresh_memory(&mut self) {
+        self.entries.clear();
+        for (k, f) in self.files.iter().enumerate() {
+            self.entries.push(Entry::File(k));
+            for (patch, staged) in &[(&f.unstaged, false), (&f.staged, true)] {
+                if let Some(patch) = patch {
+                    for i in 0..patch.hunks.len() {
+                        self.entries.push(Entry::Hunk { file: k, staged: *staged, i });
+                    }
+                }
+            }
+        }
+        let mut v = vec![];
+        for e in &self.entries {
+            let s = match *e {
+                Entry::File(k) => {
+                    let st = &self.files[k].status;
+                    format!("{}{} {}", st.index, st.worktree, st.path)
+                },
+                Entry::Hunk { file, staged, i } => {
+                    let hunk = &self.files[file].patch(staged).hunks[i];
+                    let header = hunk.lines().next().unwrap();
+                    format!("    {} {}", if staged { "staged  " } else { "unstaged" }, header)
+                },
+            };
+            let mut vv: Vec<BufElem> = s.chars().map(BufElem::Char).collect();
+            vv.push(BufElem::Eol);
+            v.push(vv);
+        }
+        if v.is_empty() {
+            v.push(vec![BufElem::Eol]);
+        }
+        let cursor = self.rb.cursor;
+        self.rb = ReadBuffer::new(v, self.state.clone(), self.message_box.clone());
+        self.rb.cursor = cursor;
+        self.rb.stabilize_cursor();
+    }
+    fn selected(&self) -> Option<Entry> {
+        self.entries.get(self.rb.cursor.row).cloned()
+    }
+
+    //
+    // eff functions
+    //
+
+    fn eff_select(&mut self, _: Key) -> String {
+        let (file, row) = match self.selected() {
+            None => return INIT.to_owned(),
+            Some(Entry::File(k)) => (k, 0),
+            Some(Entry::Hunk { file, staged, i }) => {
+                let hunk = &self.files[file].patch(staged).hunks[i];
+                (file, hunk_start(hunk).saturating_sub(1))
+            },
+        };
+        let path = self.repo.root().join(&self.files[file].status.path);
+        if let Some(page) = crate::large_file::open(&path, row) {
+            self.navigator.borrow_mut().push(page);
+            return INIT.to_owned()
+        }
+        let x = Rc::new(RefCell::new(EditBuffer::open(&path, self.navigator.clone())));
+        x.borrow_mut().rb.cursor = Cursor { row, col: 0 };
+        let page = Rc::new(RefCell::new(edit_buffer::Page::new(x)));
+        self.navigator.borrow_mut().push(page);
+        INIT.to_owned()
+    }
+    fn eff_stage(&mut self, _: Key) -> String {
+        let res = match self.selected() {
+            None => return INIT.to_owned(),
+            Some(Entry::File(k)) => self.repo.stage_file(&self.files[k].status.path),
+            Some(Entry::Hunk { staged: true, .. }) => {
+                self.message_box.send("Already staged");
+                return INIT.to_owned()
+            },
+            Some(Entry::Hunk { file, staged: false, i }) => self.repo.stage_hunk(self.files[file].patch(false), i),
+        };
+        if let Err(e) = res {
+            self.message_box.send(&e.to_string());
+        }
+        self.refresh();
+        INIT.to_owned()
+    }
+    fn eff_unstage(&mut self, _: Key) -> String {
+        let res = match self.selected() {
+            None => return INIT.to_owned(),
+            Some(Entry::File(k)) => self.repo.unstage_file(&self.files[k].status.path),
+            Some(Entry::Hunk { staged: false, .. }) => {
+                self.message_box.send("Not staged");
+                return INIT.to_owned()
+            },
+            Some(Entry::Hunk { file, staged: true, i }) => self.repo.unstage_hunk(self.files[file].patch(true), i),
+        };
+        if let Err(e) = res {
+            self.message_box.send(&e.to_string());
+        }
+        self.refresh();
+        INIT.to_owned()
+    }
+    fn eff_refresh(&mut self, _: Key) -> String {
+        self.refresh();
+        INIT.to_owned()
+    }
+}
+
+use crate::controller::Effect;
+use crate::def_effect;
+use crate::Key;
+
+def_effect!(Select, GitStatus, eff_select);
+def_effect!(Stage, GitStatus, eff_stage);
+def_effect!(Unstage, GitStatus, eff_unstage);
+def_effect!(Refresh, GitStatus, eff_refresh);
+
+use crate::shared::AsRefMut;
+pub fn mk_controller(x: Rc<RefCell<GitStatus>>) -> controller::ControllerFSM {
+    use crate::Key::*;
+    let mut g = controller::Graph::new();
+    read_buffer::add_edges(&mut g, x.clone().map(|x| &mut x.rb));
+
+    g.add_edge(INIT, Char('\n'), Rc::new(Select(x.clone())));
+    g.add_edge(INIT, Char('s'), Rc::new(Stage(x.clone())));
+    g.add_edge(INIT, Char('u'), Rc::new(Unstage(x.clone())));
+    g.add_edge(INIT, Ctrl('r'), Rc::new(Refresh(x.clone())));
+    crate::keymap::apply(&mut g, navigator::PageKind::GitStatus);
+    controller::ControllerFSM::new(INIT, Box::new(g))
+}
+
+struct AddColor<'a> {
+    x: &'a GitStatus,
+}
+impl <'a> view::View for AddColor<'a> {
+    fn get(&self, _: usize, row: usize) -> view::ViewElem {
+        match self.x.entries.get(row) {
+            Some(Entry::File(_)) => (None, Some(Color::LightRed), None),
+            Some(Entry::Hunk { staged: true, .. }) => (None, Some(Color::Green), None),
+            Some(Entry::Hunk { staged: false, .. }) => (None, Some(Color::Red), None),
+            None => (None, None, None),
+        }
+    }
+}
+
+struct ViewGen {
+    x: Rc<RefCell<GitStatus>>,
+}
+fn gen_impl(x: &mut GitStatus, region: view::Area) -> Box<dyn view::View> {
+    x.rb.stabilize_cursor();
+    x.rb.adjust_window(region.width, region.height);
+    x.update_cache();
+
+    let (_, status_area) = region.split_horizontal(view::LINE_NUMBER_W);
+
+    let status_view = view::ToView::new(&x.rb.buf);
+    let status_view = view::OverlayView::new(status_view, AddColor { x });
+
+    let add_cursor = view::AddCursor::new(x.rb.cursor);
+    let status_view = view::OverlayView::new(status_view, add_cursor);
+
+    let status_view = view::TranslateView::new(
+        status_view,
+        status_area.col as i32 - x.rb.window.col() as i32,
+        status_area.row as i32 - x.rb.window.row() as i32,
+    );
+
+    let lineno_range = x.rb.lineno_range();
+    let lineno_view = view::LineNumber {
+        from: lineno_range.start+1,
+        to: lineno_range.end,
+    };
+
+    let view = view::MergeHorizontal {
+        left: lineno_view,
+        right: status_view,
+        col_offset: status_area.col,
+    };
+
+    let view = view::CloneView::new(view, region);
+    Box::new(view)
+}
+impl view::ViewGen for ViewGen {
+    fn gen(&mut self, region: view::Area) -> Box<dyn view::View> {
+        gen_impl(&mut self.x.borrow_mut(), region)
+    }
+}
+
+pub struct Page {
+    controller: Box<dyn controller::Controller>,
+    view_gen: Box<dyn view::ViewGen>,
+    x: Rc<RefCell<GitStatus>>,
+    root: PathBuf, // cached for id(). see directory::Page
+}
+impl Page {
+    pub fn new(x: Rc<RefCell<GitStatus>>) -> Self {
+        let root = x.borrow().root();
+        Self {
+            controller: Box::new(mk_controller(x.clone())),
+            view_gen: Box::new(ViewGen { x: x.clone() }),
+            x,
+            root,
+        }
+    }
+}
+impl navigator::Page for Page {
+    fn controller(&self) -> &Box<dyn controller::Controller> {
+        &self.controller
+    }
+    fn view_gen(&mut self) -> &mut Box<dyn view::ViewGen> {
+        &mut self.view_gen
+    }
+    fn status(&self) -> String {
+        let state: &str = match self.x.borrow().state.get().as_str() {
+            read_buffer::INIT => "*",
+            read_buffer::SEARCH => "/",
+            _ => "*",
+        };
+        format!("[Git -{}-] {}", state, self.root.to_str().unwrap())
+    }
+    fn kind(&self) -> navigator::PageKind {
+        navigator::PageKind::GitStatus
+    }
+    fn id(&self) -> String {
+        format!("git:{}", self.root.to_str().unwrap())
+    }
+    fn message(&self) -> MessageBox {
+        self.x.borrow().message_box.clone()
+    }
+}
+
+/// Opens the status page of the repository containing the path.
+pub fn open(path: &std::path::Path, navigator: Rc<RefCell<Navigator>>, message_box: &MessageBox) {
+    match Repo::discover(path) {
+        Some(repo) => {
+            let x = Rc::new(RefCell::new(GitStatus::open(repo, navigator.clone())));
+            let page = Rc::new(RefCell::new(Page::new(x)));
+            navigator.borrow_mut().push(page);
+        },
+        None => message_box.send("Not a git repository"),
+    }
+}
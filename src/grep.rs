@@ -0,0 +1,339 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use regex::{Regex, RegexBuilder};
+use crate::edit_buffer::{self, EditBuffer};
+use crate::controller::{self, PageState};
+use crate::view;
+use crate::navigator::{self, Navigator};
+use crate::read_buffer::{self, BufElem, Cursor, ReadBuffer};
+use crate::read_buffer::search::Mode;
+use crate::screen::Color;
+use crate::message_box::MessageBox;
+use crate::read_buffer::INIT;
+
+// more hits than this are dropped
+const MAX_HITS: usize = 10000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hit {
+    pub path: PathBuf, // relative to the root
+    pub row: usize,
+    pub col: usize,
+    pub text: String,
+}
+
+/// The regex to search the query in the mode.
+pub fn pattern(query: &str, mode: Mode) -> Result<Regex, regex::Error> {
+    let (s, ignore_case) = match mode {
+        Mode::Literal => (regex::escape(query), true),
+        Mode::SmartCase => (regex::escape(query), !query.chars().any(|c| c.is_uppercase())),
+        Mode::Regex => (query.to_owned(), false),
+    };
+    RegexBuilder::new(&s).case_insensitive(ignore_case).build()
+}
+
+/// Searches the files under the root for the regex.
+/// Files ignored by .gitignore and binary files are skipped.
+pub fn search_dir(root: &Path, re: &Regex, show_hidden: bool) -> Vec<Hit> {
+    let walker = ignore::WalkBuilder::new(root)
+        .hidden(!show_hidden)
+        .require_git(false)
+        .sort_by_file_path(|a, b| a.cmp(b))
+        .build();
+    let mut hits = vec![];
+    for entry in walker {
+        let entry = match entry {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        if !entry.file_type().map(|t| t.is_file()).unwrap_or(false) {
+            continue
+        }
+        let s = match std::fs::read_to_string(entry.path()) {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        if s.contains('\0') {
+            continue
+        }
+        let path = entry.path().strip_prefix(root).unwrap_or(entry.path()).to_owned();
+        for (row, line) in s.lines().enumerate() {
+            if let Some(m) = re.find(line) {
+                hits.push(Hit {
+                    path: path.clone(),
+                    row,
+                    col: line[..m.start()].chars().count(),
+                    text: line.to_owned(),
+                });
+                if hits.len() == MAX_HITS {
+                    return hits
+                }
+            }
+        }
+    }
+    hits
+}
+
+/// Lists the lines matching the query in the files under the directory.
+pub struct Grep {
+    pub rb: ReadBuffer,
+    root: PathBuf,
+    query: String,
+    mode: Mode,
+    show_hidden: bool,
+    hits: Vec<Hit>,
+    prefix_lens: Vec<usize>, // the length of "file:line:" of each row
+    navigator: Rc<RefCell<Navigator>>,
+    state: PageState,
+    message_box: MessageBox,
+}
+impl Grep {
+    pub fn open(root: &Path, query: &str, mode: Mode, show_hidden: bool, navigator: Rc<RefCell<Navigator>>) -> Self {
+        let state = PageState::new(INIT.to_owned());
+        let message_box = MessageBox::new();
+        let mut r = Self {
+            rb: ReadBuffer::new(vec![], state.clone(), message_box.clone()), // not valid
+            root: root.to_owned(),
+            query: query.to_owned(),
+            mode,
+            show_hidden,
+            hits: vec![],
+            prefix_lens: vec![],
+            navigator,
+            state,
+            message_box,
+        };
+        r.refresh();
+        r
+    }
+    pub fn root(&self) -> PathBuf {
+        self.root.clone()
+    }
+    pub fn query(&self) -> String {
+        self.query.clone()
+    }
+    pub fn hits(&self) -> &[Hit] {
+        &self.hits
+    }
+    fn update_cache(&mut self) {
+
+    }
+    pub fn refresh(&mut self) {
+        self.hits = match pattern(&self.query, self.mode) {
+            Ok(re) => search_dir(&self.root, &re, self.show_hidden),
+            Err(_) => {
+                self.message_box.send("Invalid regex");
+                vec![]
+            }
+        };
+        if self.hits.len() == MAX_HITS {
+            self.message_box.send(&format!("Too many hits. Only the first {} are shown", MAX_HITS));
+        }
+        self.refresh_memory();
+    }
+    fn refresh_memory(&mut self) {
+        self.prefix_lens.clear();
+        let mut v = vec![];
+        for hit in &self.hits {
+            let prefix = format!("{}:{}:", hit.path.to_str().unwrap(), hit.row + 1);
+            self.prefix_lens.push(prefix.chars().count());
+            let s = format!("{} {}", prefix, hit.text);
+            let mut vv: Vec<BufElem> = s.chars().map(BufElem::Char).collect();
+            vv.push(BufElem::Eol);
+            v.push(vv);
+        }
+        if v.is_empty() {
+            v.push(vec![BufElem::Eol]);
+        }
+        let cursor = self.rb.cursor;
+        self.rb = ReadBuffer::new(v, self.state.clone(), self.message_box.clone());
+        self.rb.cursor = cursor;
+        self.rb.stabilize_cursor();
+    }
+
+    //
+    // eff functions
+    //
+
+    fn eff_select(&mut self, _: Key) -> String {
+        let hit = match self.hits.get(self.rb.cursor.row) {
+            Some(x) => x.clone(),
+            None => return INIT.to_owned(),
+        };
+        let path = self.root.join(&hit.path);
+        if let Some(page) = crate::large_file::open(&path, hit.row) {
+            self.navigator.borrow_mut().push(page);
+            return INIT.to_owned()
+        }
+        let x = Rc::new(RefCell::new(EditBuffer::open(&path, self.navigator.clone())));
+        x.borrow_mut().rb.cursor = Cursor { row: hit.row, col: hit.col };
+        let page = Rc::new(RefCell::new(edit_buffer::Page::new(x)));
+        self.navigator.borrow_mut().push(page);
+        INIT.to_owned()
+    }
+    fn eff_refresh(&mut self, _: Key) -> String {
+        self.refresh();
+        INIT.to_owned()
+    }
+}
+
+use crate::controller::Effect;
+use crate::def_effect;
+use crate::Key;
+
+def_effect!(Select, Grep, eff_select);
+def_effect!(Refresh, Grep, eff_refresh);
+
+use crate::shared::AsRefMut;
+pub fn mk_controller(x: Rc<RefCell<Grep>>) -> controller::ControllerFSM {
+    use crate::Key::*;
+    let mut g = controller::Graph::new();
+    read_buffer::add_edges(&mut g, x.clone().map(|x| &mut x.rb));
+
+    g.add_edge(INIT, Char('\n'), Rc::new(Select(x.clone())));
+    g.add_edge(INIT, Ctrl('r'), Rc::new(Refresh(x.clone())));
+    crate::keymap::apply(&mut g, navigator::PageKind::Grep);
+    controller::ControllerFSM::new(INIT, Box::new(g))
+}
+
+struct AddColor<'a> {
+    x: &'a Grep,
+}
+impl <'a> view::View for AddColor<'a> {
+    fn get(&self, col: usize, row: usize) -> view::ViewElem {
+        match self.x.prefix_lens.get(row) {
+            Some(&n) if col < n => (None, Some(Color::LightRed), None),
+            _ => (None, None, None),
+        }
+    }
+}
+
+struct ViewGen {
+    x: Rc<RefCell<Grep>>,
+}
+fn gen_impl(x: &mut Grep, region: view::Area) -> Box<dyn view::View> {
+    x.rb.stabilize_cursor();
+    x.rb.adjust_window(region.width, region.height);
+    x.update_cache();
+
+    let (_, grep_area) = region.split_horizontal(view::LINE_NUMBER_W);
+
+    let grep_view = view::ToView::new(&x.rb.buf);
+    let grep_view = view::OverlayView::new(grep_view, AddColor { x });
+
+    let add_cursor = view::AddCursor::new(x.rb.cursor);
+    let grep_view = view::OverlayView::new(grep_view, add_cursor);
+
+    let grep_view = view::TranslateView::new(
+        grep_view,
+        grep_area.col as i32 - x.rb.window.col() as i32,
+        grep_area.row as i32 - x.rb.window.row() as i32,
+    );
+
+    let lineno_range = x.rb.lineno_range();
+    let lineno_view = view::LineNumber {
+        from: lineno_range.start+1,
+        to: lineno_range.end,
+    };
+
+    let view = view::MergeHorizontal {
+        left: lineno_view,
+        right: grep_view,
+        col_offset: grep_area.col,
+    };
+
+    let view = view::CloneView::new(view, region);
+    Box::new(view)
+}
+impl view::ViewGen for ViewGen {
+    fn gen(&mut self, region: view::Area) -> Box<dyn view::View> {
+        gen_impl(&mut self.x.borrow_mut(), region)
+    }
+}
+
+pub struct Page {
+    controller: Box<dyn controller::Controller>,
+    view_gen: Box<dyn view::ViewGen>,
+    x: Rc<RefCell<Grep>>,
+    // cached for id(). see directory::Page
+    root: PathBuf,
+    query: String,
+}
+impl Page {
+    pub fn new(x: Rc<RefCell<Grep>>) -> Self {
+        let root = x.borrow().root();
+        let query = x.borrow().query();
+        Self {
+            controller: Box::new(mk_controller(x.clone())),
+            view_gen: Box::new(ViewGen { x: x.clone() }),
+            x,
+            root,
+            query,
+        }
+    }
+}
+impl navigator::Page for Page {
+    fn controller(&self) -> &Box<dyn controller::Controller> {
+        &self.controller
+    }
+    fn view_gen(&mut self) -> &mut Box<dyn view::ViewGen> {
+        &mut self.view_gen
+    }
+    fn status(&self) -> String {
+        let x = self.x.borrow();
+        let state: &str = match x.state.get().as_str() {
+            read_buffer::INIT => "*",
+            read_buffer::SEARCH => "/",
+            _ => "*",
+        };
+        format!("[Grep -{}-] {} in {} ({} hits){}", state, self.query, self.root.to_str().unwrap(), x.hits.len(), x.rb.search_status())
+    }
+    fn kind(&self) -> navigator::PageKind {
+        navigator::PageKind::Grep
+    }
+    fn id(&self) -> String {
+        format!("grep:{}:{}", self.root.to_str().unwrap(), self.query)
+    }
+    fn message(&self) -> MessageBox {
+        self.x.borrow().message_box.clone()
+    }
+}
+
+/// Opens the page of the lines matching the query under the directory.
+pub fn open(root: &Path, query: &str, mode: Mode, show_hidden: bool, navigator: Rc<RefCell<Navigator>>) {
+    let x = Rc::new(RefCell::new(Grep::open(root, query, mode, show_hidden, navigator.clone())));
+    let page = Rc::new(RefCell::new(Page::new(x)));
+    navigator.borrow_mut().push(page);
+}
+
+#[test]
+fn test_search_dir() {
+    use std::fs;
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::write(root.join(".gitignore"), "ignored.txt\n").unwrap();
+    fs::write(root.join("a.txt"), "foo\nbar Foo\n").unwrap();
+    fs::write(root.join("ignored.txt"), "foo\n").unwrap();
+    fs::write(root.join(".hidden"), "foo\n").unwrap();
+    fs::create_dir(root.join("sub")).unwrap();
+    fs::write(root.join("sub").join("b.txt"), "x  foo\n").unwrap();
+
+    let re = pattern("foo", Mode::Literal).unwrap();
+    let hits = search_dir(root, &re, false);
+    let found: Vec<(String, usize, usize)> = hits.iter().map(|h| (h.path.to_str().unwrap().to_owned(), h.row, h.col)).collect();
+    assert_eq!(found, vec![
+        ("a.txt".to_owned(), 0, 0),
+        ("a.txt".to_owned(), 1, 4),
+        ("sub/b.txt".to_owned(), 0, 3),
+    ]);
+
+    let hits = search_dir(root, &re, true);
+    assert!(hits.iter().any(|h| h.path == Path::new(".hidden")));
+    assert!(!hits.iter().any(|h| h.path == Path::new("ignored.txt")));
+
+    let re = pattern("Foo", Mode::SmartCase).unwrap();
+    assert_eq!(search_dir(root, &re, false).len(), 1);
+    let re = pattern("^f.o$", Mode::Regex).unwrap();
+    assert_eq!(search_dir(root, &re, false).len(), 1);
+}
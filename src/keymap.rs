@@ -0,0 +1,319 @@
+use std::rc::Rc;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use crate::controller::{self, Effect, Graph};
+use crate::navigator::PageKind;
+use crate::Key;
+
+// [keys.<page>.<state>] in the config binds a key sequence to an effect.
+//
+// [keys.buffer.Normal]
+// "g g" = "Move 0"  # calls the effect with the key 0
+// "U" = "Redo"
+//
+// The state is one of the page (e.g. Normal for read_buffer::INIT).
+// The effect is looked up by the name among the effects of the page.
+// The key after the name is passed to the effect, which is needed
+// when the effect is bound to many keys like Move.
+
+lazy_static! {
+    static ref ERRORS: Mutex<Vec<String>> = Mutex::new(vec![]);
+    static ref GLOBAL: Vec<(Vec<Key>, Global)> = global_keys();
+}
+
+/// Handled by the editor before the page.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Global {
+    Quit,
+    Navigator,
+    Split,
+    VSplit,
+    FocusNext,
+    CloseWindow,
+    Grow,
+    Shrink,
+}
+impl Global {
+    fn from_name(name: &str) -> Option<Self> {
+        let x = match name {
+            "Quit" => Global::Quit,
+            "Navigator" => Global::Navigator,
+            "Split" => Global::Split,
+            "VSplit" => Global::VSplit,
+            "FocusNext" => Global::FocusNext,
+            "CloseWindow" => Global::CloseWindow,
+            "Grow" => Global::Grow,
+            "Shrink" => Global::Shrink,
+            _ => return None,
+        };
+        Some(x)
+    }
+}
+
+/// The editor-level keys typed so far.
+pub enum GlobalMatch {
+    Action(Global),
+    Prefix,
+    None,
+}
+
+/// "a", "C-a", "M-a", "Esc", "Enter", "Space", "Tab", "BS", "Up" ...
+pub fn parse_key(s: &str) -> Result<Key, String> {
+    let single = |x: &str| {
+        let mut it = x.chars();
+        match (it.next(), it.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    let k = match s {
+        "Esc" => Key::Esc,
+        "Enter" | "EOL" => Key::Char('\n'),
+        "Space" => Key::Char(' '),
+        "Tab" => Key::Char('\t'),
+        "BS" => Key::Backspace,
+        "Left" => Key::Left,
+        "Right" => Key::Right,
+        "Up" => Key::Up,
+        "Down" => Key::Down,
+        "Home" => Key::Home,
+        "End" => Key::End,
+        "PageUp" => Key::PageUp,
+        "PageDown" => Key::PageDown,
+        "Insert" => Key::Insert,
+        "Delete" => Key::Delete,
+        x if x.starts_with("C-") && single(&x[2..]).is_some() => Key::Ctrl(single(&x[2..]).unwrap()),
+        x if x.starts_with("M-") && single(&x[2..]).is_some() => Key::Alt(single(&x[2..]).unwrap()),
+        x if single(x).is_some() => Key::Char(single(x).unwrap()),
+        x => return Err(format!("unknown key: {}", x)),
+    };
+    Ok(k)
+}
+
+/// Keys separated by spaces.
+pub fn parse_keys(s: &str) -> Result<Vec<Key>, String> {
+    let v = s.split_whitespace().map(parse_key).collect::<Result<Vec<_>, _>>()?;
+    if v.is_empty() {
+        return Err("empty key sequence".to_owned())
+    }
+    Ok(v)
+}
+
+// calls the effect with the fixed key or the key pressed if None
+struct Bound {
+    eff: Rc<dyn Effect>,
+    key: Option<Key>,
+}
+impl Effect for Bound {
+    fn run(&self, k: Key) -> String {
+        self.eff.run(self.key.clone().unwrap_or(k))
+    }
+}
+
+// moves to the state waiting for the rest of the sequence (or back)
+struct Prefix(String);
+impl Effect for Prefix {
+    fn run(&self, _: Key) -> String {
+        self.0.clone()
+    }
+}
+
+fn lookup(g: &Graph, spec: &str) -> Result<Bound, String> {
+    let mut it = spec.split_whitespace();
+    let name = it.next().ok_or("no effect")?;
+    let key = match it.next() {
+        Some(x) => Some(parse_key(x)?),
+        None => None,
+    };
+    let found = g.find_named(name);
+    if found.is_empty() {
+        return Err(format!("unknown effect: {}", name))
+    }
+    if let Some(k) = key {
+        let (_, eff) = found.into_iter().find(|(m, _)| controller::matches(m, &k)).ok_or(format!("{} isn't bound to {}", name, spec))?;
+        return Ok(Bound { eff, key: Some(k) })
+    }
+    let (m, eff) = found[0].clone();
+    if found.iter().any(|(x, _)| *x != m) {
+        return Err(format!("{} is bound to many keys. give one like \"{} <key>\"", name, name))
+    }
+    let key = match m {
+        Key::CharRange(_, _) | Key::Otherwise => None,
+        m => Some(m),
+    };
+    Ok(Bound { eff, key })
+}
+
+// the keys and the effect to bind
+fn resolve(g: &Graph, state: &str, seq: &str, spec: &str) -> Result<(Vec<Key>, Bound), String> {
+    if !g.has_state(state) {
+        return Err(format!("unknown state: {}", state))
+    }
+    let keys = parse_keys(seq)?;
+    let bound = lookup(g, spec)?;
+    Ok((keys, bound))
+}
+
+/// Binds the sequence to the effect in the graph.
+/// The prefix of the sequence overrides the same key in the state.
+pub fn bind(g: &mut Graph, state: &str, seq: &str, spec: &str) -> Result<(), String> {
+    let (keys, bound) = resolve(g, state, seq, spec)?;
+    let names: Vec<&str> = seq.split_whitespace().collect();
+    let mut from = state.to_owned();
+    for (i, k) in keys.iter().enumerate() {
+        let last = i + 1 == keys.len();
+        // e.g. Normal+g for "g g"
+        let to = format!("{}+{}", state, names[..i+1].join("+"));
+        if last {
+            g.add_edge(&from, k.clone(), Rc::new(Bound { eff: bound.eff.clone(), key: bound.key.clone() }));
+        } else {
+            g.add_edge(&from, k.clone(), Rc::new(Prefix(to.clone())));
+        }
+        if i > 0 {
+            // the other keys cancel the sequence. this must be the last edge.
+            g.add_edge(&from, Key::Otherwise, Rc::new(Prefix(state.to_owned())));
+        }
+        from = to;
+    }
+    Ok(())
+}
+
+/// Applies the bindings of the page in the config.
+/// The errors are shown in the message box by the editor.
+pub fn apply(g: &mut Graph, page: PageKind) {
+    for (state, seq, spec) in crate::config::SINGLETON.keys(page.name()) {
+        if let Err(e) = bind(g, state, seq, spec) {
+            ERRORS.lock().unwrap().push(format!("[keys.{}.{}] \"{}\": {}", page.name(), state, seq, e));
+        }
+    }
+}
+
+fn global_keys() -> Vec<(Vec<Key>, Global)> {
+    let mut v = vec![];
+    for (_, seq, name) in crate::config::SINGLETON.keys("editor") {
+        let x = match Global::from_name(name) {
+            Some(x) => x,
+            None => {
+                ERRORS.lock().unwrap().push(format!("[keys.editor] \"{}\": unknown effect: {}", seq, name));
+                continue
+            },
+        };
+        match parse_keys(seq) {
+            Ok(keys) => v.push((keys, x)),
+            Err(e) => ERRORS.lock().unwrap().push(format!("[keys.editor] \"{}\": {}", seq, e)),
+        }
+    }
+    v
+}
+
+/// The editor-level action of the keys.
+pub fn global(keys: &[Key]) -> GlobalMatch {
+    if let Some((_, g)) = GLOBAL.iter().find(|(x, _)| x.as_slice() == keys) {
+        return GlobalMatch::Action(*g)
+    }
+    if GLOBAL.iter().any(|(x, _)| x.starts_with(keys)) {
+        return GlobalMatch::Prefix
+    }
+    GlobalMatch::None
+}
+
+/// Takes the errors in applying the config.
+pub fn take_errors() -> Vec<String> {
+    std::mem::take(&mut *ERRORS.lock().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::{Controller, ControllerFSM};
+    use std::cell::RefCell;
+
+    struct Push(Rc<RefCell<Vec<Key>>>);
+    impl Effect for Push {
+        fn run(&self, k: Key) -> String {
+            self.0.borrow_mut().push(k);
+            "Init".to_owned()
+        }
+        fn name(&self) -> &'static str {
+            "Push"
+        }
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(parse_keys("g g"), Ok(vec![Key::Char('g'), Key::Char('g')]));
+        assert_eq!(parse_keys("C-x Space Enter"), Ok(vec![Key::Ctrl('x'), Key::Char(' '), Key::Char('\n')]));
+        assert!(parse_keys("Foo").is_err());
+        assert!(parse_keys("").is_err());
+    }
+
+    #[test]
+    fn test_bind() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut g = Graph::new();
+        g.add_edge("Init", Key::Char('a'), Rc::new(Push(log.clone())));
+        g.add_edge("Init", Key::Char('b'), Rc::new(Push(log.clone())));
+        assert!(bind(&mut g, "Init", "x", "Push").is_err());
+        assert!(bind(&mut g, "Init", "x", "Pull").is_err());
+        bind(&mut g, "Init", "g g", "Push b").unwrap();
+        bind(&mut g, "Init", "g h", "Push a").unwrap();
+        bind(&mut g, "Init", "z z z", "Push a").unwrap();
+
+        let ctrl = ControllerFSM::new("Init", Box::new(g));
+        for c in "ggzhghazzgzzza".chars() {
+            ctrl.receive(Key::Char(c));
+        }
+        // h after z and g after zz cancel the sequences
+        assert_eq!(*log.borrow(), vec![Key::Char('b'), Key::Char('a'), Key::Char('a'), Key::Char('a'), Key::Char('a')]);
+    }
+
+    #[test]
+    fn test_unknown_state() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut g = Graph::new();
+        g.add_edge("Init", Key::Char('a'), Rc::new(Push(log.clone())));
+        assert_eq!(bind(&mut g, "Normal", "x", "Push"), Err("unknown state: Normal".to_owned()));
+    }
+
+    #[test]
+    fn test_default_keys() {
+        use crate::navigator::{self, Navigator};
+        use crate::{directory, edit_buffer, finder, git, grep, large_file, read_buffer, registers};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        std::fs::write(&path, "a\n").unwrap();
+        std::process::Command::new("git").args(["init", "-q"]).current_dir(dir.path()).status().unwrap();
+        let navigator = Rc::new(RefCell::new(Navigator::new()));
+        fn x<T>(p: T) -> Rc<RefCell<T>> {
+            Rc::new(RefCell::new(p))
+        }
+        let repo = git::Repo::discover(dir.path()).unwrap();
+        let pages = [
+            (PageKind::Buffer, edit_buffer::mk_controller(x(edit_buffer::EditBuffer::open(&path, navigator.clone())))),
+            (PageKind::Directory, directory::mk_controller(x(directory::Directory::open(dir.path(), navigator.clone())))),
+            (PageKind::Navigator, navigator::mk_controller(navigator.clone())),
+            (PageKind::GitStatus, git::status::mk_controller(x(git::status::GitStatus::open(repo, navigator.clone())))),
+            (PageKind::Grep, grep::mk_controller(x(grep::Grep::open(dir.path(), "a", read_buffer::search::Mode::Literal, false, navigator.clone())))),
+            (PageKind::Finder, finder::mk_controller(x(finder::Finder::open(dir.path(), false, navigator.clone())))),
+            (PageKind::Registers, registers::mk_controller(x(registers::Registers::open(navigator.clone())))),
+            (PageKind::LargeFile, large_file::mk_controller(x(large_file::LargeFile::open(&path).unwrap()))),
+        ];
+        for (page, state, seq, spec) in crate::config::default_keys() {
+            if page == "editor" {
+                assert!(Global::from_name(&spec).is_some(), "{}", spec);
+                parse_keys(&seq).unwrap();
+                continue
+            }
+            let (_, ctrl) = pages.iter().find(|(k, _)| k.name() == page).unwrap();
+            if let Err(e) = resolve(ctrl.graph(), &state, &seq, &spec) {
+                panic!("[keys.{}.{}] \"{}\": {}", page, state, seq, e);
+            }
+        }
+
+        // the example in the docs
+        let (_, buffer) = &pages[0];
+        assert!(resolve(buffer.graph(), read_buffer::INIT, "g g", "Move 0").is_ok());
+        assert!(resolve(buffer.graph(), "Init", "g g", "Move 0").is_err());
+    }
+}
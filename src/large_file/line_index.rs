@@ -0,0 +1,298 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use memchr::memmem;
+use crate::read_buffer::{BufElem, Cursor, Storage};
+use super::mmap::Mmap;
+
+// The rows of the mapped file are found as they are needed (e.g. the window moves down)
+// and decoded only when they are shown. So opening a huge file costs nothing
+// and the memory used is about the offsets of the rows seen so far.
+
+const SCAN_CHUNK: usize = 1 << 20;
+/// Longer lines are cut.
+const MAX_LINE_BYTES: usize = 1 << 16;
+const HEX_WIDTH: usize = 16;
+/// The col of the first byte in a hex row: "00000010  "
+const HEX_COL: usize = 10;
+
+/// A NUL in the head of the file.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..std::cmp::min(bytes.len(), 8000)].contains(&0)
+}
+
+/// The chars and their offsets in the bytes.
+/// Invalid sequences are U+FFFD like String::from_utf8_lossy
+/// and the control chars but tabs are shown as dots.
+fn decode(bytes: &[u8]) -> Vec<(usize, char)> {
+    let mut v = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let (valid, skip) = match std::str::from_utf8(&bytes[i..]) {
+            Ok(_) => (bytes.len() - i, None),
+            Err(e) => (e.valid_up_to(), Some(e.error_len().unwrap_or(bytes.len() - i - e.valid_up_to()))),
+        };
+        let s = std::str::from_utf8(&bytes[i..i+valid]).unwrap();
+        v.extend(s.char_indices().map(|(j, c)| {
+            let c = if c.is_control() && c != '\t' { '.' } else { c };
+            (i + j, c)
+        }));
+        i += valid;
+        if let Some(n) = skip {
+            v.push((i, '\u{FFFD}'));
+            i += n;
+        }
+    }
+    v
+}
+
+pub struct LineIndex {
+    map: Mmap,
+    starts: Vec<usize>, // the offsets of the rows found so far
+    scanned: usize,
+    hex: bool,
+    // the decoded rows. cleared by clear_cache
+    cache: RefCell<HashMap<usize, Box<[BufElem]>>>,
+}
+impl LineIndex {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            map: Mmap::open(path)?,
+            starts: vec![0],
+            scanned: 0,
+            hex: false,
+            cache: RefCell::new(HashMap::new()),
+        })
+    }
+    pub fn bytes(&self) -> &[u8] {
+        &self.map
+    }
+    pub fn is_hex(&self) -> bool {
+        self.hex
+    }
+    pub fn set_hex(&mut self, hex: bool) {
+        self.hex = hex;
+        self.clear_cache();
+    }
+    /// False if there may be more rows than len().
+    pub fn is_complete(&self) -> bool {
+        self.hex || self.scanned == self.map.len()
+    }
+    fn scan(&mut self) {
+        let end = std::cmp::min(self.scanned + SCAN_CHUNK, self.map.len());
+        for i in self.scanned..end {
+            if self.map[i] == b'\n' {
+                self.starts.push(i + 1);
+            }
+        }
+        self.scanned = end;
+        // the newline at the end doesn't start a row
+        if self.scanned == self.map.len() && self.starts.len() > 1 && *self.starts.last().unwrap() == self.map.len() {
+            self.starts.pop();
+        }
+    }
+    /// Finds the rows up to the row if exist.
+    pub fn index_to(&mut self, row: usize) {
+        while !self.is_complete() && self.len() <= row {
+            self.scan();
+        }
+    }
+    /// Finds the rows up to the one containing the offset.
+    fn index_to_offset(&mut self, offset: usize) {
+        while !self.is_complete() && self.scanned <= offset {
+            self.scan();
+        }
+    }
+    pub fn index_all(&mut self) {
+        while !self.is_complete() {
+            self.scan();
+        }
+    }
+    /// Drops the decoded rows.
+    pub fn clear_cache(&mut self) {
+        self.cache.get_mut().clear();
+    }
+    // the bytes of the row without the newline
+    fn row_bytes(&self, row: usize) -> &[u8] {
+        let start = self.starts[row];
+        let mut end = match self.starts.get(row + 1) {
+            Some(&next) => next - 1,
+            None => match self.map[start..].iter().position(|&b| b == b'\n') {
+                Some(i) => start + i,
+                None => self.map.len(),
+            },
+        };
+        if end > start && self.map[end - 1] == b'\r' {
+            end -= 1;
+        }
+        &self.map[start..std::cmp::min(end, start + MAX_LINE_BYTES)]
+    }
+    fn decode_row(&self, row: usize) -> Box<[BufElem]> {
+        let mut v: Vec<BufElem> = if self.hex {
+            self.hex_row(row).chars().map(BufElem::Char).collect()
+        } else {
+            decode(self.row_bytes(row)).into_iter().map(|(_, c)| BufElem::Char(c)).collect()
+        };
+        v.push(BufElem::Eol);
+        v.into_boxed_slice()
+    }
+    // 00000010  41 42 43 ...  |ABC...|
+    fn hex_row(&self, row: usize) -> String {
+        let start = row * HEX_WIDTH;
+        let chunk = &self.map[start..std::cmp::min(start + HEX_WIDTH, self.map.len())];
+        let mut s = format!("{:08x}  ", start);
+        for i in 0..HEX_WIDTH {
+            match chunk.get(i) {
+                Some(b) => s.push_str(&format!("{:02x} ", b)),
+                None => s.push_str("   "),
+            }
+        }
+        s.push_str(" |");
+        for &b in chunk {
+            s.push(if (0x20..0x7f).contains(&b) { b as char } else { '.' });
+        }
+        s.push('|');
+        s
+    }
+    /// The offset of the byte at the cursor.
+    pub fn offset_of(&self, cur: Cursor) -> usize {
+        if self.hex {
+            let i = std::cmp::min(cur.col.saturating_sub(HEX_COL) / 3, HEX_WIDTH - 1);
+            return std::cmp::min(cur.row * HEX_WIDTH + i, self.map.len())
+        }
+        let start = self.starts[cur.row];
+        let line = self.row_bytes(cur.row);
+        match decode(line).get(cur.col) {
+            Some((i, _)) => start + i,
+            None => start + line.len(),
+        }
+    }
+    /// The cursor on the byte.
+    pub fn cursor_at(&mut self, offset: usize) -> Cursor {
+        if self.hex {
+            return Cursor { row: offset / HEX_WIDTH, col: HEX_COL + offset % HEX_WIDTH * 3 }
+        }
+        self.index_to_offset(offset);
+        let row = self.starts.partition_point(|&x| x <= offset) - 1;
+        let line = self.row_bytes(row);
+        let col = decode(line).iter().take_while(|(i, _)| self.starts[row] + i < offset).count();
+        Cursor { row, col }
+    }
+    /// The next (or previous) occurrence of the pattern from the cursor. Wraps around the file.
+    pub fn find(&mut self, cur: Cursor, pat: &[u8], forward: bool) -> Option<Cursor> {
+        if pat.is_empty() {
+            return None
+        }
+        let from = self.offset_of(cur);
+        let bytes = self.bytes();
+        let len = bytes.len();
+        // memmem scans with SIMD so even a huge file takes a moment
+        let hit = if forward {
+            let finder = memmem::Finder::new(pat);
+            let after = std::cmp::min(from + 1, len);
+            finder.find(&bytes[after..]).map(|i| after + i)
+                .or_else(|| finder.find(&bytes[..std::cmp::min(from + pat.len(), len)]))
+        } else {
+            let finder = memmem::FinderRev::new(pat);
+            let before = std::cmp::min((from + pat.len()).saturating_sub(1), len);
+            finder.rfind(&bytes[..before])
+                .or_else(|| finder.rfind(&bytes[from..]).map(|i| from + i))
+        };
+        hit.map(|i| self.cursor_at(i))
+    }
+}
+
+impl Storage for LineIndex {
+    /// The rows found so far.
+    fn len(&self) -> usize {
+        if self.hex {
+            return std::cmp::max(self.map.len().div_ceil(HEX_WIDTH), 1)
+        }
+        if self.scanned == self.map.len() {
+            self.starts.len()
+        } else {
+            // the last one may continue
+            self.starts.len() - 1
+        }
+    }
+    fn line(&self, row: usize) -> &[BufElem] {
+        let mut cache = self.cache.borrow_mut();
+        let line: &[BufElem] = cache.entry(row).or_insert_with(|| self.decode_row(row));
+        let ptr: *const [BufElem] = line;
+        drop(cache);
+        // the boxed rows don't move when the map grows
+        // and are dropped only by clear_cache which takes &mut self.
+        unsafe { &*ptr }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(bytes: &[u8]) -> (tempfile::TempDir, LineIndex) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a");
+        std::fs::write(&path, bytes).unwrap();
+        let index = LineIndex::open(&path).unwrap();
+        (dir, index)
+    }
+
+    fn text(index: &LineIndex, row: usize) -> String {
+        index.line(row).iter().filter_map(|e| match e {
+            BufElem::Char(c) => Some(*c),
+            BufElem::Eol => None,
+        }).collect()
+    }
+
+    #[test]
+    fn test_index() {
+        let (_dir, mut index) = open(b"ab\r\nc\xffd\n\ne\n");
+        assert_eq!(index.len(), 0);
+        index.index_to(1);
+        assert!(index.is_complete());
+        assert_eq!(index.len(), 4);
+        assert_eq!(text(&index, 0), "ab");
+        assert_eq!(text(&index, 1), "c\u{FFFD}d");
+        assert_eq!(text(&index, 2), "");
+        assert_eq!(text(&index, 3), "e");
+
+        let (_dir, mut index) = open(b"");
+        index.index_all();
+        assert_eq!(index.len(), 1);
+        assert_eq!(text(&index, 0), "");
+    }
+
+    #[test]
+    fn test_find() {
+        let (_dir, mut index) = open("aé\nxyz\nxyz".as_bytes());
+        let cur = Cursor { row: 0, col: 0 };
+        let hit = index.find(cur, b"xyz", true).unwrap();
+        assert_eq!(hit, Cursor { row: 1, col: 0 });
+        let hit = index.find(hit, b"xyz", true).unwrap();
+        assert_eq!(hit, Cursor { row: 2, col: 0 });
+        // wraps around
+        let hit = index.find(hit, b"xyz", true).unwrap();
+        assert_eq!(hit, Cursor { row: 1, col: 0 });
+        let hit = index.find(hit, b"xyz", false).unwrap();
+        assert_eq!(hit, Cursor { row: 2, col: 0 });
+        let hit = index.find(hit, b"xyz", false).unwrap();
+        assert_eq!(hit, Cursor { row: 1, col: 0 });
+        assert_eq!(index.find(cur, "é".as_bytes(), true), Some(Cursor { row: 0, col: 1 }));
+        assert_eq!(index.find(cur, b"none", true), None);
+    }
+
+    #[test]
+    fn test_hex() {
+        let (_dir, mut index) = open(b"\x00\x01ABCDEFGHIJKLMNOPQ");
+        assert!(is_binary(index.bytes()));
+        index.set_hex(true);
+        assert_eq!(index.len(), 2);
+        assert_eq!(text(&index, 0), "00000000  00 01 41 42 43 44 45 46 47 48 49 4a 4b 4c 4d 4e  |..ABCDEFGHIJKLMN|");
+        assert_eq!(text(&index, 1), format!("00000010  4f 50 51{} |OPQ|", " ".repeat(40)));
+        let hit = index.find(Cursor { row: 0, col: 0 }, b"P", true).unwrap();
+        assert_eq!(hit, Cursor { row: 1, col: 13 });
+        assert_eq!(index.offset_of(hit), 17);
+    }
+}
@@ -0,0 +1,63 @@
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+
+/// The bytes of a file mapped read-only.
+/// The kernel reads the pages on access so mapping a huge file is instant.
+/// Like any other mmap user, truncating the file by others while it's mapped may crash.
+pub enum Mmap {
+    #[cfg(unix)]
+    Mapped(*const u8, usize),
+    // an empty file can't be mapped
+    Owned(Vec<u8>),
+}
+impl Mmap {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Ok(Mmap::Owned(vec![]))
+        }
+        map(&file, len)
+    }
+}
+
+#[cfg(unix)]
+fn map(file: &File, len: usize) -> io::Result<Mmap> {
+    use std::os::unix::io::AsRawFd;
+    let ptr = unsafe { libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0) };
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error())
+    }
+    Ok(Mmap::Mapped(ptr as *const u8, len))
+}
+
+#[cfg(not(unix))]
+fn map(file: &File, len: usize) -> io::Result<Mmap> {
+    use std::io::Read;
+    let mut v = Vec::with_capacity(len);
+    let mut file = file;
+    file.read_to_end(&mut v)?;
+    Ok(Mmap::Owned(v))
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match self {
+            #[cfg(unix)]
+            Mmap::Mapped(ptr, len) => unsafe { std::slice::from_raw_parts(*ptr, *len) },
+            Mmap::Owned(v) => v,
+        }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Mmap::Mapped(ptr, len) = self {
+            unsafe { libc::munmap(*ptr as *mut libc::c_void, *len) };
+        }
+    }
+}
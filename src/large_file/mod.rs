@@ -0,0 +1,341 @@
+mod mmap;
+mod line_index;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::controller::{self, PageState};
+use crate::view;
+use crate::navigator;
+use crate::read_buffer::{Cursor, Storage};
+use crate::read_buffer::visibility_window::VisibilityWindow;
+use crate::message_box::MessageBox;
+use crate::read_buffer::{INIT, SEARCH, JUMP};
+use self::line_index::LineIndex;
+
+pub use self::line_index::is_binary;
+
+/// Files at least this large or binary are opened here instead of the edit buffer.
+pub fn is_large(path: &Path) -> bool {
+    let meta = match fs::metadata(path) {
+        Ok(x) if x.is_file() => x,
+        _ => return false,
+    };
+    if meta.len() >= crate::config::SINGLETON.large_file_threshold() {
+        return true
+    }
+    let mut head = vec![0; 8000];
+    let n = fs::File::open(path).and_then(|mut f| io::Read::read(&mut f, &mut head)).unwrap_or(0);
+    is_binary(&head[..n])
+}
+
+/// Shows the mapped file read-only. No highlighting.
+/// Binary files are shown in hex.
+pub struct LargeFile {
+    path: PathBuf,
+    index: LineIndex,
+    pub cursor: Cursor,
+    window: VisibilityWindow,
+    num_buffer: Vec<char>,
+    search_word: Vec<char>,
+    state: PageState,
+    message_box: MessageBox,
+}
+impl LargeFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut index = LineIndex::open(path)?;
+        index.set_hex(is_binary(index.bytes()));
+        index.index_to(0);
+        Ok(Self {
+            path: path.to_owned(),
+            index,
+            cursor: Cursor { row: 0, col: 0 },
+            window: VisibilityWindow::new(Cursor { row: 0, col: 0 }),
+            num_buffer: vec![],
+            search_word: vec![],
+            state: PageState::new(INIT.to_owned()),
+            message_box: MessageBox::new(),
+        })
+    }
+    fn stabilize_cursor(&mut self) {
+        self.index.index_to(self.cursor.row);
+        let max_row = self.index.len() - 1;
+        if self.cursor.row > max_row {
+            self.cursor.row = max_row;
+        }
+        let max_col = self.index.line(self.cursor.row).len() - 1;
+        if self.cursor.col > max_col {
+            self.cursor.col = max_col;
+        }
+    }
+    pub fn jump_to(&mut self, row: usize) {
+        self.cursor = Cursor { row, col: 0 };
+        self.stabilize_cursor();
+    }
+    /// Maps the file again. The cursor stays.
+    pub fn reload(&mut self) {
+        let hex = self.index.is_hex();
+        match LineIndex::open(&self.path) {
+            Ok(index) => self.index = index,
+            Err(e) => {
+                self.message_box.send(&format!("Failed to reload {}: {}", self.path.to_str().unwrap(), e));
+                return
+            },
+        }
+        self.index.set_hex(hex);
+        self.stabilize_cursor();
+    }
+    fn take_count(&mut self) -> Option<usize> {
+        let s: String = self.num_buffer.drain(..).collect();
+        s.parse().ok()
+    }
+    fn search(&mut self, forward: bool) {
+        let word: String = self.search_word.iter().collect();
+        match self.index.find(self.cursor, word.as_bytes(), forward) {
+            Some(cur) => self.cursor = cur,
+            None => self.message_box.send(&format!("Pattern not found: {}", word)),
+        }
+    }
+
+    //
+    // eff functions
+    //
+
+    fn eff_move(&mut self, k: Key) -> String {
+        let count = self.take_count();
+        if k == Key::Char('G') {
+            match count {
+                Some(n) => self.jump_to(std::cmp::max(n, 1) - 1),
+                None => {
+                    self.index.index_all();
+                    self.jump_to(self.index.len() - 1);
+                },
+            }
+            return INIT.to_owned()
+        }
+        for _ in 0..count.unwrap_or(1) {
+            match k {
+                Key::Char('j') => self.cursor.row += 1,
+                Key::Char('k') => self.cursor.row = self.cursor.row.saturating_sub(1),
+                Key::Char('h') => self.cursor.col = self.cursor.col.saturating_sub(1),
+                Key::Char('l') => self.cursor.col += 1,
+                Key::Char('0') => self.cursor.col = 0,
+                Key::Char('$') => self.cursor.col = usize::MAX,
+                Key::Char('n') => self.search(true),
+                Key::Char('N') => self.search(false),
+                _ => {},
+            }
+            self.stabilize_cursor();
+        }
+        INIT.to_owned()
+    }
+    fn eff_jump_page_forward(&mut self, _: Key) -> String {
+        self.cursor.row += self.window.height();
+        self.stabilize_cursor();
+        INIT.to_owned()
+    }
+    fn eff_jump_page_backward(&mut self, _: Key) -> String {
+        self.cursor.row = self.cursor.row.saturating_sub(self.window.height());
+        self.stabilize_cursor();
+        INIT.to_owned()
+    }
+    fn eff_enter_jump_mode(&mut self, k: Key) -> String {
+        self.num_buffer.clear();
+        self.eff_acc_jump_num(k)
+    }
+    fn eff_acc_jump_num(&mut self, k: Key) -> String {
+        if let Key::Char(c) = k {
+            self.num_buffer.push(c);
+        }
+        JUMP.to_owned()
+    }
+    fn eff_cancel(&mut self, _: Key) -> String {
+        self.num_buffer.clear();
+        self.message_box.send("");
+        INIT.to_owned()
+    }
+    fn eff_enter_search_mode(&mut self, _: Key) -> String {
+        self.search_word.clear();
+        self.message_box.send("/");
+        SEARCH.to_owned()
+    }
+    fn eff_search_mode_input(&mut self, k: Key) -> String {
+        match k {
+            Key::Backspace => { self.search_word.pop(); },
+            Key::Char(c) => self.search_word.push(c),
+            _ => {},
+        }
+        self.message_box.send(&format!("/{}", self.search_word.iter().collect::<String>()));
+        SEARCH.to_owned()
+    }
+    fn eff_leave_search_mode(&mut self, _: Key) -> String {
+        self.search(true);
+        INIT.to_owned()
+    }
+    // the cursor stays on the same byte
+    fn eff_toggle_hex(&mut self, _: Key) -> String {
+        let offset = self.index.offset_of(self.cursor);
+        let hex = !self.index.is_hex();
+        self.index.set_hex(hex);
+        self.cursor = self.index.cursor_at(offset);
+        self.stabilize_cursor();
+        INIT.to_owned()
+    }
+    fn eff_reload(&mut self, _: Key) -> String {
+        self.reload();
+        INIT.to_owned()
+    }
+}
+
+use crate::controller::Effect;
+use crate::def_effect;
+use crate::Key;
+
+def_effect!(Move, LargeFile, eff_move);
+def_effect!(JumpPageForward, LargeFile, eff_jump_page_forward);
+def_effect!(JumpPageBackward, LargeFile, eff_jump_page_backward);
+def_effect!(EnterJumpMode, LargeFile, eff_enter_jump_mode);
+def_effect!(AccJumpNum, LargeFile, eff_acc_jump_num);
+def_effect!(Cancel, LargeFile, eff_cancel);
+def_effect!(EnterSearchMode, LargeFile, eff_enter_search_mode);
+def_effect!(SearchModeInput, LargeFile, eff_search_mode_input);
+def_effect!(LeaveSearchMode, LargeFile, eff_leave_search_mode);
+def_effect!(ToggleHex, LargeFile, eff_toggle_hex);
+def_effect!(Reload, LargeFile, eff_reload);
+
+pub fn mk_controller(x: Rc<RefCell<LargeFile>>) -> controller::ControllerFSM {
+    use crate::Key::*;
+    let mut g = controller::Graph::new();
+
+    let moves = [Char('j'), Char('k'), Char('h'), Char('l'), Char('0'), Char('$'), Char('G'), Char('n'), Char('N')];
+    for k in &moves {
+        g.add_edge(INIT, k.clone(), Rc::new(Move(x.clone())));
+    }
+    g.add_edge(INIT, Ctrl('f'), Rc::new(JumpPageForward(x.clone())));
+    g.add_edge(INIT, Ctrl('b'), Rc::new(JumpPageBackward(x.clone())));
+    g.add_edge(INIT, Char('x'), Rc::new(ToggleHex(x.clone())));
+    g.add_edge(INIT, Ctrl('r'), Rc::new(Reload(x.clone())));
+    g.add_edge(INIT, Esc, Rc::new(Cancel(x.clone())));
+
+    // count (5j, 10G)
+    g.add_edge(INIT, CharRange('1', '9'), Rc::new(EnterJumpMode(x.clone())));
+    g.add_edge(JUMP, CharRange('0', '9'), Rc::new(AccJumpNum(x.clone())));
+    for k in &moves {
+        g.add_edge(JUMP, k.clone(), Rc::new(Move(x.clone())));
+    }
+    g.add_edge(JUMP, Esc, Rc::new(Cancel(x.clone())));
+
+    // the bytes of the word are searched
+    g.add_edge(INIT, Char('/'), Rc::new(EnterSearchMode(x.clone())));
+    g.add_edge(SEARCH, Char('\n'), Rc::new(LeaveSearchMode(x.clone())));
+    g.add_edge(SEARCH, Esc, Rc::new(Cancel(x.clone())));
+    g.add_edge(SEARCH, Otherwise, Rc::new(SearchModeInput(x.clone())));
+    crate::keymap::apply(&mut g, navigator::PageKind::LargeFile);
+    controller::ControllerFSM::new(INIT, Box::new(g))
+}
+
+struct ViewGen {
+    x: Rc<RefCell<LargeFile>>,
+}
+fn gen_impl(x: &mut LargeFile, region: view::Area) -> Box<dyn view::View> {
+    // only the rows shown are kept decoded
+    x.index.clear_cache();
+    x.stabilize_cursor();
+
+    let (_, file_area) = region.split_horizontal(view::LINE_NUMBER_W);
+    x.window.adjust_window(x.cursor, file_area.width, file_area.height);
+    x.index.index_to(x.window.row_high);
+
+    let file_view = view::ToView::new(&x.index);
+    let add_cursor = view::AddCursor::new(x.cursor);
+    let file_view = view::OverlayView::new(file_view, add_cursor);
+    let file_view = view::TranslateView::new(
+        file_view,
+        file_area.col as i32 - x.window.col() as i32,
+        file_area.row as i32 - x.window.row() as i32,
+    );
+
+    let lineno_view = view::LineNumber {
+        from: x.window.row_low + 1,
+        to: std::cmp::min(x.window.row_high + 1, x.index.len()),
+    };
+
+    let view = view::MergeHorizontal {
+        left: lineno_view,
+        right: file_view,
+        col_offset: file_area.col,
+    };
+
+    let view = view::CloneView::new(view, region);
+    Box::new(view)
+}
+impl view::ViewGen for ViewGen {
+    fn gen(&mut self, region: view::Area) -> Box<dyn view::View> {
+        gen_impl(&mut self.x.borrow_mut(), region)
+    }
+}
+
+pub struct Page {
+    controller: Box<dyn controller::Controller>,
+    view_gen: Box<dyn view::ViewGen>,
+    x: Rc<RefCell<LargeFile>>,
+}
+impl Page {
+    pub fn new(x: Rc<RefCell<LargeFile>>) -> Self {
+        Self {
+            controller: Box::new(mk_controller(x.clone())),
+            view_gen: Box::new(ViewGen { x: x.clone() }),
+            x,
+        }
+    }
+}
+impl navigator::Page for Page {
+    fn controller(&self) -> &Box<dyn controller::Controller> {
+        &self.controller
+    }
+    fn view_gen(&mut self) -> &mut Box<dyn view::ViewGen> {
+        &mut self.view_gen
+    }
+    fn status(&self) -> String {
+        let x = self.x.borrow();
+        let count: String = x.num_buffer.iter().collect();
+        let state: &str = match x.state.get().as_str() {
+            INIT => "*",
+            SEARCH => "/",
+            // the count typed
+            JUMP => &count,
+            _ => "*",
+        };
+        let mode = if x.index.is_hex() { "hex" } else { "text" };
+        // the rows not found yet aren't counted
+        let more = if x.index.is_complete() { "" } else { "+" };
+        format!("[LargeFile -{}-] {} [{}, {}{} rows, read-only]", state, x.path.to_str().unwrap(), mode, x.index.len(), more)
+    }
+    fn kind(&self) -> navigator::PageKind {
+        navigator::PageKind::LargeFile
+    }
+    fn id(&self) -> String {
+        self.x.borrow().path.to_str().unwrap().to_owned()
+    }
+    fn message(&self) -> MessageBox {
+        self.x.borrow().message_box.clone()
+    }
+    fn watch_path(&self) -> Option<PathBuf> {
+        fs::canonicalize(&self.x.borrow().path).ok()
+    }
+    fn changed_on_disk(&self) {
+        self.x.borrow_mut().reload();
+    }
+}
+
+/// Opens the file here if it's large or binary. None to open it in the edit buffer.
+pub fn open(path: &Path, row: usize) -> Option<Rc<RefCell<Page>>> {
+    if !is_large(path) {
+        return None
+    }
+    let mut x = LargeFile::open(path).ok()?;
+    x.jump_to(row);
+    Some(Rc::new(RefCell::new(Page::new(Rc::new(RefCell::new(x))))))
+}
@@ -0,0 +1,272 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::navigator::Page;
+use crate::read_buffer::Cursor;
+use crate::view::{self, Area, View, ViewElem};
+
+// The windows on the screen as a binary tree of splits.
+//
+// Each window shows a page and its own status line.
+// The focused window shows the current page of the navigator
+// so opening a page in any page goes to the focused window.
+
+/// The rows and cols a window needs at least. One for the status line.
+const MIN_HEIGHT: usize = 3;
+const MIN_WIDTH: usize = 10;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Split {
+    Horizontal, // left and right
+    Vertical, // top and bottom
+}
+
+enum Node {
+    Window(Rc<RefCell<dyn Page>>),
+    Split {
+        split: Split,
+        ratio: usize, // the percent of the first
+        first: Box<Node>,
+        second: Box<Node>,
+    },
+}
+impl Node {
+    fn count(&self) -> usize {
+        match self {
+            Node::Window(_) => 1,
+            Node::Split { first, second, .. } => first.count() + second.count(),
+        }
+    }
+    fn windows(&self, area: Area, out: &mut Vec<(Area, Rc<RefCell<dyn Page>>)>) {
+        match self {
+            Node::Window(page) => out.push((area, page.clone())),
+            Node::Split { split, ratio, first, second } => {
+                let (a, b) = split_area(&area, *split, *ratio);
+                first.windows(a, out);
+                second.windows(b, out);
+            },
+        }
+    }
+    fn window_mut(&mut self, i: usize) -> &mut Node {
+        match self {
+            Node::Window(_) => self,
+            Node::Split { first, second, .. } => {
+                let n = first.count();
+                if i < n {
+                    first.window_mut(i)
+                } else {
+                    second.window_mut(i - n)
+                }
+            },
+        }
+    }
+    // the ratio of the innermost split that has the window i and whether it's the first
+    fn parent_mut(&mut self, i: usize) -> Option<(&mut usize, bool)> {
+        match self {
+            Node::Window(_) => None,
+            Node::Split { ratio, first, second, .. } => {
+                let n = first.count();
+                let (child, j) = if i < n { (first, i) } else { (second, i - n) };
+                if let Node::Split { .. } = **child {
+                    child.parent_mut(j)
+                } else {
+                    Some((ratio, i < n))
+                }
+            },
+        }
+    }
+    // removes the window i. the sibling takes the place of the split.
+    fn remove(self, i: usize) -> Node {
+        match self {
+            Node::Window(_) => self,
+            Node::Split { split, ratio, first, second } => {
+                let n = first.count();
+                if i < n {
+                    if let Node::Window(_) = *first {
+                        return *second
+                    }
+                    Node::Split { split, ratio, first: Box::new(first.remove(i)), second }
+                } else {
+                    if let Node::Window(_) = *second {
+                        return *first
+                    }
+                    Node::Split { split, ratio, first, second: Box::new(second.remove(i - n)) }
+                }
+            },
+        }
+    }
+}
+
+// the first is ratio percent of the area, leaving a column for the separator on horizontal split.
+fn split_area(area: &Area, split: Split, ratio: usize) -> (Area, Area) {
+    let size = |total: usize, min: usize| {
+        let x = total * ratio / 100;
+        if total < 2 * min {
+            total / 2
+        } else {
+            x.max(min).min(total - min)
+        }
+    };
+    match split {
+        Split::Vertical => area.split_vertical(size(area.height, MIN_HEIGHT)),
+        Split::Horizontal => {
+            let w = size(area.width.saturating_sub(1), MIN_WIDTH);
+            let (a, b) = area.split_horizontal(w);
+            let (_, b) = b.split_horizontal(1.min(b.width));
+            (a, b)
+        },
+    }
+}
+
+pub struct Layout {
+    root: Option<Node>,
+    focus: usize,
+}
+impl Layout {
+    pub fn new(page: Rc<RefCell<dyn Page>>) -> Self {
+        Self {
+            root: Some(Node::Window(page)),
+            focus: 0,
+        }
+    }
+    fn root(&self) -> &Node {
+        self.root.as_ref().unwrap()
+    }
+    pub fn count(&self) -> usize {
+        self.root().count()
+    }
+    pub fn focus(&self) -> usize {
+        self.focus
+    }
+    /// The windows in order with their areas.
+    pub fn windows(&self, area: Area) -> Vec<(Area, Rc<RefCell<dyn Page>>)> {
+        let mut v = vec![];
+        self.root().windows(area, &mut v);
+        v
+    }
+    /// Puts the page in the focused window.
+    pub fn set_page(&mut self, page: Rc<RefCell<dyn Page>>) {
+        let focus = self.focus;
+        *self.root.as_mut().unwrap().window_mut(focus) = Node::Window(page);
+    }
+    pub fn page(&self) -> Rc<RefCell<dyn Page>> {
+        self.windows(Area { col: 0, row: 0, width: 0, height: 0 }).remove(self.focus).1
+    }
+    /// Splits the focused window into two showing the same page. The focus stays in the first.
+    /// Returns false if the window is too small.
+    pub fn split(&mut self, split: Split, area: Area) -> bool {
+        let (cur, page) = self.windows(area).remove(self.focus);
+        let enough = match split {
+            Split::Vertical => cur.height >= 2 * MIN_HEIGHT,
+            Split::Horizontal => cur.width > 2 * MIN_WIDTH,
+        };
+        if !enough {
+            return false
+        }
+        let focus = self.focus;
+        *self.root.as_mut().unwrap().window_mut(focus) = Node::Split {
+            split,
+            ratio: 50,
+            first: Box::new(Node::Window(page.clone())),
+            second: Box::new(Node::Window(page)),
+        };
+        true
+    }
+    /// Closes the focused window. Returns false for the last window.
+    pub fn close(&mut self) -> bool {
+        if self.count() == 1 {
+            return false
+        }
+        let root = self.root.take().unwrap();
+        self.root = Some(root.remove(self.focus));
+        if self.focus >= self.count() {
+            self.focus = self.count() - 1;
+        }
+        true
+    }
+    pub fn focus_next(&mut self) {
+        self.focus = (self.focus + 1) % self.count();
+    }
+    /// Grows (or shrinks if negative) the focused window by the percent.
+    pub fn resize(&mut self, delta: i32) {
+        let focus = self.focus;
+        if let Some((ratio, is_first)) = self.root.as_mut().unwrap().parent_mut(focus) {
+            let delta = if is_first { delta } else { -delta };
+            *ratio = (*ratio as i32 + delta).clamp(10, 90) as usize;
+        }
+    }
+}
+
+/// The views of the windows. The cells between the windows are the separators.
+pub struct Tiled {
+    pub views: Vec<(Area, Box<dyn View>)>,
+    pub cursor: Option<Cursor>,
+}
+impl View for Tiled {
+    fn get(&self, col: usize, row: usize) -> ViewElem {
+        for (area, v) in &self.views {
+            if area.contains(col, row) {
+                return v.get(col, row)
+            }
+        }
+        (Some('|'), Some(view::default_fg()), Some(view::default_bg()))
+    }
+    fn get_cursor_pos(&self) -> Option<Cursor> {
+        self.cursor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::navigator::{Navigator, NavigatorPage};
+
+    fn page() -> Rc<RefCell<dyn Page>> {
+        let navigator = Rc::new(RefCell::new(Navigator::new()));
+        Rc::new(RefCell::new(NavigatorPage::new(navigator)))
+    }
+    fn area(width: usize, height: usize) -> Area {
+        Area { col: 0, row: 0, width, height }
+    }
+    fn rect(a: &Area) -> (usize, usize, usize, usize) {
+        (a.col, a.row, a.width, a.height)
+    }
+
+    #[test]
+    fn test_split_and_close() {
+        let p = page();
+        let mut x = Layout::new(p.clone());
+        assert!(x.split(Split::Horizontal, area(41, 20)));
+        let v: Vec<_> = x.windows(area(41, 20)).iter().map(|(a, _)| rect(a)).collect();
+        assert_eq!(v, vec![(0, 0, 20, 20), (21, 0, 20, 20)]);
+
+        // the right one into top and bottom
+        x.focus_next();
+        assert_eq!(x.focus(), 1);
+        let q = page();
+        x.set_page(q.clone());
+        assert!(x.split(Split::Vertical, area(41, 20)));
+        let v: Vec<_> = x.windows(area(41, 20)).iter().map(|(a, _)| rect(a)).collect();
+        assert_eq!(v, vec![(0, 0, 20, 20), (21, 0, 20, 10), (21, 10, 20, 10)]);
+        assert!(Rc::ptr_eq(&x.page(), &q));
+
+        x.resize(20);
+        let v: Vec<_> = x.windows(area(41, 20)).iter().map(|(a, _)| rect(a)).collect();
+        assert_eq!(v[1], (21, 0, 20, 14));
+
+        assert!(x.close());
+        assert_eq!(x.count(), 2);
+        assert!(Rc::ptr_eq(&x.page(), &q));
+        x.focus_next();
+        assert!(x.close());
+        assert!(!x.close());
+        assert!(Rc::ptr_eq(&x.page(), &q));
+    }
+
+    #[test]
+    fn test_split_too_small() {
+        let mut x = Layout::new(page());
+        assert!(!x.split(Split::Vertical, area(40, 5)));
+        assert!(!x.split(Split::Horizontal, area(20, 20)));
+        assert_eq!(x.count(), 1);
+    }
+}
@@ -29,10 +29,21 @@ pub mod controller;
 pub mod view;
 pub mod navigator;
 pub mod directory;
+pub mod finder;
 pub mod message_box;
 pub mod util;
 pub mod theme;
 pub mod shared;
+pub mod lsp;
+pub mod git;
+pub mod grep;
+pub mod registers;
+pub mod keymap;
+pub mod macros;
+pub mod layout;
+pub mod session;
+pub mod watcher;
+pub mod large_file;
 mod config;
 
 extern crate flame;
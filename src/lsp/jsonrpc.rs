@@ -0,0 +1,50 @@
+use std::io::{self, BufRead, Write};
+use serde_json::Value;
+
+/// Writes a message with the base protocol header.
+pub fn write_message<W: Write>(out: &mut W, msg: &Value) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    out.flush()
+}
+
+/// Reads a message. Returns None if the stream is closed.
+pub fn read_message<R: BufRead>(inp: &mut R) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if inp.read_line(&mut line)? == 0 {
+            return Ok(None)
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break
+        }
+        let mut kv = line.splitn(2, ':');
+        if let (Some(k), Some(v)) = (kv.next(), kv.next()) {
+            if k.eq_ignore_ascii_case("Content-Length") {
+                content_length = v.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let n = content_length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; n];
+    inp.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[test]
+fn test_jsonrpc_roundtrip() {
+    let msgs = vec![
+        serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+        serde_json::json!({"jsonrpc": "2.0", "method": "exit"}),
+    ];
+    let mut buf = vec![];
+    for msg in &msgs {
+        write_message(&mut buf, msg).unwrap();
+    }
+    let mut inp = io::BufReader::new(&buf[..]);
+    assert_eq!(read_message(&mut inp).unwrap(), Some(msgs[0].clone()));
+    assert_eq!(read_message(&mut inp).unwrap(), Some(msgs[1].clone()));
+    assert_eq!(read_message(&mut inp).unwrap(), None);
+}
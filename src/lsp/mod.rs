@@ -0,0 +1,387 @@
+pub mod jsonrpc;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex, mpsc};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use serde_json::{json, Value};
+use crate::read_buffer::BufElem;
+use crate::edit_buffer::change_log::ChangeLog;
+use crate::screen::Color;
+use crate::view;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+impl Severity {
+    fn from_lsp(x: Option<u64>) -> Self {
+        match x {
+            Some(2) => Severity::Warning,
+            Some(3) => Severity::Information,
+            Some(4) => Severity::Hint,
+            _ => Severity::Error,
+        }
+    }
+    fn mark(self) -> (char, Color) {
+        match self {
+            Severity::Error => ('E', Color::Red),
+            Severity::Warning => ('W', Color::Yellow),
+            Severity::Information => ('I', Color::Cyan),
+            Severity::Hint => ('H', Color::Cyan),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub row: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+type Diagnostics = Arc<Mutex<HashMap<String, Vec<Diagnostic>>>>;
+type Pending = Arc<Mutex<HashMap<u64, mpsc::Sender<Value>>>>;
+
+enum Conn {
+    /// Waiting for the response to initialize. The messages are sent after it.
+    Starting(Vec<Value>),
+    Ready,
+    Closed,
+}
+
+// the id of the initialize request
+const INIT_ID: u64 = 0;
+
+/// A running language server.
+/// Messages from the server are read by a dedicated thread.
+pub struct Client {
+    child: Mutex<Child>,
+    stdin: Arc<Mutex<ChildStdin>>,
+    conn: Arc<Mutex<Conn>>,
+    next_id: AtomicU64,
+    pending: Pending,
+    diagnostics: Diagnostics,
+}
+impl Client {
+    /// Spawns the server. It's initialized in the background so this doesn't wait for the server.
+    pub fn start(command: &[String], root: &Path) -> io::Result<Arc<Client>> {
+        if command.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty command"))
+        }
+        let mut child = Command::new(&command[0])
+            .args(&command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = Arc::new(Mutex::new(child.stdin.take().unwrap()));
+        let stdout = child.stdout.take().unwrap();
+        let client = Arc::new(Client {
+            child: Mutex::new(child),
+            stdin: stdin.clone(),
+            conn: Arc::new(Mutex::new(Conn::Starting(vec![]))),
+            next_id: AtomicU64::new(INIT_ID + 1),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            diagnostics: Arc::new(Mutex::new(HashMap::new())),
+        });
+
+        let params = json!({
+            "processId": std::process::id(),
+            "rootUri": path_to_uri(root),
+            "capabilities": {
+                "textDocument": {
+                    "synchronization": { "didSave": true },
+                    "publishDiagnostics": {},
+                },
+            },
+        });
+        let init = json!({ "jsonrpc": "2.0", "id": INIT_ID, "method": "initialize", "params": params });
+        jsonrpc::write_message(&mut *stdin.lock().unwrap(), &init)?;
+
+        let conn = client.conn.clone();
+        let pending = client.pending.clone();
+        let diagnostics = client.diagnostics.clone();
+        std::thread::spawn(move || serve(stdout, stdin, conn, pending, diagnostics));
+        Ok(client)
+    }
+    // queued until the server is initialized
+    fn send(&self, msg: Value) -> io::Result<()> {
+        match &mut *self.conn.lock().unwrap() {
+            Conn::Starting(queue) => {
+                queue.push(msg);
+                Ok(())
+            },
+            Conn::Ready => jsonrpc::write_message(&mut *self.stdin.lock().unwrap(), &msg),
+            Conn::Closed => Err(io::Error::new(io::ErrorKind::BrokenPipe, "the language server is closed")),
+        }
+    }
+    pub fn request(&self, method: &str, params: Value) -> io::Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (tx, rx) = mpsc::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+        let msg = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if let Err(e) = self.send(msg) {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(e)
+        }
+        let res = rx.recv_timeout(TIMEOUT);
+        self.pending.lock().unwrap().remove(&id);
+        let res = res.map_err(|_| io::Error::new(io::ErrorKind::TimedOut, method.to_owned()))?;
+        match res.get("error") {
+            Some(e) => Err(io::Error::other(e.to_string())),
+            None => Ok(res.get("result").cloned().unwrap_or(Value::Null)),
+        }
+    }
+    pub fn notify(&self, method: &str, params: Value) -> io::Result<()> {
+        let msg = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        self.send(msg)
+    }
+    pub fn diagnostics(&self, uri: &str) -> Vec<Diagnostic> {
+        self.diagnostics.lock().unwrap().get(uri).cloned().unwrap_or_default()
+    }
+    pub fn shutdown(&self) {
+        if self.request("shutdown", Value::Null).is_ok() {
+            let _ = self.notify("exit", Value::Null);
+            for _ in 0..10 {
+                if let Ok(Some(_)) = self.child.lock().unwrap().try_wait() {
+                    return
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+        self.kill();
+    }
+    fn kill(&self) {
+        let mut child = self.child.lock().unwrap();
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+// sends the queued messages once initialized
+fn on_initialized(res: &Value, stdin: &Mutex<ChildStdin>, conn: &Mutex<Conn>) {
+    let mut conn = conn.lock().unwrap();
+    if res.get("error").is_some() {
+        *conn = Conn::Closed;
+        return
+    }
+    let queue = match std::mem::replace(&mut *conn, Conn::Ready) {
+        Conn::Starting(queue) => queue,
+        _ => vec![],
+    };
+    let mut stdin = stdin.lock().unwrap();
+    let initialized = json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} });
+    for msg in std::iter::once(&initialized).chain(&queue) {
+        if jsonrpc::write_message(&mut *stdin, msg).is_err() {
+            *conn = Conn::Closed;
+            return
+        }
+    }
+}
+
+fn serve(stdout: ChildStdout, stdin: Arc<Mutex<ChildStdin>>, conn: Arc<Mutex<Conn>>, pending: Pending, diagnostics: Diagnostics) {
+    let mut inp = BufReader::new(stdout);
+    while let Ok(Some(msg)) = jsonrpc::read_message(&mut inp) {
+        let id = msg.get("id").cloned();
+        match msg.get("method").and_then(|x| x.as_str()) {
+            Some("textDocument/publishDiagnostics") => {
+                let params = &msg["params"];
+                let uri = params["uri"].as_str().unwrap_or("").to_owned();
+                let mut v = vec![];
+                for d in params["diagnostics"].as_array().cloned().unwrap_or_default() {
+                    v.push(Diagnostic {
+                        row: d["range"]["start"]["line"].as_u64().unwrap_or(0) as usize,
+                        severity: Severity::from_lsp(d["severity"].as_u64()),
+                        message: d["message"].as_str().unwrap_or("").to_owned(),
+                    });
+                }
+                diagnostics.lock().unwrap().insert(uri, v);
+            },
+            Some(_) => {
+                // requests from the server aren't supported but must be answered.
+                if let Some(id) = id {
+                    let res = json!({ "jsonrpc": "2.0", "id": id, "result": null });
+                    let _ = jsonrpc::write_message(&mut *stdin.lock().unwrap(), &res);
+                }
+            },
+            None => {
+                let id = id.and_then(|x| x.as_u64());
+                if id == Some(INIT_ID) {
+                    on_initialized(&msg, &stdin, &conn);
+                    continue;
+                }
+                if let Some(tx) = id.and_then(|id| pending.lock().unwrap().remove(&id)) {
+                    let _ = tx.send(msg);
+                }
+            },
+        }
+    }
+    // the server is gone
+    *conn.lock().unwrap() = Conn::Closed;
+}
+
+use lazy_static::lazy_static;
+lazy_static! {
+    static ref SERVERS: Mutex<HashMap<String, Arc<Client>>> = Mutex::new(HashMap::new());
+}
+
+/// Returns the server for the language. The server is started on the first call.
+pub fn get_client(lang: &str, command: &[String]) -> io::Result<Arc<Client>> {
+    let mut servers = SERVERS.lock().unwrap();
+    if let Some(client) = servers.get(lang) {
+        return Ok(client.clone())
+    }
+    let root = std::env::current_dir()?;
+    let client = Client::start(command, &root)?;
+    servers.insert(lang.to_owned(), client.clone());
+    Ok(client)
+}
+
+pub fn shutdown_all() {
+    let servers: Vec<Arc<Client>> = SERVERS.lock().unwrap().drain().map(|(_, x)| x).collect();
+    for client in servers {
+        client.shutdown();
+    }
+}
+
+pub fn path_to_uri(path: &Path) -> String {
+    let abs = fs::canonicalize(path).unwrap_or_else(|_| std::env::current_dir().unwrap().join(path));
+    let mut s = "file://".to_owned();
+    for b in abs.to_str().unwrap().bytes() {
+        match b {
+            b'A' ..= b'Z' | b'a' ..= b'z' | b'0' ..= b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => s.push(b as char),
+            _ => s.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    s
+}
+
+fn utf16_len(xs: &[BufElem]) -> usize {
+    xs.iter().map(|e| match e {
+        BufElem::Char(c) => c.len_utf16(),
+        BufElem::Eol => 1,
+    }).sum()
+}
+
+fn to_string(xs: &[BufElem]) -> String {
+    xs.iter().map(|e| match e {
+        BufElem::Char(c) => *c,
+        BufElem::Eol => '\n',
+    }).collect()
+}
+
+/// A buffer opened in the server.
+pub struct Document {
+    client: Arc<Client>,
+    uri: String,
+    version: i64,
+}
+impl Document {
+    pub fn open(client: Arc<Client>, path: &Path, language_id: &str, text: &[Vec<BufElem>]) -> Self {
+        let uri = path_to_uri(path);
+        let text: String = text.iter().map(|line| to_string(line)).collect();
+        let _ = client.notify("textDocument/didOpen", json!({
+            "textDocument": { "uri": uri, "languageId": language_id, "version": 0, "text": text },
+        }));
+        Self { client, uri, version: 0 }
+    }
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+    /// Sends the change as an incremental update.
+    /// `line_head` is the elements before `log.at` in the line which the change doesn't touch.
+    pub fn did_change(&mut self, log: &ChangeLog, line_head: &[BufElem]) {
+        let start_char = utf16_len(line_head);
+        let n_eol = log.deleted.iter().filter(|&e| *e == BufElem::Eol).count();
+        let end = if n_eol == 0 {
+            json!({ "line": log.at.row, "character": start_char + utf16_len(&log.deleted) })
+        } else {
+            let last_eol = log.deleted.iter().rposition(|e| *e == BufElem::Eol).unwrap();
+            json!({ "line": log.at.row + n_eol, "character": utf16_len(&log.deleted[last_eol+1..]) })
+        };
+        self.version += 1;
+        let _ = self.client.notify("textDocument/didChange", json!({
+            "textDocument": { "uri": self.uri, "version": self.version },
+            "contentChanges": [{
+                "range": {
+                    "start": { "line": log.at.row, "character": start_char },
+                    "end": end,
+                },
+                "text": to_string(&log.inserted),
+            }],
+        }));
+    }
+    pub fn did_save(&self) {
+        let _ = self.client.notify("textDocument/didSave", json!({
+            "textDocument": { "uri": self.uri },
+        }));
+    }
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.client.diagnostics(&self.uri)
+    }
+}
+impl Drop for Document {
+    fn drop(&mut self) {
+        let _ = self.client.notify("textDocument/didClose", json!({
+            "textDocument": { "uri": self.uri },
+        }));
+    }
+}
+
+/// Puts the mark of the most severe diagnostic of each row in the line number column.
+pub struct GutterDiffView {
+    marks: HashMap<usize, Severity>,
+    from: usize,
+}
+impl GutterDiffView {
+    pub fn new(diagnostics: &[Diagnostic], from: usize) -> Self {
+        let mut marks: HashMap<usize, Severity> = HashMap::new();
+        for d in diagnostics {
+            let e = marks.entry(d.row).or_insert(d.severity);
+            if d.severity < *e {
+                *e = d.severity;
+            }
+        }
+        Self { marks, from }
+    }
+}
+impl view::View for GutterDiffView {
+    fn get(&self, col: usize, row: usize) -> view::ViewElem {
+        if col != view::LINE_NUMBER_W - 1 {
+            return (None, None, None)
+        }
+        match self.marks.get(&(self.from + row)) {
+            Some(severity) => {
+                let (c, fg) = severity.mark();
+                (Some(c), Some(fg), None)
+            },
+            None => (None, None, None),
+        }
+    }
+}
+
+#[test]
+fn test_gutter_most_severe() {
+    use crate::view::View;
+    let diagnostics = vec![
+        Diagnostic { row: 3, severity: Severity::Warning, message: "w".to_owned() },
+        Diagnostic { row: 3, severity: Severity::Error, message: "e".to_owned() },
+        Diagnostic { row: 5, severity: Severity::Hint, message: "h".to_owned() },
+    ];
+    let view = GutterDiffView::new(&diagnostics, 2);
+    let col = view::LINE_NUMBER_W - 1;
+    assert_eq!(view.get(col, 1).0, Some('E'));
+    assert_eq!(view.get(col, 3).0, Some('H'));
+    assert_eq!(view.get(col, 0).0, None);
+    assert_eq!(view.get(0, 1).0, None);
+}
@@ -0,0 +1,233 @@
+use std::path::PathBuf;
+use crate::edit_buffer::clipboard::{self, Type};
+use crate::navigator::Page;
+use crate::read_buffer::{BufElem, INIT, JUMP};
+use crate::Key;
+
+// q<reg> ... q records the keys to the register and [count]@<reg> replays them.
+// @@ replays the last one. The keys are handled before the page so any page can record.
+//
+// The keys are kept in the register as text. Ctrl and Esc are the control chars.
+
+/// The keys a replay can push at most. Stops the macros calling themselves.
+pub const MAX_REPLAY: usize = 100000;
+
+enum Wait {
+    None,
+    Record,
+    Play(usize),
+}
+
+pub struct Macros {
+    wait: Wait,
+    recording: Option<(char, Vec<Key>)>,
+    // the count typed in the page
+    count: String,
+    last: Option<char>,
+    persist: bool,
+}
+impl Macros {
+    /// Loads the saved macros if persist.
+    pub fn new(persist: bool) -> Self {
+        if persist {
+            load_all();
+        }
+        Self {
+            wait: Wait::None,
+            recording: None,
+            count: String::new(),
+            last: None,
+            persist,
+        }
+    }
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|x| x.0)
+    }
+    fn record_key(&mut self, k: &Key) {
+        if let Some((_, v)) = &mut self.recording {
+            v.push(k.clone());
+        }
+    }
+    fn stop(&mut self, page: &dyn Page) {
+        let (reg, keys) = self.recording.take().unwrap();
+        clipboard::record(reg, Type::Range(to_text(&keys)));
+        if self.persist {
+            if let Err(e) = save(reg.to_ascii_lowercase()) {
+                page.message().send(&format!("Failed to save the macro: {}", e));
+                return
+            }
+        }
+        page.message().send(&format!("recorded @{}", reg.to_ascii_lowercase()));
+    }
+    fn play(&mut self, reg: char, n: usize, page: &dyn Page) -> Vec<Key> {
+        let reg = if reg == '@' {
+            match self.last {
+                Some(c) => c,
+                None => {
+                    page.message().send("no previous macro");
+                    return vec![]
+                },
+            }
+        } else {
+            reg
+        };
+        self.last = Some(reg);
+        let keys = match clipboard::paste(Some(reg)) {
+            Ok(Some(x)) => from_text(x.elems()),
+            Ok(None) => {
+                page.message().send(&format!("@{} is empty", reg));
+                return vec![]
+            },
+            Err(e) => {
+                page.message().send(&format!("Failed to read @{}: {}", reg, e));
+                return vec![]
+            },
+        };
+        let mut v = vec![];
+        for _ in 0..n {
+            v.extend_from_slice(&keys);
+        }
+        v
+    }
+    /// Sends the key to the page unless it's for the macros.
+    /// Returns the keys to replay.
+    /// The replayed keys aren't recorded because the @ that replays them is.
+    pub fn receive(&mut self, k: Key, page: &dyn Page, replaying: bool) -> Vec<Key> {
+        match std::mem::replace(&mut self.wait, Wait::None) {
+            Wait::Record => {
+                match k {
+                    Key::Char(c) if c.is_ascii_alphanumeric() => {
+                        self.recording = Some((c, vec![]));
+                        page.message().send(&format!("recording @{}", c.to_ascii_lowercase()));
+                    },
+                    _ => {},
+                }
+                return vec![]
+            },
+            Wait::Play(n) => {
+                if !replaying {
+                    self.record_key(&k);
+                }
+                return match k {
+                    Key::Char(c) if c == '@' || c == '+' || c.is_ascii_alphanumeric() => self.play(c, n, page),
+                    _ => vec![],
+                }
+            },
+            Wait::None => {},
+        }
+
+        let state = page.controller().state();
+        match k {
+            Key::Char('q') if state == INIT => {
+                if self.recording.is_some() {
+                    self.stop(page);
+                } else {
+                    self.wait = Wait::Record;
+                }
+                return vec![]
+            },
+            Key::Char('@') if state == INIT || state == JUMP => {
+                if !replaying {
+                    self.record_key(&k);
+                }
+                let n = if state == JUMP {
+                    // the page forgets the count
+                    page.controller().receive(Key::Esc);
+                    self.count.parse().unwrap_or(1)
+                } else {
+                    1
+                };
+                self.wait = Wait::Play(n);
+                return vec![]
+            },
+            Key::Char(c) if c.is_ascii_digit() => {
+                if state == INIT {
+                    self.count.clear();
+                }
+                if state == INIT || state == JUMP {
+                    self.count.push(c);
+                }
+            },
+            _ => {},
+        }
+        if !replaying {
+            self.record_key(&k);
+        }
+        page.controller().receive(k);
+        vec![]
+    }
+}
+
+pub fn to_text(keys: &[Key]) -> Vec<BufElem> {
+    let mut v = vec![];
+    for k in keys {
+        let c = match *k {
+            Key::Char('\n') => {
+                v.push(BufElem::Eol);
+                continue
+            },
+            Key::Char(c) => c,
+            Key::Ctrl(c) if c.is_ascii_lowercase() => (c as u8 & 0x1f) as char,
+            Key::Esc => '\x1b',
+            Key::Backspace => '\x7f',
+            _ => continue,
+        };
+        v.push(BufElem::Char(c));
+    }
+    v
+}
+
+pub fn from_text(x: &[BufElem]) -> Vec<Key> {
+    let mut v = vec![];
+    for e in x {
+        let k = match *e {
+            BufElem::Eol => Key::Char('\n'),
+            BufElem::Char('\x1b') => Key::Esc,
+            BufElem::Char('\x7f') => Key::Backspace,
+            BufElem::Char('\t') => Key::Char('\t'),
+            BufElem::Char(c) if (c as u32) < 0x20 => Key::Ctrl((c as u8 | 0x60) as char),
+            BufElem::Char(c) => Key::Char(c),
+        };
+        v.push(k);
+    }
+    v
+}
+
+/// ~/.ijk/macros
+fn macro_dir() -> PathBuf {
+    crate::config::ijk_dir().join("macros")
+}
+
+// saved as a keys file
+fn save(reg: char) -> std::io::Result<()> {
+    let keys = match clipboard::paste(Some(reg)) {
+        Ok(Some(x)) => from_text(x.elems()),
+        _ => return Ok(()),
+    };
+    std::fs::create_dir_all(macro_dir())?;
+    std::fs::write(macro_dir().join(reg.to_string()), crate::util::write_keys(&keys))
+}
+
+fn load_all() {
+    let entries = match std::fs::read_dir(macro_dir()) {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let reg = match name.chars().next() {
+            Some(c) if name.len() == 1 && c.is_ascii_lowercase() => c,
+            _ => continue,
+        };
+        if let Ok(s) = std::fs::read_to_string(entry.path()) {
+            let keys: Vec<Key> = crate::util::read_keys(&s).into_iter().filter_map(crate::editor::to_key).collect();
+            clipboard::record(reg, Type::Range(to_text(&keys)));
+        }
+    }
+}
+
+#[test]
+fn test_text() {
+    let keys = vec![Key::Char('d'), Key::Char('\n'), Key::Ctrl('r'), Key::Esc, Key::Backspace, Key::Char('\t')];
+    assert_eq!(from_text(&to_text(&keys)), keys);
+}
@@ -1,6 +1,7 @@
 use super::controller::{self, PageState};
 use super::view;
 use super::read_buffer::{self, BufElem, ReadBuffer};
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::cell::RefCell;
 use crate::message_box::MessageBox;
@@ -11,6 +12,26 @@ pub enum PageKind {
     Buffer,
     Directory,
     Navigator,
+    GitStatus,
+    Grep,
+    Finder,
+    Registers,
+    LargeFile,
+}
+impl PageKind {
+    /// The name in the [keys] section of the config.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PageKind::Buffer => "buffer",
+            PageKind::Directory => "directory",
+            PageKind::Navigator => "navigator",
+            PageKind::GitStatus => "git_status",
+            PageKind::Grep => "grep",
+            PageKind::Finder => "finder",
+            PageKind::Registers => "registers",
+            PageKind::LargeFile => "large_file",
+        }
+    }
 }
 
 pub trait Page {
@@ -20,12 +41,53 @@ pub trait Page {
     fn id(&self) -> String;
     fn status(&self) -> String;
     fn message(&self) -> MessageBox;
+    /// What to save in the session. None if the page isn't restored.
+    fn session(&self) -> Option<crate::session::PageSession> {
+        None
+    }
+    /// The file or directory to watch for the changes made outside.
+    fn watch_path(&self) -> Option<PathBuf> {
+        None
+    }
+    /// Called when the watched path changed. Only in the initial state.
+    fn changed_on_disk(&self) {}
+    /// True if the page has the changes not saved.
+    fn dirty(&self) -> bool {
+        false
+    }
+    /// Called when the editor quits.
+    fn quit(&self) {}
+}
+
+const WORKSPACE_INPUT: &str = "WorkspaceInput";
+
+/// The name and the pages of a workspace.
+pub type WorkspacePages = (String, Vec<Rc<RefCell<dyn Page>>>);
+
+/// A set of pages with its own MRU list.
+/// The pages of the active workspace are kept in the navigator while it is active.
+struct Workspace {
+    name: String,
+    current: Option<Rc<RefCell<dyn Page>>>,
+    list: Vec<Rc<RefCell<dyn Page>>>,
+}
+impl Workspace {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            current: None,
+            list: vec![],
+        }
+    }
 }
 
 pub struct Navigator {
     needs_refresh: bool,
     current: Option<Rc<RefCell<Page>>>,
     list: Vec<Rc<RefCell<Page>>>,
+    workspaces: Vec<Workspace>,
+    active: usize,
+    workspace_name: String,
     rb: ReadBuffer,
     state: PageState,
     message_box: MessageBox,
@@ -38,6 +100,9 @@ impl Navigator {
             needs_refresh: false,
             current: None,
             list: vec![],
+            workspaces: vec![Workspace::new("default")],
+            active: 0,
+            workspace_name: String::new(),
             rb: read_buffer::ReadBuffer::new(vec![], state.clone(), message_box.clone()),
             state,
             message_box,
@@ -49,8 +114,16 @@ impl Navigator {
     fn update_cache(&mut self) {
 
     }
+    // the workspaces come first in the buffer
     fn refresh_buffer(&mut self) {
         let mut v = vec![];
+        for (i, ws) in self.workspaces.iter().enumerate() {
+            let (mark, n) = if i == self.active { ('*', self.list.len()) } else { (' ', ws.list.len()) };
+            let line = format!("{} workspace: {} ({} pages)", mark, ws.name, n);
+            let mut vv: Vec<BufElem> = line.chars().map(BufElem::Char).collect();
+            vv.push(BufElem::Eol);
+            v.push(vv);
+        }
         for e in &self.list {
             let mut vv = vec![];
             for c in e.borrow().status().chars() {
@@ -60,6 +133,93 @@ impl Navigator {
             v.push(vv);
         }
         self.rb = read_buffer::ReadBuffer::new(v, self.state.clone(), self.message_box.clone());
+        // on the current page
+        self.rb.cursor.row = self.workspaces.len();
+        self.rb.stabilize_cursor();
+    }
+    /// The names of the workspaces and the index of the active one.
+    pub fn workspaces(&self) -> (Vec<String>, usize) {
+        (self.workspaces.iter().map(|ws| ws.name.clone()).collect(), self.active)
+    }
+    /// The pages of the workspaces and the index of the active one.
+    pub fn workspace_pages(&self) -> (Vec<WorkspacePages>, usize) {
+        let v = self.workspaces.iter().enumerate().map(|(i, ws)| {
+            let list = if i == self.active { &self.list } else { &ws.list };
+            (ws.name.clone(), list.clone())
+        }).collect();
+        (v, self.active)
+    }
+    /// Replaces the workspaces. The first page in the list is the current page.
+    pub fn restore_workspaces(&mut self, v: Vec<WorkspacePages>, active: usize) {
+        self.workspaces = v.into_iter().map(|(name, list)| {
+            let mut ws = Workspace::new(&name);
+            ws.current = list.first().cloned();
+            ws.list = list;
+            ws
+        }).collect();
+        let ws = &mut self.workspaces[active];
+        self.list = std::mem::take(&mut ws.list);
+        self.current = ws.current.clone();
+        self.active = active;
+        self.needs_refresh = true;
+    }
+    /// Makes the workspace active. The current page is the one last used in it.
+    pub fn switch_workspace(&mut self, i: usize) {
+        if i == self.active || i >= self.workspaces.len() {
+            return
+        }
+        let old = &mut self.workspaces[self.active];
+        old.list = std::mem::take(&mut self.list);
+        old.current = self.current.clone();
+        let new = &mut self.workspaces[i];
+        self.list = std::mem::take(&mut new.list);
+        // stays in the page if the workspace has never shown a page
+        if let Some(page) = new.current.take() {
+            self.current = Some(page);
+        }
+        self.active = i;
+        self.needs_refresh = true;
+    }
+    /// Creates the workspace and switches to it. It starts with the current page.
+    /// Switches to the workspace if the name exists.
+    pub fn new_workspace(&mut self, name: &str) {
+        if let Some(i) = self.workspaces.iter().position(|ws| ws.name == name) {
+            self.switch_workspace(i);
+            return
+        }
+        let mut ws = Workspace::new(name);
+        let page = self.list.first().cloned().or_else(|| self.current.clone());
+        if let Some(page) = page {
+            ws.list.push(page.clone());
+            ws.current = Some(page);
+        }
+        self.workspaces.push(ws);
+        self.switch_workspace(self.workspaces.len() - 1);
+    }
+    /// Removes the workspace and closes its pages as in quitting.
+    /// The active one and the one with a dirty page can't be removed.
+    pub fn delete_workspace(&mut self, i: usize) -> Result<(), String> {
+        if i == self.active || i >= self.workspaces.len() {
+            return Err("The active workspace can't be closed".to_owned())
+        }
+        if let Some(page) = self.workspaces[i].list.iter().find(|e| e.borrow().dirty()) {
+            return Err(format!("{} isn't saved", page.borrow().id()))
+        }
+        let ws = self.workspaces.remove(i);
+        // the pages also in the other workspaces are still open
+        let is_open = |page: &Rc<RefCell<dyn Page>>| {
+            self.list.iter().chain(self.workspaces.iter().flat_map(|ws| ws.list.iter())).any(|e| Rc::ptr_eq(e, page))
+        };
+        for page in &ws.list {
+            if !is_open(page) {
+                page.borrow().quit();
+            }
+        }
+        if i < self.active {
+            self.active -= 1;
+        }
+        self.needs_refresh = true;
+        Ok(())
     }
     pub fn set(&mut self, page: Rc<RefCell<Page>>) {
         self.needs_refresh = true;
@@ -90,13 +250,25 @@ impl Navigator {
             }
         }
     }
+    /// Removes the page of the id if it's in the list.
+    pub fn close(&mut self, id: &str) {
+        if let Some(i) = self.list.iter().position(|e| e.borrow().id() == id) {
+            self.delete(i);
+        }
+    }
     pub fn pop_and_push(&mut self, e: Rc<RefCell<Page>>) {
         self.list.remove(0);
         self.list.insert(0, e);
         self.select(0);
     }
     pub fn eff_select(&mut self, _: Key) -> String {
-        self.select(self.rb.cursor.row);
+        let row = self.rb.cursor.row;
+        let n = self.workspaces.len();
+        if row < n {
+            self.switch_workspace(row);
+        } else {
+            self.select(row - n);
+        }
         INIT.to_owned()
     }
     pub fn eff_select_cur_directory(&mut self, _: Key) -> String {
@@ -112,9 +284,47 @@ impl Navigator {
         INIT.to_owned()
     }
     pub fn eff_close_selected(&mut self, _: Key) -> String {
-        self.delete(self.rb.cursor.row);
+        let row = self.rb.cursor.row;
+        let n = self.workspaces.len();
+        if row >= n {
+            self.delete(row - n);
+        } else if let Err(e) = self.delete_workspace(row) {
+            self.message_box.send(&e);
+        }
+        INIT.to_owned()
+    }
+    fn show_workspace_name(&self) {
+        self.message_box.send(&format!("workspace: {}", self.workspace_name));
+    }
+    pub fn eff_enter_workspace_input(&mut self, _: Key) -> String {
+        self.workspace_name.clear();
+        self.show_workspace_name();
+        WORKSPACE_INPUT.to_owned()
+    }
+    pub fn eff_cancel_workspace_input(&mut self, _: Key) -> String {
+        self.message_box.send("");
         INIT.to_owned()
     }
+    pub fn eff_workspace_input(&mut self, k: Key) -> String {
+        match k {
+            Key::Char('\n') => {
+                self.message_box.send("");
+                if !self.workspace_name.is_empty() {
+                    let name = self.workspace_name.clone();
+                    self.new_workspace(&name);
+                }
+                return INIT.to_owned()
+            },
+            Key::Backspace if self.workspace_name.is_empty() => return self.eff_cancel_workspace_input(k),
+            Key::Backspace => {
+                self.workspace_name.pop();
+            },
+            Key::Char(c) => self.workspace_name.push(c),
+            _ => {},
+        }
+        self.show_workspace_name();
+        WORKSPACE_INPUT.to_owned()
+    }
 }
 
 use crate::controller::Effect;
@@ -125,6 +335,9 @@ def_effect!(Select, Navigator, eff_select);
 def_effect!(SelectCurDirectory, Navigator, eff_select_cur_directory);
 def_effect!(SelectCurBuffer, Navigator, eff_select_cur_buffer);
 def_effect!(CloseSelected, Navigator, eff_close_selected);
+def_effect!(EnterWorkspaceInput, Navigator, eff_enter_workspace_input);
+def_effect!(CancelWorkspaceInput, Navigator, eff_cancel_workspace_input);
+def_effect!(WorkspaceInput, Navigator, eff_workspace_input);
 
 use crate::shared::AsRefMut;
 pub fn mk_controller(x: Rc<RefCell<Navigator>>) -> controller::ControllerFSM {
@@ -136,6 +349,10 @@ pub fn mk_controller(x: Rc<RefCell<Navigator>>) -> controller::ControllerFSM {
     g.add_edge(INIT, Char('h'), Rc::new(SelectCurDirectory(x.clone())));
     g.add_edge(INIT, Char('l'), Rc::new(SelectCurBuffer(x.clone())));
     g.add_edge(INIT, Char('d'), Rc::new(CloseSelected(x.clone())));
+    g.add_edge(INIT, Char('w'), Rc::new(EnterWorkspaceInput(x.clone())));
+    g.add_edge(WORKSPACE_INPUT, Esc, Rc::new(CancelWorkspaceInput(x.clone())));
+    g.add_edge(WORKSPACE_INPUT, Otherwise, Rc::new(WorkspaceInput(x.clone())));
+    crate::keymap::apply(&mut g, PageKind::Navigator);
     controller::ControllerFSM::new(INIT, Box::new(g))
 }
 pub struct ViewGen {
@@ -218,9 +435,11 @@ impl Page for NavigatorPage {
         let state: &str = match self.x.borrow().state.get().as_str() {
             read_buffer::INIT => "*",
             read_buffer::SEARCH => "/",
+            WORKSPACE_INPUT => "w",
             _ => "*",
         };
-        format!("[Navigator -{}-]", state)
+        let x = self.x.borrow();
+        format!("[Navigator -{}-] {}", state, x.workspaces[x.active].name)
     }
     fn kind(&self) -> PageKind {
         PageKind::Navigator
@@ -5,8 +5,14 @@ use self::visibility_window::VisibilityWindow;
 use self::search::Search;
 use std::io::Write;
 
-mod visibility_window;
+pub(crate) mod visibility_window;
 pub mod search;
+pub mod rope;
+pub mod storage;
+pub mod text_object;
+
+pub use self::rope::Rope;
+pub use self::storage::Storage;
 
 type Buf = Vec<Vec<BufElem>>;
 
@@ -105,17 +111,17 @@ fn test_line_word_range() {
     assert_eq!(line.word_range(4), None);
 }
 
-pub fn write_to_file<W: Write>(mut out: W, buf: &Buf) {
+pub fn write_to_file<W: Write, S: Storage + ?Sized>(mut out: W, buf: &S) -> std::io::Result<()> {
     // TODO trim the eols from the back
     for i in 0..buf.len() {
-        for j in 0..buf[i].len() {
-            let e = &buf[i][j];
+        for e in buf.line(i) {
             match *e {
-                BufElem::Char(c) => write!(out, "{}", c).unwrap(),
-                BufElem::Eol => writeln!(out).unwrap(),
+                BufElem::Char(c) => write!(out, "{}", c)?,
+                BufElem::Eol => writeln!(out)?,
             }
         }
     }
+    Ok(())
 }
 
 fn convert_to_bufelems(cs: Vec<char>) -> Vec<BufElem> {
@@ -138,13 +144,13 @@ pub fn read_from_string(s: Option<String>) -> Buf {
     }).unwrap_or(vec![vec![BufElem::Eol]])
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum BufElem {
     Char(char),
     Eol,
 }
 
-#[derive(Copy, Clone, PartialOrd, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialOrd, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Cursor {
     pub row: usize,
     pub col: usize,
@@ -158,10 +164,54 @@ pub struct CursorRange {
 
 pub const INIT: &str = "Normal";
 pub const SEARCH: &str = "Search";
-pub const JUMP: &str = "Jump";
+pub const JUMP: &str = "Jump"; // typing the count
+
+/// The cursor movements. Operators in the pages take them as the range to operate on.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    LineHead,
+    LineLast,
+    Last, // the line of the count
+    WordForward,
+    WordBackward,
+    SearchForward,
+    SearchBackward,
+}
+pub const MOTIONS: [(char, Motion); 11] = [
+    ('h', Motion::Left),
+    ('l', Motion::Right),
+    ('k', Motion::Up),
+    ('j', Motion::Down),
+    ('0', Motion::LineHead),
+    ('$', Motion::LineLast),
+    ('G', Motion::Last),
+    ('w', Motion::WordForward),
+    ('b', Motion::WordBackward),
+    ('n', Motion::SearchForward),
+    ('N', Motion::SearchBackward),
+];
+impl Motion {
+    pub fn from_key(k: &Key) -> Option<Self> {
+        match *k {
+            Key::Char(c) => MOTIONS.iter().find(|(x, _)| *x == c).map(|(_, m)| *m),
+            _ => None,
+        }
+    }
+    /// Operates on the whole lines between the cursor and the target.
+    pub fn linewise(self) -> bool {
+        match self {
+            Motion::Up | Motion::Down | Motion::Last => true,
+            _ => false,
+        }
+    }
+}
 
 pub struct ReadBuffer {
-    pub buf: Vec<Vec<BufElem>>,
+    pub buf: Rope<Vec<BufElem>>,
     pub cursor: Cursor,
     num_buffer: Vec<char>,
     pub window: VisibilityWindow,
@@ -174,7 +224,7 @@ impl ReadBuffer {
     pub fn new(init_buf: Vec<Vec<BufElem>>, state: PageState, message_box: MessageBox) -> Self {
         let n_rows = init_buf.len();
         Self {
-            buf: init_buf,
+            buf: Rope::from(init_buf),
             cursor: Cursor { row: 0, col: 0 },
             num_buffer: vec![],
             window: VisibilityWindow::new(Cursor { col: 0, row: 0 }),
@@ -272,15 +322,50 @@ impl ReadBuffer {
             _ => panic!(),
         }
     }
-    pub fn jump(&mut self) {
-        let mut s = String::new();
-        for c in self.num_buffer.clone() {
-            s.push(c);
+    /// Takes the number typed in the jump mode.
+    pub fn take_num(&mut self) -> usize {
+        let s: String = self.num_buffer.drain(..).collect();
+        s.parse::<usize>().unwrap()
+    }
+    /// Takes the count typed before the command. None if no number is typed.
+    pub fn take_count(&mut self) -> Option<usize> {
+        if self.num_buffer.is_empty() {
+            None
+        } else {
+            Some(self.take_num())
+        }
+    }
+    pub fn has_count(&self) -> bool {
+        !self.num_buffer.is_empty()
+    }
+    /// Moves the cursor by the motion count times.
+    /// G goes to the line of the count or the last line without the count.
+    pub fn apply_motion(&mut self, m: Motion, count: Option<usize>) {
+        if m == Motion::Last {
+            match count {
+                Some(n) => {
+                    self.cursor.row = std::cmp::min(std::cmp::max(n, 1), self.buf.len()) - 1;
+                    self.cursor.col = 0;
+                },
+                None => self.jump_last(),
+            }
+            return
+        }
+        for _ in 0..count.unwrap_or(1) {
+            match m {
+                Motion::Left => self.cursor_left(),
+                Motion::Right => self.cursor_right(),
+                Motion::Up => self.cursor_up(),
+                Motion::Down => self.cursor_down(),
+                Motion::LineHead => self.jump_line_head(),
+                Motion::LineLast => self.jump_line_last(),
+                Motion::WordForward => self.jump_word_forward(),
+                Motion::WordBackward => self.jump_word_backward(),
+                Motion::SearchForward => self.search_jump_forward(),
+                Motion::SearchBackward => self.search_jump_backward(),
+                Motion::Last => unreachable!(),
+            }
         }
-        let n = s.parse::<usize>().unwrap();
-        let row = n-1;
-        self.cursor.row = row;
-        self.cursor.col = 0;
     }
     pub fn cancel_jump(&mut self) {
         self.num_buffer.clear();
@@ -347,6 +432,7 @@ impl ReadBuffer {
         for x in next {
             self.cursor = x;
         }
+        self.search.update_status(self.cursor, &self.buf);
     }
     pub fn search_jump_backward(&mut self) {
         self.search.show_search();
@@ -354,6 +440,14 @@ impl ReadBuffer {
         for x in prev {
             self.cursor = x;
         }
+        self.search.update_status(self.cursor, &self.buf);
+    }
+    /// " [3/17]" to append to the status line.
+    pub fn search_status(&self) -> String {
+        match self.search.status() {
+            Some(x) => format!(" [{}]", x),
+            None => "".to_owned(),
+        }
     }
     pub fn adjust_window(&mut self, w: usize, h: usize) {
         self.window.adjust_window(self.cursor, w, h);
@@ -387,28 +481,9 @@ impl ReadBuffer {
         self.reset();
         INIT.to_owned()
     }
-    pub fn eff_cursor_up(&mut self, _: Key) -> String {
-        self.cursor_up();
-        INIT.to_owned()
-    }
-    pub fn eff_cursor_down(&mut self, _: Key) -> String {
-        self.cursor_down();
-        INIT.to_owned()
-    }
-    pub fn eff_cursor_left(&mut self, _: Key) -> String {
-        self.cursor_left();
-        INIT.to_owned()
-    }
-    pub fn eff_cursor_right(&mut self, _: Key) -> String {
-        self.cursor_right();
-        INIT.to_owned()
-    }
-    pub fn eff_jump_line_head(&mut self, _: Key) -> String {
-        self.jump_line_head();
-        INIT.to_owned()
-    }
-    pub fn eff_jump_line_last(&mut self, _: Key) -> String {
-        self.jump_line_last();
+    fn eff_move(&mut self, k: Key) -> String {
+        let count = self.take_count();
+        self.apply_motion(Motion::from_key(&k).unwrap(), count);
         INIT.to_owned()
     }
     pub fn eff_jump_page_forward(&mut self, _: Key) -> String {
@@ -419,14 +494,6 @@ impl ReadBuffer {
         self.jump_page_backward();
         INIT.to_owned()
     }
-    fn eff_jump_word_forward(&mut self, _: Key) -> String {
-        self.jump_word_forward();
-        INIT.to_owned()
-    }
-    fn eff_jump_word_backward(&mut self, _: Key) -> String {
-        self.jump_word_backward();
-        INIT.to_owned()
-    }
     pub fn eff_enter_jump_mode(&mut self, k: Key) -> String {
         self.enter_jump_mode(k);
         JUMP.to_owned()
@@ -435,18 +502,10 @@ impl ReadBuffer {
         self.acc_jump_num(k);
         JUMP.to_owned()
     }
-    pub fn eff_jump(&mut self, _: Key) -> String {
-        self.jump();
-        INIT.to_owned()
-    }
     pub fn eff_cancel_jump(&mut self, _: Key) -> String {
         self.cancel_jump();
         INIT.to_owned()
     }
-    pub fn eff_jump_last(&mut self, _: Key) -> String {
-        self.jump_last();
-        INIT.to_owned()
-    }
     pub fn eff_enter_search_mode(&mut self, _: Key) -> String {
         self.enter_search_mode();
         SEARCH.to_owned()
@@ -455,6 +514,10 @@ impl ReadBuffer {
         self.search_mode_input(k);
         SEARCH.to_owned()
     }
+    fn eff_toggle_search_mode(&mut self, _: Key) -> String {
+        self.search.toggle_mode();
+        SEARCH.to_owned()
+    }
     pub fn eff_leave_search_mode(&mut self, _: Key) -> String {
         self.leave_search_mode();
         INIT.to_owned()
@@ -463,72 +526,49 @@ impl ReadBuffer {
         self.cancel_search_mode();
         INIT.to_owned()
     }
-    pub fn eff_search_jump_forward(&mut self, _: Key) -> String {
-        self.search_jump_forward();
-        INIT.to_owned()
-    }
-    pub fn eff_search_jump_backward(&mut self, _: Key) -> String {
-        self.search_jump_backward();
-        INIT.to_owned()
-    }
 }
 
 use crate::controller::{PageState, Effect};
 use crate::def_effect;
 
-def_effect!(CursorUp, ReadBuffer, eff_cursor_up);
-def_effect!(CursorDown, ReadBuffer, eff_cursor_down);
-def_effect!(CursorLeft, ReadBuffer, eff_cursor_left);
-def_effect!(CursorRight, ReadBuffer, eff_cursor_right);
-def_effect!(JumpLineHead, ReadBuffer, eff_jump_line_head);
-def_effect!(JumpLineLast, ReadBuffer, eff_jump_line_last);
+def_effect!(Move, ReadBuffer, eff_move);
 def_effect!(JumpPageForward, ReadBuffer, eff_jump_page_forward);
 def_effect!(JumpPageBackward, ReadBuffer, eff_jump_page_backward);
 def_effect!(EnterJumpMode, ReadBuffer, eff_enter_jump_mode);
 def_effect!(AccJumpNum, ReadBuffer, eff_acc_jump_num);
-def_effect!(Jump, ReadBuffer, eff_jump);
 def_effect!(CancelJump, ReadBuffer, eff_cancel_jump);
-def_effect!(JumpLast, ReadBuffer, eff_jump_last);
-def_effect!(JumpWordForward, ReadBuffer, eff_jump_word_forward);
-def_effect!(JumpWordBackward, ReadBuffer, eff_jump_word_backward);
 def_effect!(Reset, ReadBuffer, eff_reset);
 
 def_effect!(EnterSearchMode, ReadBuffer, eff_enter_search_mode);
 def_effect!(SearchModeInput, ReadBuffer, eff_search_mode_input);
 def_effect!(LeaveSearchMode, ReadBuffer, eff_leave_search_mode);
+def_effect!(ToggleSearchMode, ReadBuffer, eff_toggle_search_mode);
 def_effect!(CancelSearchMode, ReadBuffer, eff_cancel_search_mode);
-def_effect!(SearchJumpForward, ReadBuffer, eff_search_jump_forward);
-def_effect!(SearchJumpBackward, ReadBuffer, eff_search_jump_backward);
 
 pub fn add_edges<S: crate::shared::AsRefMut<ReadBuffer> + 'static>(g: &mut crate::controller::Graph, x: S) {
     use std::rc::Rc;
     use crate::Key::*;
 
     // normal movement
-    g.add_edge(INIT, Char('k'), Rc::new(CursorUp(x.clone())));
-    g.add_edge(INIT, Char('j'), Rc::new(CursorDown(x.clone())));
-    g.add_edge(INIT, Char('h'), Rc::new(CursorLeft(x.clone())));
-    g.add_edge(INIT, Char('l'), Rc::new(CursorRight(x.clone())));
-    g.add_edge(INIT, Char('0'), Rc::new(JumpLineHead(x.clone())));
-    g.add_edge(INIT, Char('$'), Rc::new(JumpLineLast(x.clone())));
+    for (c, _) in MOTIONS.iter() {
+        g.add_edge(INIT, Char(*c), Rc::new(Move(x.clone())));
+    }
     g.add_edge(INIT, Ctrl('f'), Rc::new(JumpPageForward(x.clone())));
     g.add_edge(INIT, Ctrl('b'), Rc::new(JumpPageBackward(x.clone())));
-    g.add_edge(INIT, Char('G'), Rc::new(JumpLast(x.clone())));
-    g.add_edge(INIT, Char('n'), Rc::new(SearchJumpForward(x.clone())));
-    g.add_edge(INIT, Char('N'), Rc::new(SearchJumpBackward(x.clone())));
-    g.add_edge(INIT, Char('w'), Rc::new(JumpWordForward(x.clone())));
-    g.add_edge(INIT, Char('b'), Rc::new(JumpWordBackward(x.clone())));
     g.add_edge(INIT, Esc, Rc::new(Reset(x.clone())));
 
-    // num jump
+    // count (5j, 10G)
     g.add_edge(INIT, CharRange('1', '9'), Rc::new(EnterJumpMode(x.clone())));
     g.add_edge(JUMP, CharRange('0', '9'), Rc::new(AccJumpNum(x.clone())));
-    g.add_edge(JUMP, Char('G'), Rc::new(Jump(x.clone())));
+    for (c, _) in MOTIONS.iter() {
+        g.add_edge(JUMP, Char(*c), Rc::new(Move(x.clone())));
+    }
     g.add_edge(JUMP, Esc, Rc::new(CancelJump(x.clone())));
 
     // search
     g.add_edge(INIT, Char('/'), Rc::new(EnterSearchMode(x.clone())));
     g.add_edge(SEARCH, Char('\n'), Rc::new(LeaveSearchMode(x.clone())));
     g.add_edge(SEARCH, Esc, Rc::new(CancelSearchMode(x.clone())));
+    g.add_edge(SEARCH, Ctrl('t'), Rc::new(ToggleSearchMode(x.clone())));
     g.add_edge(SEARCH, Otherwise, Rc::new(SearchModeInput(x.clone())))
 }
\ No newline at end of file
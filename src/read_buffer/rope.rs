@@ -0,0 +1,252 @@
+use std::ops::{Index, IndexMut};
+use std::rc::Rc;
+
+const MAX_LEAF: usize = 64;
+const MAX_CHILDREN: usize = 16;
+
+/// A persistent B-tree of rows.
+///
+/// Inserting, removing or modifying a row costs O(log n).
+/// Nodes are shared between clones and copied on write
+/// so cloning the whole tree (e.g. to take a snapshot before an edit) is O(1).
+#[derive(Clone)]
+pub struct Rope<T: Clone> {
+    root: Rc<Node<T>>,
+}
+
+#[derive(Clone)]
+enum Node<T: Clone> {
+    Leaf(Vec<T>),
+    Internal(Vec<Rc<Node<T>>>, usize), // children, total number of rows
+}
+
+impl <T: Clone> Node<T> {
+    fn len(&self) -> usize {
+        match self {
+            Node::Leaf(v) => v.len(),
+            Node::Internal(_, n) => *n,
+        }
+    }
+    fn internal(children: Vec<Rc<Node<T>>>) -> Self {
+        let n = children.iter().map(|c| c.len()).sum();
+        Node::Internal(children, n)
+    }
+    /// returns the index of the child that contains the row and the row offset in the child.
+    /// if the row is the end of the node the last child is chosen.
+    fn locate(children: &[Rc<Node<T>>], row: usize) -> (usize, usize) {
+        let mut row = row;
+        let last = children.len() - 1;
+        for (i, c) in children.iter().enumerate() {
+            if row < c.len() || i == last {
+                return (i, row)
+            }
+            row -= c.len();
+        }
+        unreachable!()
+    }
+    fn get(&self, row: usize) -> &T {
+        match self {
+            Node::Leaf(v) => &v[row],
+            Node::Internal(children, _) => {
+                let (i, row) = Self::locate(children, row);
+                children[i].get(row)
+            }
+        }
+    }
+    fn get_mut(&mut self, row: usize) -> &mut T {
+        match self {
+            Node::Leaf(v) => &mut v[row],
+            Node::Internal(children, _) => {
+                let (i, row) = Self::locate(children, row);
+                Rc::make_mut(&mut children[i]).get_mut(row)
+            }
+        }
+    }
+    /// returns the new right sibling if the node is split.
+    fn insert(&mut self, row: usize, x: T) -> Option<Node<T>> {
+        match self {
+            Node::Leaf(v) => {
+                v.insert(row, x);
+                if v.len() > MAX_LEAF {
+                    let right = v.split_off(v.len() / 2);
+                    Some(Node::Leaf(right))
+                } else {
+                    None
+                }
+            },
+            Node::Internal(children, n) => {
+                *n += 1;
+                let (i, row) = Self::locate(children, row);
+                let split = Rc::make_mut(&mut children[i]).insert(row, x);
+                if let Some(right) = split {
+                    children.insert(i + 1, Rc::new(right));
+                }
+                if children.len() > MAX_CHILDREN {
+                    let right = children.split_off(children.len() / 2);
+                    *n -= right.iter().map(|c| c.len()).sum::<usize>();
+                    Some(Self::internal(right))
+                } else {
+                    None
+                }
+            },
+        }
+    }
+    fn remove(&mut self, row: usize) -> T {
+        match self {
+            Node::Leaf(v) => v.remove(row),
+            Node::Internal(children, n) => {
+                *n -= 1;
+                let (i, row) = Self::locate(children, row);
+                let removed = Rc::make_mut(&mut children[i]).remove(row);
+                // underflow is allowed but an empty child is dropped
+                if children[i].len() == 0 && children.len() > 1 {
+                    children.remove(i);
+                }
+                removed
+            },
+        }
+    }
+    fn collect(&self, out: &mut Vec<T>) {
+        match self {
+            Node::Leaf(v) => out.extend(v.iter().cloned()),
+            Node::Internal(children, _) => {
+                for c in children {
+                    c.collect(out);
+                }
+            }
+        }
+    }
+}
+
+impl <T: Clone> Rope<T> {
+    pub fn new() -> Self {
+        Self { root: Rc::new(Node::Leaf(vec![])) }
+    }
+    pub fn len(&self) -> usize {
+        self.root.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn insert(&mut self, row: usize, x: T) {
+        assert!(row <= self.len());
+        let split = Rc::make_mut(&mut self.root).insert(row, x);
+        if let Some(right) = split {
+            let left = self.root.clone();
+            self.root = Rc::new(Node::internal(vec![left, Rc::new(right)]));
+        }
+    }
+    pub fn remove(&mut self, row: usize) -> T {
+        assert!(row < self.len());
+        let removed = Rc::make_mut(&mut self.root).remove(row);
+        // shrink the height if the root has only one child
+        let only_child = match &*self.root {
+            Node::Internal(children, _) if children.len() == 1 => Some(children[0].clone()),
+            _ => None,
+        };
+        if let Some(child) = only_child {
+            self.root = child;
+        }
+        removed
+    }
+    pub fn push(&mut self, x: T) {
+        let n = self.len();
+        self.insert(n, x);
+    }
+    pub fn to_vec(&self) -> Vec<T> {
+        let mut v = Vec::with_capacity(self.len());
+        self.root.collect(&mut v);
+        v
+    }
+}
+
+impl <T: Clone> Default for Rope<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <T: Clone> From<Vec<T>> for Rope<T> {
+    fn from(v: Vec<T>) -> Self {
+        let mut v = v;
+        let mut nodes = vec![];
+        while !v.is_empty() {
+            let rest = v.split_off(std::cmp::min(MAX_LEAF, v.len()));
+            nodes.push(Rc::new(Node::Leaf(v)));
+            v = rest;
+        }
+        if nodes.is_empty() {
+            return Self::new()
+        }
+        while nodes.len() > 1 {
+            let mut upper = vec![];
+            while !nodes.is_empty() {
+                let rest = nodes.split_off(std::cmp::min(MAX_CHILDREN, nodes.len()));
+                upper.push(Rc::new(Node::internal(nodes)));
+                nodes = rest;
+            }
+            nodes = upper;
+        }
+        Self { root: nodes.pop().unwrap() }
+    }
+}
+
+impl <T: Clone> Index<usize> for Rope<T> {
+    type Output = T;
+    fn index(&self, row: usize) -> &T {
+        self.root.get(row)
+    }
+}
+
+impl <T: Clone> IndexMut<usize> for Rope<T> {
+    fn index_mut(&mut self, row: usize) -> &mut T {
+        Rc::make_mut(&mut self.root).get_mut(row)
+    }
+}
+
+#[test]
+fn test_rope_insert_remove() {
+    let mut rope = Rope::new();
+    let mut v = vec![];
+    // enough rows to make the tree a few levels deep
+    for i in 0..5000 {
+        let row = (i * 7) % (v.len() + 1);
+        rope.insert(row, i);
+        v.insert(row, i);
+    }
+    assert_eq!(rope.len(), v.len());
+    assert_eq!(rope.to_vec(), v);
+    for i in 0..4000 {
+        let row = (i * 13) % v.len();
+        assert_eq!(rope.remove(row), v.remove(row));
+    }
+    assert_eq!(rope.to_vec(), v);
+    for (i, x) in v.iter().enumerate() {
+        assert_eq!(rope[i], *x);
+    }
+}
+
+#[test]
+fn test_rope_copy_on_write() {
+    let v: Vec<usize> = (0..1000).collect();
+    let mut a = Rope::from(v.clone());
+    assert_eq!(a.to_vec(), v);
+    let b = a.clone();
+    a[500] = 0;
+    a.remove(0);
+    a.insert(0, 1);
+    assert_eq!(b.to_vec(), v);
+    assert_eq!(a[500], 0);
+    assert_eq!(a[0], 1);
+    assert_eq!(a.len(), 1000);
+}
+
+#[test]
+fn test_rope_empty() {
+    let mut rope = Rope::from(vec![]);
+    assert!(rope.is_empty());
+    rope.push('a');
+    rope.push('b');
+    assert_eq!(rope.remove(0), 'a');
+    assert_eq!(rope.to_vec(), vec!['b']);
+}
@@ -1,25 +1,59 @@
 use crate::view;
 use crate::screen;
-use super::{Cursor, BufElem};
+use super::{Cursor, BufElem, Rope, Storage};
 use crate::message_box::MessageBox;
+use regex::Regex;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    Literal, // ignore case
+    SmartCase, // ignore case unless the word has an upper case letter
+    Regex,
+}
+impl Mode {
+    pub fn next(self) -> Self {
+        match self {
+            Mode::Literal => Mode::SmartCase,
+            Mode::SmartCase => Mode::Regex,
+            Mode::Regex => Mode::Literal,
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Literal => "literal",
+            Mode::SmartCase => "smartcase",
+            Mode::Regex => "regex",
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 /// invariant: L(search_word) == L(results)
 struct CacheLine {
+    case_sensitive: bool,
     search_word: Vec<char>,
-    results: Vec<Vec<usize>>
+    results: Vec<Vec<usize>>,
+    // the regex mode isn't incremental.
+    // the pattern and the hits (col, len).
+    regex: Option<(String, Vec<(usize, usize)>)>,
 }
-fn eq(e: &BufElem, c: char) -> bool {
+fn eq(e: &BufElem, c: char, case_sensitive: bool) -> bool {
     match e {
         &BufElem::Eol => false,
-        &BufElem::Char(x) => x.to_string().to_lowercase() == c.to_string().to_lowercase()
+        &BufElem::Char(x) => if case_sensitive {
+            x == c
+        } else {
+            x.to_string().to_lowercase() == c.to_string().to_lowercase()
+        }
     }
 }
 impl CacheLine {
     fn new() -> Self {
         CacheLine {
+            case_sensitive: false,
             search_word: vec![],
             results: vec![],
+            regex: None,
         }
     }
     fn rollback_search(&mut self, new_search_word: &[char]) -> usize {
@@ -44,7 +78,7 @@ impl CacheLine {
         let n_sw = self.search_word.len();
         if n_sw == 0 {
             for (i, e) in line.iter().enumerate() {
-                if eq(e, new_c) {
+                if eq(e, new_c, self.case_sensitive) {
                     v.push(i)
                 }
             }
@@ -54,7 +88,7 @@ impl CacheLine {
             for i in last {
                 // as hit eol once the hit is removed
                 // this code is safe without the care for out of boundary case.
-                if eq(&line[i+n], new_c) {
+                if eq(&line[i+n], new_c, self.case_sensitive) {
                     v.push(*i);
                 }
             }
@@ -70,22 +104,51 @@ impl CacheLine {
             self.results[n_sw-1].clone()
         }
     }
-    fn next(&self, i: Option<usize>) -> Option<usize> {
-        match i {
-            Some(i) => {
-                self.result().into_iter().find(|j| *j > i)
-            },
-            None => self.result().first().cloned()
-        }
+}
+
+fn next_col(starts: Vec<usize>, i: Option<usize>) -> Option<usize> {
+    match i {
+        Some(i) => {
+            starts.into_iter().find(|j| *j > i)
+        },
+        None => starts.first().cloned()
     }
-    fn prev(&self, i: Option<usize>) -> Option<usize> {
-        match i {
-            Some(i) => {
-                self.result().into_iter().rev().find(|j| *j < i)
-            },
-            None => self.result().last().cloned()
+}
+fn prev_col(starts: Vec<usize>, i: Option<usize>) -> Option<usize> {
+    match i {
+        Some(i) => {
+            starts.into_iter().rev().find(|j| *j < i)
+        },
+        None => starts.last().cloned()
+    }
+}
+
+/// The hits starting in the row.
+/// The following rows are joined as many as the newlines in the pattern so a hit can span lines.
+fn regex_hits<S: Storage + ?Sized>(re: &Regex, row: usize, buf: &S) -> Vec<(usize, usize)> {
+    let n_lines = re.as_str().matches("\\n").count() + 1;
+    let mut s = String::new();
+    let mut cols = vec![]; // byte offset -> col
+    let mut col = 0;
+    for r in row..std::cmp::min(row + n_lines, buf.len()) {
+        for e in buf.line(r) {
+            let c = match *e {
+                BufElem::Char(c) => c,
+                BufElem::Eol => '\n',
+            };
+            for _ in 0..c.len_utf8() {
+                cols.push(col);
+            }
+            s.push(c);
+            col += 1;
         }
     }
+    cols.push(col);
+    let n = buf.line(row).len();
+    re.find_iter(&s)
+        .filter(|m| m.start() < m.end() && cols[m.start()] < n)
+        .map(|m| (cols[m.start()], cols[m.end()] - cols[m.start()]))
+        .collect()
 }
 
 #[test]
@@ -119,48 +182,86 @@ fn test_hit() {
 #[derive(Clone)]
 pub struct Search {
     pub cur_word: Vec<char>,
-    cache: Vec<CacheLine>,
+    mode: Mode,
+    regex: Option<Regex>, // None if the word isn't a valid regex
+    cache: Rope<CacheLine>,
+    // the most rows a cached regex hit has looked at
+    regex_span: usize,
     message_box: MessageBox,
     show: bool,
+    status: Option<(usize, usize)>, // the index of the current hit and the number of hits
 }
 impl Search {
     pub fn new(n_rows: usize, message_box: MessageBox) -> Self {
         Self {
             cur_word: vec![],
-            cache: vec![CacheLine::new(); n_rows],
+            mode: Mode::Literal,
+            regex: None,
+            cache: Rope::from(vec![CacheLine::new(); n_rows]),
+            regex_span: 1,
             show: false,
+            status: None,
             message_box,
         }
     }
     pub fn hide_search(&mut self) {
         self.show = false;
+        self.status = None;
     }
     pub fn show_search(&mut self) {
         self.show = true;
     }
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+    pub fn toggle_mode(&mut self) {
+        self.mode = self.mode.next();
+        self.update_regex();
+        self.show_search_word();
+    }
     pub fn clear_search_word(&mut self) {
         self.cur_word.clear();
+        self.status = None;
+        self.update_regex();
         self.show_search_word();
     }
     fn show_search_word(&self) {
-        if self.cur_word.is_empty() {
-            self.message_box.send("");
-        } else {
-            let mut x = String::new();
-            for c in &self.cur_word {
-                x.push(*c)
-            }
-            self.message_box.send(&x);
+        let mut x = String::new();
+        if self.mode != Mode::Literal {
+            x.push_str(&format!("({}) ", self.mode.name()));
+        }
+        for c in &self.cur_word {
+            x.push(*c)
         }
+        if self.mode == Mode::Regex && self.regex.is_none() && !self.cur_word.is_empty() {
+            x.push_str(" [invalid]");
+        }
+        self.message_box.send(&x);
     }
     pub fn push_search_word(&mut self, c: char) {
         self.cur_word.push(c);
+        self.update_regex();
         self.show_search_word();
     }
     pub fn pop_search_word(&mut self) {
         self.cur_word.pop();
+        self.update_regex();
         self.show_search_word();
     }
+    fn update_regex(&mut self) {
+        self.regex = if self.mode == Mode::Regex && !self.cur_word.is_empty() {
+            let s: String = self.cur_word.iter().collect();
+            Regex::new(&s).ok()
+        } else {
+            None
+        };
+    }
+    fn case_sensitive(&self) -> bool {
+        match self.mode {
+            Mode::SmartCase => self.cur_word.iter().any(|c| c.is_uppercase()),
+            _ => false,
+        }
+    }
     fn restruct_cache(&mut self, row: usize, deleted: usize, inserted: usize) {
         for _ in 0..deleted {
             self.cache.remove(row);
@@ -171,31 +272,91 @@ impl Search {
     }
     pub fn cache_insert_new_line(&mut self, row: usize) {
         self.cache.insert(row, CacheLine::new());
+        self.invalidate_regex_above(row);
     }
     pub fn cache_remove_line(&mut self, row: usize) {
         self.cache.remove(row);
+        self.invalidate_regex_above(row);
+    }
+    // the regex hits of the rows above may span the changed row
+    fn invalidate_regex_above(&mut self, row: usize) {
+        for r in row.saturating_sub(self.regex_span - 1)..std::cmp::min(row, self.cache.len()) {
+            self.cache[r].regex = None;
+        }
     }
     // tmp: instead of diff update
     // slow version. clear the data on every change
     pub fn clear_cache(&mut self, n_rows: usize) {
-        self.cache = vec![CacheLine::new(); n_rows];
+        self.cache = Rope::from(vec![CacheLine::new(); n_rows]);
     }
-    fn update_cache_line(&mut self, row: usize, buf: &[Vec<BufElem>]) {
-        let n = self.cache[row].rollback_search(&self.cur_word);
+    fn update_cache_line<S: Storage + ?Sized>(&mut self, row: usize, buf: &S) {
+        if self.mode == Mode::Regex {
+            let pattern = self.regex.as_ref().map(|re| re.as_str().to_owned()).unwrap_or_default();
+            let cache_line = &mut self.cache[row];
+            if cache_line.regex.as_ref().map(|x| &x.0) != Some(&pattern) {
+                let hits = match self.regex.as_ref() {
+                    Some(re) => {
+                        self.regex_span = std::cmp::max(self.regex_span, re.as_str().matches("\\n").count() + 1);
+                        regex_hits(re, row, buf)
+                    },
+                    None => vec![],
+                };
+                self.cache[row].regex = Some((pattern, hits));
+            }
+            return
+        }
+        let case_sensitive = self.case_sensitive();
+        let cache_line = &mut self.cache[row];
+        if cache_line.case_sensitive != case_sensitive {
+            // the results so far are of the other case sensitivity
+            cache_line.rollback_search(&[]);
+            cache_line.case_sensitive = case_sensitive;
+        }
+        let n = cache_line.rollback_search(&self.cur_word);
         // if L(cur_word) == n this slice is empty
         for c in &self.cur_word[n..] {
-            self.cache[row].inc_search(*c, &buf[row]);
+            cache_line.inc_search(*c, buf.line(row));
+        }
+    }
+    /// The hits (col, len) in the row.
+    fn hits(&self, row: usize) -> Vec<(usize, usize)> {
+        let cache_line = &self.cache[row];
+        if self.mode == Mode::Regex {
+            cache_line.regex.as_ref().map(|x| x.1.clone()).unwrap_or_default()
+        } else {
+            let n = self.cur_word.len();
+            cache_line.result().into_iter().map(|col| (col, n)).collect()
+        }
+    }
+    fn starts(&self, row: usize) -> Vec<usize> {
+        self.hits(row).into_iter().map(|x| x.0).collect()
+    }
+    /// The length of the hit starting at the cursor.
+    pub fn hit_at<S: Storage + ?Sized>(&mut self, cur: Cursor, buf: &S) -> Option<usize> {
+        self.update_cache_line(cur.row, buf);
+        self.hits(cur.row).into_iter().find(|x| x.0 == cur.col).map(|x| x.1)
+    }
+    /// The replacement of the hit. `$1` or `${name}` is expanded in the regex mode.
+    pub fn expand(&self, hit: &str, replacement: &str) -> String {
+        match self.regex.as_ref().and_then(|re| re.captures(hit)) {
+            Some(caps) if self.mode == Mode::Regex => {
+                let mut s = String::new();
+                caps.expand(replacement, &mut s);
+                s
+            },
+            _ => replacement.to_owned(),
         }
     }
     /// ensure:
     /// L(this) == L(buf)
-    pub fn update_cache(&mut self, range: std::ops::Range<usize>, buf: &[Vec<BufElem>]) {
+    pub fn update_cache<S: Storage + ?Sized>(&mut self, range: std::ops::Range<usize>, buf: &S) {
         for row in range {
             self.update_cache_line(row, buf)
         }
     }
-    pub fn next(&mut self, cur: Cursor, buf: &[Vec<BufElem>]) -> Option<Cursor> {
-        match self.cache[cur.row].next(Some(cur.col)) {
+    pub fn next<S: Storage + ?Sized>(&mut self, cur: Cursor, buf: &S) -> Option<Cursor> {
+        self.update_cache_line(cur.row, buf);
+        match next_col(self.starts(cur.row), Some(cur.col)) {
             Some(next_col) => Some(Cursor { row: cur.row, col: next_col }),
             None => {
                 let mut search_rows = vec![];
@@ -208,7 +369,7 @@ impl Search {
 
                 search_rows.into_iter().map(|row| {
                     self.update_cache_line(row, buf);
-                    let first0 = self.cache[row].next(None);
+                    let first0 = next_col(self.starts(row), None);
                     match first0 {
                         Some(first) => Some(Cursor { row: row, col: first }),
                         None => None,
@@ -217,8 +378,9 @@ impl Search {
             }
         }
     }
-    pub fn prev(&mut self, cur: Cursor, buf: &[Vec<BufElem>]) -> Option<Cursor> {
-        match self.cache[cur.row].prev(Some(cur.col)) {
+    pub fn prev<S: Storage + ?Sized>(&mut self, cur: Cursor, buf: &S) -> Option<Cursor> {
+        self.update_cache_line(cur.row, buf);
+        match prev_col(self.starts(cur.row), Some(cur.col)) {
             Some(prev_col) => Some(Cursor { row: cur.row, col: prev_col }),
             None => {
                 let mut search_rows = vec![];
@@ -230,7 +392,7 @@ impl Search {
                 }
                 search_rows.into_iter().map(|row| {
                     self.update_cache_line(row, buf);
-                    let last0 = self.cache[row].prev(None);
+                    let last0 = prev_col(self.starts(row), None);
                     match last0 {
                         Some(last) => Some(Cursor { row: row, col: last }),
                         None => None,
@@ -239,6 +401,25 @@ impl Search {
             }
         }
     }
+    /// Counts the hits in the buffer and finds the index of the one at the cursor.
+    pub fn update_status<S: Storage + ?Sized>(&mut self, cur: Cursor, buf: &S) {
+        let mut total = 0;
+        let mut index = 0;
+        for row in 0..self.cache.len() {
+            self.update_cache_line(row, buf);
+            for col in self.starts(row) {
+                total += 1;
+                if (Cursor { row, col }) <= cur {
+                    index = total;
+                }
+            }
+        }
+        self.status = if total > 0 { Some((index, total)) } else { None };
+    }
+    /// "3/17" if the hits are counted.
+    pub fn status(&self) -> Option<String> {
+        self.status.map(|(i, n)| format!("{}/{}", i, n))
+    }
 }
 
 pub struct DiffView<'a> {
@@ -253,10 +434,9 @@ impl <'a> DiffView<'a> {
 }
 impl <'a> view::View for DiffView<'a> {
     fn get(&self, col: usize, row: usize) -> view::ViewElem {
-        let search_word_len = self.model.cur_word.len();
         if row >= self.model.cache.len() {
             (None, None, None)
-        } else if self.model.cache[row].result().iter().any(|&s| s <= col && col < s+search_word_len) {
+        } else if self.model.hits(row).iter().any(|&(s, n)| s <= col && col < s+n) {
             if self.model.show {
                 (None, None, Some(screen::Color::Green))
             } else {
@@ -266,4 +446,91 @@ impl <'a> view::View for DiffView<'a> {
             (None, None, None)
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+fn to_buf(lines: &[&str]) -> Vec<Vec<BufElem>> {
+    lines.iter().map(|s| {
+        let mut v: Vec<BufElem> = s.chars().map(BufElem::Char).collect();
+        v.push(BufElem::Eol);
+        v
+    }).collect()
+}
+
+#[test]
+fn test_search_modes() {
+    let buf = to_buf(&["let key = Key::Char;", "key"]);
+    let mut search = Search::new(buf.len(), MessageBox::new());
+    let origin = Cursor { row: 0, col: 0 };
+    for c in "key".chars() {
+        search.push_search_word(c);
+    }
+    search.update_status(origin, &buf);
+    assert_eq!(search.status(), Some("0/3".to_owned()));
+
+    // an upper case letter makes the search case sensitive
+    search.toggle_mode();
+    search.clear_search_word();
+    for c in "Key".chars() {
+        search.push_search_word(c);
+    }
+    assert_eq!(search.next(origin, &buf), Some(Cursor { row: 0, col: 10 }));
+    search.update_status(Cursor { row: 0, col: 10 }, &buf);
+    assert_eq!(search.status(), Some("1/1".to_owned()));
+    // back to lower case
+    for _ in 0..3 {
+        search.pop_search_word();
+    }
+    for c in "key".chars() {
+        search.push_search_word(c);
+    }
+    search.update_status(origin, &buf);
+    assert_eq!(search.status(), Some("0/3".to_owned()));
+
+    search.toggle_mode();
+    search.clear_search_word();
+    for c in r"[A-Z]\w+::".chars() {
+        search.push_search_word(c);
+    }
+    search.update_cache(0..2, &buf);
+    assert_eq!(search.hits(0), vec![(10, 5)]);
+    assert!(search.hits(1).is_empty());
+}
+
+#[test]
+fn test_regex_search_spans_lines() {
+    let buf = to_buf(&["foo", "bar", "foo", "baz"]);
+    let mut search = Search::new(buf.len(), MessageBox::new());
+    search.toggle_mode();
+    search.toggle_mode();
+    for c in r"foo\nba".chars() {
+        search.push_search_word(c);
+    }
+    search.update_status(Cursor { row: 2, col: 0 }, &buf);
+    assert_eq!(search.status(), Some("2/2".to_owned()));
+    assert_eq!(search.hits(0), vec![(0, 6)]);
+
+    // an invalid regex hits nothing
+    search.push_search_word('(');
+    assert_eq!(search.next(Cursor { row: 0, col: 0 }, &buf), None);
+}
+
+#[test]
+fn test_regex_hit_after_edit_below() {
+    let mut buf = to_buf(&["foo", "bar"]);
+    let mut search = Search::new(buf.len(), MessageBox::new());
+    search.toggle_mode();
+    search.toggle_mode();
+    for c in r"foo\nba".chars() {
+        search.push_search_word(c);
+    }
+    search.update_cache(0..2, &buf);
+    assert_eq!(search.hits(0), vec![(0, 6)]);
+
+    // rewrite the second row as the editor does
+    buf.remove(1);
+    search.cache_remove_line(1);
+    buf.insert(1, to_buf(&["qux"]).remove(0));
+    search.cache_insert_new_line(1);
+    search.update_cache(0..2, &buf);
+    assert!(search.hits(0).is_empty());
+}
@@ -0,0 +1,40 @@
+use super::BufElem;
+use super::rope::Rope;
+
+/// Read access to the rows of a text.
+/// The search, the highlighter and the views only need this
+/// so they don't depend on how the rows are actually stored.
+pub trait Storage {
+    fn len(&self) -> usize;
+    fn line(&self, row: usize) -> &[BufElem];
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Storage for Rope<Vec<BufElem>> {
+    fn len(&self) -> usize {
+        Rope::len(self)
+    }
+    fn line(&self, row: usize) -> &[BufElem] {
+        &self[row]
+    }
+}
+
+impl Storage for [Vec<BufElem>] {
+    fn len(&self) -> usize {
+        <[Vec<BufElem>]>::len(self)
+    }
+    fn line(&self, row: usize) -> &[BufElem] {
+        &self[row]
+    }
+}
+
+impl Storage for Vec<Vec<BufElem>> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+    fn line(&self, row: usize) -> &[BufElem] {
+        &self[row]
+    }
+}
@@ -0,0 +1,246 @@
+use super::{BufElem, Cursor, CursorRange, Storage, Line, is_word_char};
+
+// The text objects after i (inside) or a (around).
+//
+// iw aw: word
+// i( a( ib ab: parens
+// i{ a{ iB aB: braces
+// i[ a[: brackets
+// i< a<: angle brackets
+// i" a" i' a' i` a`: quotes in the line
+// ip ap: paragraph
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextObject {
+    Word,
+    Pair(char, char),
+    Quote(char),
+    Paragraph,
+}
+impl TextObject {
+    pub fn from_char(c: char) -> Option<Self> {
+        let x = match c {
+            'w' => TextObject::Word,
+            '(' | ')' | 'b' => TextObject::Pair('(', ')'),
+            '{' | '}' | 'B' => TextObject::Pair('{', '}'),
+            '[' | ']' => TextObject::Pair('[', ']'),
+            '<' | '>' => TextObject::Pair('<', '>'),
+            '"' | '\'' | '`' => TextObject::Quote(c),
+            'p' => TextObject::Paragraph,
+            _ => return None,
+        };
+        Some(x)
+    }
+    /// The range covers whole lines.
+    pub fn linewise(self) -> bool {
+        self == TextObject::Paragraph
+    }
+    /// The range of the object around the cursor. The end is exclusive.
+    pub fn range<S: Storage + ?Sized>(self, buf: &S, cur: Cursor, inner: bool) -> Option<CursorRange> {
+        match self {
+            TextObject::Word => word(buf, cur, inner),
+            TextObject::Pair(open, close) => pair(buf, cur, open, close, inner),
+            TextObject::Quote(q) => quote(buf, cur, q, inner),
+            TextObject::Paragraph => paragraph(buf, cur, inner),
+        }
+    }
+}
+
+fn char_at<S: Storage + ?Sized>(buf: &S, c: Cursor) -> Option<char> {
+    match buf.line(c.row)[c.col] {
+        BufElem::Char(x) => Some(x),
+        BufElem::Eol => None,
+    }
+}
+fn next<S: Storage + ?Sized>(buf: &S, c: Cursor) -> Option<Cursor> {
+    if c.col + 1 < buf.line(c.row).len() {
+        Some(Cursor { row: c.row, col: c.col + 1 })
+    } else if c.row + 1 < buf.len() {
+        Some(Cursor { row: c.row + 1, col: 0 })
+    } else {
+        None
+    }
+}
+fn prev<S: Storage + ?Sized>(buf: &S, c: Cursor) -> Option<Cursor> {
+    if c.col > 0 {
+        Some(Cursor { row: c.row, col: c.col - 1 })
+    } else if c.row > 0 {
+        Some(Cursor { row: c.row - 1, col: buf.line(c.row - 1).len() - 1 })
+    } else {
+        None
+    }
+}
+
+fn word<S: Storage + ?Sized>(buf: &S, cur: Cursor, inner: bool) -> Option<CursorRange> {
+    let line = buf.line(cur.row);
+    if line[cur.col] == BufElem::Eol {
+        return None
+    }
+    // the run of the chars of the same class
+    let class = |e: &BufElem| (is_word_char(e), *e == BufElem::Char(' ') || *e == BufElem::Char('\t'));
+    let k = class(&line[cur.col]);
+    let same = |i: usize| line[i] != BufElem::Eol && class(&line[i]) == k;
+    let start = (0..cur.col+1).rev().take_while(|&i| same(i)).last().unwrap();
+    let mut end = (cur.col..line.len()).take_while(|&i| same(i)).last().unwrap() + 1;
+    if !inner {
+        // the trailing spaces
+        while line[end] == BufElem::Char(' ') || line[end] == BufElem::Char('\t') {
+            end += 1;
+        }
+    }
+    Some(CursorRange {
+        start: Cursor { row: cur.row, col: start },
+        end: Cursor { row: cur.row, col: end },
+    })
+}
+
+fn pair<S: Storage + ?Sized>(buf: &S, cur: Cursor, open: char, close: char, inner: bool) -> Option<CursorRange> {
+    // find the unclosed open char backward. the close char under the cursor is a part of the pair.
+    let mut c = if char_at(buf, cur) == Some(close) { prev(buf, cur)? } else { cur };
+    let mut depth = 0;
+    let start = loop {
+        match char_at(buf, c) {
+            Some(x) if x == open && depth == 0 => break c,
+            Some(x) if x == open => depth -= 1,
+            Some(x) if x == close => depth += 1,
+            _ => {},
+        }
+        c = prev(buf, c)?;
+    };
+    let mut c = next(buf, start)?;
+    let mut depth = 0;
+    let end = loop {
+        match char_at(buf, c) {
+            Some(x) if x == close && depth == 0 => break c,
+            Some(x) if x == close => depth -= 1,
+            Some(x) if x == open => depth += 1,
+            _ => {},
+        }
+        c = next(buf, c)?;
+    };
+    if !inner {
+        return Some(CursorRange { start, end: Cursor { row: end.row, col: end.col + 1 } })
+    }
+    let after_open = start.col + 1 == buf.line(start.row).len() - 1;
+    let before_close = end.col == Line::new(buf.line(end.row)).first_non_space_index();
+    if after_open && before_close && end.row > start.row {
+        // the lines between the pair. the line of the close char is kept.
+        // {      {
+        //   a => }
+        // }
+        let eol = |row: usize| Cursor { row, col: buf.line(row).len() - 1 };
+        return Some(CursorRange { start: eol(start.row), end: eol(end.row - 1) })
+    }
+    Some(CursorRange { start: Cursor { row: start.row, col: start.col + 1 }, end })
+}
+
+fn quote<S: Storage + ?Sized>(buf: &S, cur: Cursor, q: char, inner: bool) -> Option<CursorRange> {
+    let line = buf.line(cur.row);
+    let mut quotes = vec![];
+    for i in 0..line.len() {
+        let escaped = i > 0 && line[i-1] == BufElem::Char('\\');
+        if line[i] == BufElem::Char(q) && !escaped {
+            quotes.push(i);
+        }
+    }
+    // the first pair that ends at or after the cursor
+    let (a, b) = quotes.chunks(2).filter(|x| x.len() == 2).map(|x| (x[0], x[1])).find(|&(_, b)| cur.col <= b)?;
+    let (start, mut end) = if inner { (a + 1, b) } else { (a, b + 1) };
+    if !inner {
+        while line[end] == BufElem::Char(' ') || line[end] == BufElem::Char('\t') {
+            end += 1;
+        }
+    }
+    Some(CursorRange {
+        start: Cursor { row: cur.row, col: start },
+        end: Cursor { row: cur.row, col: end },
+    })
+}
+
+fn is_blank(line: &[BufElem]) -> bool {
+    line.iter().all(|e| *e == BufElem::Eol || *e == BufElem::Char(' ') || *e == BufElem::Char('\t'))
+}
+fn paragraph<S: Storage + ?Sized>(buf: &S, cur: Cursor, inner: bool) -> Option<CursorRange> {
+    let blank = is_blank(buf.line(cur.row));
+    let same = |row: usize| is_blank(buf.line(row)) == blank;
+    let mut top = cur.row;
+    while top > 0 && same(top - 1) {
+        top -= 1;
+    }
+    let mut bottom = cur.row;
+    while bottom + 1 < buf.len() && same(bottom + 1) {
+        bottom += 1;
+    }
+    if !inner {
+        // the following blank lines or the preceding ones at the end of the text
+        let other = |row: usize| is_blank(buf.line(row)) != blank;
+        if bottom + 1 < buf.len() {
+            while bottom + 1 < buf.len() && other(bottom + 1) {
+                bottom += 1;
+            }
+        } else {
+            while top > 0 && other(top - 1) {
+                top -= 1;
+            }
+        }
+    }
+    Some(CursorRange {
+        start: Cursor { row: top, col: 0 },
+        end: Cursor { row: bottom, col: buf.line(bottom).len() },
+    })
+}
+
+#[cfg(test)]
+fn to_buf(s: &str) -> Vec<Vec<BufElem>> {
+    super::read_from_string(Some(s.to_owned()))
+}
+#[cfg(test)]
+fn cursor(row: usize, col: usize) -> Cursor {
+    Cursor { row, col }
+}
+#[cfg(test)]
+fn span(x: Option<CursorRange>) -> Option<((usize, usize), (usize, usize))> {
+    x.map(|r| ((r.start.row, r.start.col), (r.end.row, r.end.col)))
+}
+
+#[test]
+fn test_text_object_pair() {
+    let buf = to_buf("f(a, (b), c)\nfn f() {\n    x;\n}");
+    let paren = TextObject::from_char('(').unwrap();
+    assert_eq!(span(paren.range(&buf, cursor(0, 2), true)), Some(((0, 2), (0, 11))));
+    assert_eq!(span(paren.range(&buf, cursor(0, 2), false)), Some(((0, 1), (0, 12))));
+    // nested
+    assert_eq!(span(paren.range(&buf, cursor(0, 6), true)), Some(((0, 6), (0, 7))));
+    // on the close char
+    assert_eq!(span(paren.range(&buf, cursor(0, 11), true)), Some(((0, 2), (0, 11))));
+    assert_eq!(span(paren.range(&buf, cursor(0, 0), true)), None);
+
+    // multiple lines
+    let brace = TextObject::from_char('B').unwrap();
+    assert_eq!(span(brace.range(&buf, cursor(2, 4), true)), Some(((1, 8), (2, 6))));
+    assert_eq!(span(brace.range(&buf, cursor(2, 4), false)), Some(((1, 7), (3, 1))));
+}
+
+#[test]
+fn test_text_object_quote_and_word() {
+    let buf = to_buf(r#"say("a \" b", 'c')"#);
+    let dq = TextObject::from_char('"').unwrap();
+    assert_eq!(span(dq.range(&buf, cursor(0, 0), true)), Some(((0, 5), (0, 11))));
+    assert_eq!(span(dq.range(&buf, cursor(0, 7), false)), Some(((0, 4), (0, 12))));
+    assert_eq!(span(TextObject::Quote('\'').range(&buf, cursor(0, 15), true)), Some(((0, 15), (0, 16))));
+
+    let buf = to_buf("ab  cd");
+    assert_eq!(span(TextObject::Word.range(&buf, cursor(0, 1), true)), Some(((0, 0), (0, 2))));
+    assert_eq!(span(TextObject::Word.range(&buf, cursor(0, 1), false)), Some(((0, 0), (0, 4))));
+    assert_eq!(span(TextObject::Word.range(&buf, cursor(0, 2), true)), Some(((0, 2), (0, 4))));
+}
+
+#[test]
+fn test_text_object_paragraph() {
+    let buf = to_buf("a\nb\n\n\nc\n");
+    let p = TextObject::Paragraph;
+    assert_eq!(span(p.range(&buf, cursor(1, 0), true)), Some(((0, 0), (1, 2))));
+    assert_eq!(span(p.range(&buf, cursor(1, 0), false)), Some(((0, 0), (3, 1))));
+    // the preceding blank lines for the last paragraph
+    assert_eq!(span(p.range(&buf, cursor(4, 0), false)), Some(((2, 0), (4, 2))));
+}
@@ -1,6 +1,7 @@
 use crate::view;
 use super::Cursor;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct VisibilityWindow {
     cur_cursor: Cursor,
     pub col_low: usize,
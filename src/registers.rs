@@ -0,0 +1,219 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::edit_buffer::clipboard::{self, Type};
+use crate::controller::{self, PageState};
+use crate::view;
+use crate::navigator::{self, Navigator};
+use crate::read_buffer::{self, BufElem, ReadBuffer};
+use crate::screen::Color;
+use crate::message_box::MessageBox;
+use crate::read_buffer::INIT;
+
+/// Lists the contents of the registers to pick one to paste.
+pub struct Registers {
+    pub rb: ReadBuffer,
+    names: Vec<char>,
+    navigator: Rc<RefCell<Navigator>>,
+    state: PageState,
+    message_box: MessageBox,
+}
+impl Registers {
+    pub fn open(navigator: Rc<RefCell<Navigator>>) -> Self {
+        let state = PageState::new(INIT.to_owned());
+        let message_box = MessageBox::new();
+        let mut r = Self {
+            rb: ReadBuffer::new(vec![], state.clone(), message_box.clone()), // not valid
+            names: vec![],
+            navigator,
+            state,
+            message_box,
+        };
+        r.refresh();
+        r
+    }
+    fn update_cache(&mut self) {
+
+    }
+    pub fn refresh(&mut self) {
+        self.names.clear();
+        let mut v = vec![];
+        for (c, x) in clipboard::list() {
+            self.names.push(c);
+            let kind = match x {
+                Type::Range(_) => 'c',
+                Type::Line(_) => 'l',
+            };
+            // "a l first line\nsecond line
+            let mut vv: Vec<BufElem> = format!("\"{} {} ", c, kind).chars().map(BufElem::Char).collect();
+            for e in x.elems() {
+                match *e {
+                    BufElem::Char(c) => vv.push(BufElem::Char(c)),
+                    BufElem::Eol => vv.extend("\\n".chars().map(BufElem::Char)),
+                }
+            }
+            vv.push(BufElem::Eol);
+            v.push(vv);
+        }
+        if v.is_empty() {
+            v.push(vec![BufElem::Eol]);
+        }
+        let cursor = self.rb.cursor;
+        self.rb = ReadBuffer::new(v, self.state.clone(), self.message_box.clone());
+        self.rb.cursor = cursor;
+        self.rb.stabilize_cursor();
+    }
+
+    //
+    // eff functions
+    //
+
+    // goes back to the buffer and pastes by "ap
+    fn eff_select(&mut self, k: Key) -> String {
+        let c = match self.names.get(self.rb.cursor.row) {
+            Some(c) => *c,
+            None => return INIT.to_owned(),
+        };
+        let paste = match k {
+            Key::Char('P') => 'P',
+            _ => 'p',
+        };
+        self.navigator.borrow_mut().pop();
+        let page = self.navigator.borrow().current_page();
+        if page.borrow().kind() != navigator::PageKind::Buffer {
+            return INIT.to_owned()
+        }
+        for k in &['"', c, paste] {
+            page.borrow().controller().receive(Key::Char(*k));
+        }
+        INIT.to_owned()
+    }
+    fn eff_refresh(&mut self, _: Key) -> String {
+        self.refresh();
+        INIT.to_owned()
+    }
+}
+
+use crate::controller::Effect;
+use crate::def_effect;
+use crate::Key;
+
+def_effect!(Select, Registers, eff_select);
+def_effect!(Refresh, Registers, eff_refresh);
+
+use crate::shared::AsRefMut;
+pub fn mk_controller(x: Rc<RefCell<Registers>>) -> controller::ControllerFSM {
+    use crate::Key::*;
+    let mut g = controller::Graph::new();
+    read_buffer::add_edges(&mut g, x.clone().map(|x| &mut x.rb));
+
+    g.add_edge(INIT, Char('\n'), Rc::new(Select(x.clone())));
+    g.add_edge(INIT, Char('p'), Rc::new(Select(x.clone())));
+    g.add_edge(INIT, Char('P'), Rc::new(Select(x.clone())));
+    g.add_edge(INIT, Ctrl('r'), Rc::new(Refresh(x.clone())));
+    crate::keymap::apply(&mut g, navigator::PageKind::Registers);
+    controller::ControllerFSM::new(INIT, Box::new(g))
+}
+
+struct AddColor<'a> {
+    x: &'a Registers,
+}
+impl <'a> view::View for AddColor<'a> {
+    fn get(&self, col: usize, row: usize) -> view::ViewElem {
+        if row < self.x.names.len() && col < 4 {
+            (None, Some(Color::LightRed), None)
+        } else {
+            (None, None, None)
+        }
+    }
+}
+
+struct ViewGen {
+    x: Rc<RefCell<Registers>>,
+}
+fn gen_impl(x: &mut Registers, region: view::Area) -> Box<dyn view::View> {
+    x.rb.stabilize_cursor();
+    x.rb.adjust_window(region.width, region.height);
+    x.update_cache();
+
+    let (_, reg_area) = region.split_horizontal(view::LINE_NUMBER_W);
+
+    let reg_view = view::ToView::new(&x.rb.buf);
+    let reg_view = view::OverlayView::new(reg_view, AddColor { x });
+
+    let add_cursor = view::AddCursor::new(x.rb.cursor);
+    let reg_view = view::OverlayView::new(reg_view, add_cursor);
+
+    let reg_view = view::TranslateView::new(
+        reg_view,
+        reg_area.col as i32 - x.rb.window.col() as i32,
+        reg_area.row as i32 - x.rb.window.row() as i32,
+    );
+
+    let lineno_range = x.rb.lineno_range();
+    let lineno_view = view::LineNumber {
+        from: lineno_range.start+1,
+        to: lineno_range.end,
+    };
+
+    let view = view::MergeHorizontal {
+        left: lineno_view,
+        right: reg_view,
+        col_offset: reg_area.col,
+    };
+
+    let view = view::CloneView::new(view, region);
+    Box::new(view)
+}
+impl view::ViewGen for ViewGen {
+    fn gen(&mut self, region: view::Area) -> Box<dyn view::View> {
+        gen_impl(&mut self.x.borrow_mut(), region)
+    }
+}
+
+pub struct Page {
+    controller: Box<dyn controller::Controller>,
+    view_gen: Box<dyn view::ViewGen>,
+    x: Rc<RefCell<Registers>>,
+}
+impl Page {
+    pub fn new(x: Rc<RefCell<Registers>>) -> Self {
+        Self {
+            controller: Box::new(mk_controller(x.clone())),
+            view_gen: Box::new(ViewGen { x: x.clone() }),
+            x,
+        }
+    }
+}
+impl navigator::Page for Page {
+    fn controller(&self) -> &Box<dyn controller::Controller> {
+        &self.controller
+    }
+    fn view_gen(&mut self) -> &mut Box<dyn view::ViewGen> {
+        &mut self.view_gen
+    }
+    fn status(&self) -> String {
+        let state: &str = match self.x.borrow().state.get().as_str() {
+            read_buffer::INIT => "*",
+            read_buffer::SEARCH => "/",
+            _ => "*",
+        };
+        format!("[Registers -{}-]", state)
+    }
+    fn kind(&self) -> navigator::PageKind {
+        navigator::PageKind::Registers
+    }
+    fn id(&self) -> String {
+        "registers".to_owned()
+    }
+    fn message(&self) -> MessageBox {
+        self.x.borrow().message_box.clone()
+    }
+}
+
+/// Opens the list of the registers. The contents are read again if it's already open.
+pub fn open(navigator: Rc<RefCell<Navigator>>) {
+    let x = Rc::new(RefCell::new(Registers::open(navigator.clone())));
+    let page = Rc::new(RefCell::new(Page::new(x)));
+    navigator.borrow_mut().close("registers");
+    navigator.borrow_mut().push(page);
+}
@@ -0,0 +1,126 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use crate::directory;
+use crate::edit_buffer::{self, EditBuffer};
+use crate::navigator::{Navigator, Page, PageKind};
+use crate::read_buffer::{Cursor, ReadBuffer};
+use crate::read_buffer::visibility_window::VisibilityWindow;
+
+// ijk --session <name> restores the pages in ~/.ijk/sessions/<name>.json
+// and the session is saved there on exit.
+// Without --session nothing is restored and the pages are saved in the default session,
+// so ijk --session default brings back the last plain ijk.
+//
+// Only the buffers and the directories are saved. The other pages are temporary.
+
+/// The page to open again and where the cursor was.
+#[derive(Serialize, Deserialize)]
+pub struct PageSession {
+    kind: String,
+    path: PathBuf,
+    cursor: Cursor,
+    window: VisibilityWindow,
+}
+impl PageSession {
+    pub fn new(kind: PageKind, path: &Path, rb: &ReadBuffer) -> Self {
+        Self {
+            kind: kind.name().to_owned(),
+            // to open from any directory
+            path: fs::canonicalize(path).unwrap_or_else(|_| path.to_owned()),
+            cursor: rb.cursor,
+            window: rb.window.clone(),
+        }
+    }
+    // the file may have changed since
+    fn apply(&self, rb: &mut ReadBuffer) {
+        rb.cursor = self.cursor;
+        rb.window = self.window.clone();
+        rb.stabilize_cursor();
+    }
+    fn open(&self, navigator: Rc<RefCell<Navigator>>) -> Option<Rc<RefCell<dyn Page>>> {
+        if !self.path.exists() {
+            return None
+        }
+        let page: Rc<RefCell<dyn Page>> = match self.kind.as_str() {
+            "buffer" => {
+                let eb = Rc::new(RefCell::new(EditBuffer::open(&self.path, navigator)));
+                self.apply(&mut eb.borrow_mut().rb);
+                Rc::new(RefCell::new(edit_buffer::Page::new(eb)))
+            },
+            "directory" => {
+                let dir = Rc::new(RefCell::new(directory::Directory::open(&self.path, navigator)));
+                self.apply(&mut dir.borrow_mut().rb);
+                Rc::new(RefCell::new(directory::Page::new(dir, fs::canonicalize(&self.path).ok()?)))
+            },
+            _ => return None,
+        };
+        Some(page)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct WorkspaceSession {
+    name: String,
+    pages: Vec<PageSession>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Session {
+    active: usize,
+    workspaces: Vec<WorkspaceSession>,
+}
+
+/// The session saved without --session.
+pub const DEFAULT_SESSION: &str = "default";
+
+/// ~/.ijk/sessions/<name>.json
+/// The name is a file name so it can't point out of the directory.
+pub fn session_path(name: &str) -> Result<PathBuf, String> {
+    if name.is_empty() || name.contains("..") || name.contains('/') || name.contains(std::path::MAIN_SEPARATOR) {
+        return Err(format!("invalid session name: {}", name))
+    }
+    Ok(crate::config::ijk_dir().join("sessions").join(format!("{}.json", name)))
+}
+
+/// Saves the pages in the workspaces in MRU order.
+pub fn save(path: &Path, navigator: &Navigator) -> Result<(), String> {
+    let (workspaces, active) = navigator.workspace_pages();
+    let workspaces = workspaces.into_iter().map(|(name, pages)| {
+        let pages = pages.iter().filter_map(|p| p.borrow().session()).collect();
+        WorkspaceSession { name, pages }
+    }).collect();
+    let s = serde_json::to_string_pretty(&Session { active, workspaces }).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, s).map_err(|e| e.to_string())
+}
+
+/// Opens the pages in the session. Returns false if there is no page to restore.
+pub fn restore(path: &Path, navigator: Rc<RefCell<Navigator>>) -> Result<bool, String> {
+    let s = match fs::read_to_string(path) {
+        Ok(x) => x,
+        Err(_) => return Ok(false),
+    };
+    let session: Session = serde_json::from_str(&s).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut workspaces = vec![];
+    let mut active = 0;
+    for (i, ws) in session.workspaces.iter().enumerate() {
+        let pages: Vec<_> = ws.pages.iter().filter_map(|p| p.open(navigator.clone())).collect();
+        // the workspace without pages can't have the current page
+        if pages.is_empty() {
+            continue
+        }
+        if i == session.active {
+            active = workspaces.len();
+        }
+        workspaces.push((ws.name.clone(), pages));
+    }
+    if workspaces.is_empty() {
+        return Ok(false)
+    }
+    navigator.borrow_mut().restore_workspaces(workspaces, active);
+    Ok(true)
+}
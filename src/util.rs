@@ -5,17 +5,36 @@ use std::path;
 fn to_term_key(s: &str) -> termion::event::Key {
     match s {
         "EOL" => Char('\n'),
-        c if c.starts_with('C') => Ctrl(c.chars().nth(2).unwrap()),
+        "BS" => Backspace,
+        c if c.starts_with("C-") => Ctrl(c.chars().nth(2).unwrap()),
         c => Char(c.chars().nth(0).unwrap()),
         _ => panic!(), // other keys are not necessary in benchmark
     }
 }
+/// Keys in the format of the keys file. One key per line.
+pub fn read_keys(s: &str) -> Vec<termion::event::Key> {
+    s.lines().filter(|line| !line.is_empty()).map(to_term_key).collect()
+}
+/// The inverse of read_keys. Esc is written as C-c.
+pub fn write_keys(keys: &[crate::Key]) -> String {
+    let mut s = String::new();
+    for k in keys {
+        let line = match *k {
+            crate::Key::Char('\n') => "EOL".to_owned(),
+            crate::Key::Char(c) => c.to_string(),
+            crate::Key::Ctrl(c) => format!("C-{}", c),
+            crate::Key::Esc => "C-c".to_owned(),
+            crate::Key::Backspace => "BS".to_owned(),
+            _ => continue,
+        };
+        s.push_str(&line);
+        s.push('\n');
+    }
+    s
+}
 pub fn read_keys_file(path: &path::Path) -> Vec<Result<termion::event::Key, std::io::Error>> {
-    let mut v = vec![];
     let s = fs::read_to_string(path).unwrap();
-    for line in s.lines() {
-        v.push(Ok(to_term_key(line)))
-    }
+    let mut v: Vec<_> = read_keys(&s).into_iter().map(Ok).collect();
     v.push(Ok(Ctrl('z')));
     v
 }
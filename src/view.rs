@@ -1,7 +1,7 @@
 extern crate flame;
 
 use crate::screen::Color;
-use crate::read_buffer::{BufElem, Cursor};
+use crate::read_buffer::{BufElem, Cursor, Storage};
 
 impl From<syntect::highlighting::Color> for Color {
     fn from(c: syntect::highlighting::Color) -> Color {
@@ -89,29 +89,29 @@ impl Area {
     }
 }
 
-pub struct BufArea<'a, T> {
-    backing: &'a [Vec<T>],
+pub struct BufArea<'a, S: ?Sized> {
+    backing: &'a S,
 }
-impl <'a, T> BufArea<'a, T> {
-    pub fn new(backing: &'a [Vec<T>]) -> Self {
+impl <'a, S: Storage + ?Sized> BufArea<'a, S> {
+    pub fn new(backing: &'a S) -> Self {
         Self { backing }
     }
-    fn get(&self, col: usize, row: usize) -> Option<&T> {
-        if row > self.backing.len() - 1 || col > self.backing[row].len() - 1 {
+    fn get(&self, col: usize, row: usize) -> Option<&BufElem> {
+        if row >= self.backing.len() {
             None
         } else {
-            Some(&self.backing[row][col])
+            self.backing.line(row).get(col)
         }
     }
-    pub fn map(self, f: fn(&T) -> ViewElem) -> Map<'a, T> {
+    pub fn map(self, f: fn(&BufElem) -> ViewElem) -> Map<'a, S> {
         Map { backing: self, f }
     }
 }
-pub struct Map<'a, T> {
-    backing: BufArea<'a, T>,
-    f: fn(&T) -> ViewElem,
+pub struct Map<'a, S: ?Sized> {
+    backing: BufArea<'a, S>,
+    f: fn(&BufElem) -> ViewElem,
 }
-impl <'a, T> View for Map<'a, T> {
+impl <'a, S: Storage + ?Sized> View for Map<'a, S> {
     fn get(&self, col: usize, row: usize) -> ViewElem {
         match self.backing.get(col, row) {
             None => (None, None, None),
@@ -274,20 +274,20 @@ impl View for CloneView {
     }
 }
 
-pub struct ToView<'a> {
-    pub back: &'a[Vec<BufElem>],
+pub struct ToView<'a, S: ?Sized> {
+    pub back: &'a S,
 }
-impl <'a> ToView<'a> {
-    pub fn new(back: &'a[Vec<BufElem>]) -> Self {
+impl <'a, S: Storage + ?Sized> ToView<'a, S> {
+    pub fn new(back: &'a S) -> Self {
         Self { back }
     }
 }
-impl <'a> View for ToView<'a> {
+impl <'a, S: Storage + ?Sized> View for ToView<'a, S> {
     fn get(&self, col: usize, row: usize) -> ViewElem {
-        if row > self.back.len() - 1 || col > self.back[row].len() - 1 {
+        if row > self.back.len() - 1 || col > self.back.line(row).len() - 1 {
             (Some(' '), Some(default_fg()), Some(default_bg()))
         } else {
-            let e = &self.back[row][col];
+            let e = &self.back.line(row)[col];
             let c = match *e {
                 BufElem::Char(c) => c,
                 BufElem::Eol => ' ',
@@ -0,0 +1,226 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+// The files and directories shown in the pages are watched in a background thread.
+// The thread sends the changed paths and wakes up the editor loop
+// which tells the pages showing them.
+//
+// inotify is used on Linux. The paths are polled if it's not available.
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The modified time and the length of the file.
+/// The page compares them to tell the changes made by others from its own.
+pub type Stamp = (SystemTime, u64);
+
+pub fn stamp(path: &Path) -> Option<Stamp> {
+    let meta = std::fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+pub struct Watcher {
+    paths: Arc<Mutex<BTreeSet<PathBuf>>>,
+    rx: Receiver<PathBuf>,
+    stop: Arc<AtomicBool>,
+}
+impl Watcher {
+    /// wake is called in the thread after the changes are sent.
+    pub fn new<F: Fn() + Send + 'static>(wake: F) -> Self {
+        let paths = Arc::new(Mutex::new(BTreeSet::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+        let (paths_t, stop_t) = (paths.clone(), stop.clone());
+        std::thread::spawn(move || run(paths_t, stop_t, tx, wake));
+        Self { paths, rx, stop }
+    }
+    /// Replaces the paths to watch.
+    pub fn set_paths(&self, paths: BTreeSet<PathBuf>) {
+        *self.paths.lock().unwrap() = paths;
+    }
+    /// The paths changed since the last call.
+    pub fn changes(&self) -> BTreeSet<PathBuf> {
+        self.rx.try_iter().collect()
+    }
+}
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn run<F: Fn()>(paths: Arc<Mutex<BTreeSet<PathBuf>>>, stop: Arc<AtomicBool>, tx: Sender<PathBuf>, wake: F) {
+    let mut backend = match inotify::Inotify::new() {
+        Some(x) => Backend::Inotify(x),
+        None => Backend::Poll(HashMap::new()),
+    };
+    while !stop.load(Ordering::Relaxed) {
+        let paths = paths.lock().unwrap().clone();
+        let changed = backend.wait(&paths);
+        if changed.is_empty() {
+            continue
+        }
+        for path in changed {
+            if tx.send(path).is_err() {
+                return
+            }
+        }
+        wake();
+    }
+}
+
+enum Backend {
+    Inotify(inotify::Inotify),
+    Poll(HashMap<PathBuf, Option<Stamp>>),
+}
+impl Backend {
+    /// Waits for the changes at most the interval.
+    fn wait(&mut self, paths: &BTreeSet<PathBuf>) -> BTreeSet<PathBuf> {
+        match self {
+            Backend::Inotify(x) => x.wait(paths),
+            Backend::Poll(stamps) => {
+                std::thread::sleep(POLL_INTERVAL);
+                stamps.retain(|path, _| paths.contains(path));
+                let mut changed = BTreeSet::new();
+                for path in paths {
+                    let cur = stamp(path);
+                    match stamps.insert(path.clone(), cur) {
+                        Some(prev) if prev != cur => {
+                            changed.insert(path.clone());
+                        },
+                        _ => {},
+                    }
+                }
+                changed
+            },
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::collections::{BTreeSet, HashMap};
+    use std::ffi::{CString, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+    use super::POLL_INTERVAL;
+
+    // the directories are watched because the editors often replace the file by renaming
+    const MASK: u32 = libc::IN_MODIFY | libc::IN_CLOSE_WRITE | libc::IN_CREATE | libc::IN_DELETE
+        | libc::IN_MOVED_FROM | libc::IN_MOVED_TO | libc::IN_ATTRIB;
+
+    pub struct Inotify {
+        fd: i32,
+        dirs: HashMap<PathBuf, i32>,
+    }
+    impl Inotify {
+        pub fn new() -> Option<Self> {
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return None
+            }
+            Some(Self { fd, dirs: HashMap::new() })
+        }
+        fn sync(&mut self, paths: &BTreeSet<PathBuf>) {
+            let dirs: BTreeSet<PathBuf> = paths.iter().filter_map(|p| {
+                if p.is_dir() { Some(p.clone()) } else { p.parent().map(|x| x.to_owned()) }
+            }).collect();
+            let fd = self.fd;
+            self.dirs.retain(|dir, wd| {
+                let keep = dirs.contains(dir);
+                if !keep {
+                    unsafe { libc::inotify_rm_watch(fd, *wd) };
+                }
+                keep
+            });
+            for dir in dirs {
+                if self.dirs.contains_key(&dir) {
+                    continue
+                }
+                let s = match CString::new(dir.as_os_str().as_bytes()) {
+                    Ok(x) => x,
+                    Err(_) => continue,
+                };
+                let wd = unsafe { libc::inotify_add_watch(fd, s.as_ptr(), MASK) };
+                if wd >= 0 {
+                    self.dirs.insert(dir, wd);
+                }
+            }
+        }
+        pub fn wait(&mut self, paths: &BTreeSet<PathBuf>) -> BTreeSet<PathBuf> {
+            self.sync(paths);
+            let mut changed = BTreeSet::new();
+            let mut pfd = libc::pollfd { fd: self.fd, events: libc::POLLIN, revents: 0 };
+            let n = unsafe { libc::poll(&mut pfd, 1, POLL_INTERVAL.as_millis() as i32) };
+            if n <= 0 {
+                return changed
+            }
+            let mut buf = [0u8; 4096];
+            loop {
+                let len = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+                if len <= 0 {
+                    break
+                }
+                let mut i = 0;
+                let header = std::mem::size_of::<libc::inotify_event>();
+                while i + header <= len as usize {
+                    let ev: libc::inotify_event = unsafe { std::ptr::read_unaligned(buf[i..].as_ptr() as *const _) };
+                    let name = &buf[i + header..i + header + ev.len as usize];
+                    let name = &name[..name.iter().position(|&c| c == 0).unwrap_or(name.len())];
+                    if let Some((dir, _)) = self.dirs.iter().find(|(_, wd)| **wd == ev.wd) {
+                        let file = dir.join(OsStr::from_bytes(name));
+                        if paths.contains(&file) {
+                            changed.insert(file);
+                        }
+                        if paths.contains(dir) {
+                            changed.insert(dir.clone());
+                        }
+                    }
+                    i += header + ev.len as usize;
+                }
+            }
+            changed
+        }
+    }
+    impl Drop for Inotify {
+        fn drop(&mut self) {
+            unsafe { libc::close(self.fd) };
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod inotify {
+    use std::collections::BTreeSet;
+    use std::path::PathBuf;
+
+    pub struct Inotify;
+    impl Inotify {
+        pub fn new() -> Option<Self> {
+            None
+        }
+        pub fn wait(&mut self, _: &BTreeSet<PathBuf>) -> BTreeSet<PathBuf> {
+            BTreeSet::new()
+        }
+    }
+}
+
+#[test]
+fn test_watch_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.txt");
+    std::fs::write(&path, "a\n").unwrap();
+    let (tx, rx) = mpsc::channel();
+    let watcher = Watcher::new(move || { let _ = tx.send(()); });
+    let mut paths = BTreeSet::new();
+    paths.insert(path.clone());
+    watcher.set_paths(paths);
+    // the thread starts to watch within the interval
+    std::thread::sleep(POLL_INTERVAL * 3);
+    std::fs::write(&path, "ab\n").unwrap();
+    rx.recv_timeout(POLL_INTERVAL * 10).unwrap();
+    assert!(watcher.changes().contains(&path));
+}
@@ -0,0 +1,25 @@
+mod common;
+
+use std::fs;
+use ijk::Key;
+use ijk::navigator::Page;
+use common::{edit, input};
+
+#[test]
+fn test_history() {
+    let f = edit("a.txt", "aaa\n");
+
+    input(&f.page, ":s/a/b/\n:w\n");
+    // C-p twice goes back to the substitution
+    input(&f.page, ":");
+    for k in "pp".chars() {
+        f.page.borrow().controller().receive(Key::Ctrl(k));
+    }
+    input(&f.page, "\n:");
+    // s, w, s then C-n forward to w
+    for k in "pppn".chars() {
+        f.page.borrow().controller().receive(Key::Ctrl(k));
+    }
+    input(&f.page, "\n");
+    assert_eq!(fs::read_to_string(&f.path).unwrap(), "bba\n");
+}
@@ -0,0 +1,66 @@
+// the helpers shared by the tests. not every test uses all of them.
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Once;
+use tempfile::TempDir;
+use ijk::Key;
+use ijk::edit_buffer::{self, EditBuffer};
+use ijk::navigator::{Navigator, Page};
+use ijk::read_buffer::BufElem;
+
+pub fn input<P: Page + ?Sized>(page: &RefCell<P>, keys: &str) {
+    for k in keys.chars() {
+        page.borrow().controller().receive(Key::Char(k));
+    }
+}
+
+/// Points ~/.ijk (e.g. the swap files) to the target directory.
+/// Must be called before the config is read.
+pub fn isolate() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| std::env::set_var("IJK_DIR", Path::new(env!("CARGO_TARGET_TMPDIR")).join("ijk")));
+}
+
+/// A new file in a new tempdir.
+pub fn file(name: &str, content: impl AsRef<[u8]>) -> (TempDir, PathBuf) {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(name);
+    fs::write(&path, content).unwrap();
+    (dir, path)
+}
+
+/// Opens the file in a new page on the navigator.
+pub fn open(navigator: &Rc<RefCell<Navigator>>, path: &Path) -> (Rc<RefCell<EditBuffer>>, Rc<RefCell<edit_buffer::Page>>) {
+    isolate();
+    let eb = Rc::new(RefCell::new(EditBuffer::open(path, navigator.clone())));
+    let page = Rc::new(RefCell::new(edit_buffer::Page::new(eb.clone())));
+    navigator.borrow_mut().push(page.clone());
+    (eb, page)
+}
+
+/// A new file opened in the editor.
+pub struct Fixture {
+    pub dir: TempDir,
+    pub path: PathBuf,
+    pub navigator: Rc<RefCell<Navigator>>,
+    pub eb: Rc<RefCell<EditBuffer>>,
+    pub page: Rc<RefCell<edit_buffer::Page>>,
+}
+pub fn edit(name: &str, content: impl AsRef<[u8]>) -> Fixture {
+    let (dir, path) = file(name, content);
+    let navigator = Rc::new(RefCell::new(Navigator::new()));
+    let (eb, page) = open(&navigator, &path);
+    Fixture { dir, path, navigator, eb, page }
+}
+
+pub fn text(eb: &Rc<RefCell<EditBuffer>>) -> String {
+    eb.borrow().rb.buf.to_vec().concat().into_iter().map(|e| match e {
+        BufElem::Char(c) => c,
+        BufElem::Eol => '\n',
+    }).collect()
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use std::fs;
+use ijk::navigator::Page;
+use common::{edit, input};
+
+#[test]
+fn test_preserve_format() {
+    let f = edit("a.txt", b"\xEF\xBB\xBFa\r\nb");
+    assert!(f.page.borrow().status().contains("[utf-8-bom|crlf|noeol]"));
+    input(&f.page, "x:w\n");
+    assert_eq!(fs::read(&f.path).unwrap(), b"\xEF\xBB\xBF\r\nb");
+}
+
+#[test]
+fn test_convert_format() {
+    let f = edit("a.txt", b"caf\xE9\r\n");
+    assert!(f.page.borrow().status().contains("[latin1|crlf]"));
+    input(&f.page, ":set lineending=lf\n");
+    // converting makes the buffer dirty
+    assert!(f.page.borrow().status().contains("[D]"));
+    input(&f.page, ":set encoding=utf-8\n:w\n");
+    assert_eq!(fs::read_to_string(&f.path).unwrap(), "café\n");
+    assert!(!f.page.borrow().status().contains("[D]"));
+}
+
+#[test]
+fn test_refuse_unencodable() {
+    let f = edit("a.txt", "あ\n");
+    input(&f.page, ":set encoding=latin1\n");
+    assert!(f.page.borrow().status().contains("[utf-8|lf]"));
+    assert!(!f.page.borrow().status().contains("[D]"));
+}
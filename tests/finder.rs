@@ -0,0 +1,30 @@
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+use ijk::Key;
+use ijk::finder::{self, Finder};
+use ijk::navigator::{self, Navigator, Page};
+
+#[test]
+fn test_finder() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("src/edit_buffer")).unwrap();
+    fs::write(dir.path().join("src/edit_buffer/mod.rs"), "").unwrap();
+    fs::write(dir.path().join("src/web.rs"), "").unwrap();
+    fs::write(dir.path().join("README.md"), "").unwrap();
+
+    let navigator = Rc::new(RefCell::new(Navigator::new()));
+    let x = Rc::new(RefCell::new(Finder::open(dir.path(), false, navigator.clone())));
+    let page = finder::Page::new(x.clone());
+    assert_eq!(x.borrow().matches().len(), 3);
+
+    for k in "eb".chars() {
+        page.controller().receive(Key::Char(k));
+    }
+    assert_eq!(x.borrow().matches(), vec!["src/edit_buffer/mod.rs", "src/web.rs"]);
+    page.controller().receive(Key::Down);
+    page.controller().receive(Key::Char('\n'));
+    let current = navigator.borrow().current_page();
+    assert!(current.borrow().kind() == navigator::PageKind::Buffer);
+    assert!(current.borrow().id().ends_with("web.rs"));
+}
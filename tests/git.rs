@@ -0,0 +1,108 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::rc::Rc;
+use ijk::Key;
+use ijk::git::{self, Marker, Repo};
+use ijk::git::status::{self, GitStatus};
+use ijk::navigator::{self, Navigator, Page};
+use ijk::read_buffer;
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let out = Command::new("git")
+        .arg("-C").arg(dir)
+        .args(["-c", "user.name=ijk", "-c", "user.email=ijk@example.com"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    String::from_utf8(out.stdout).unwrap()
+}
+
+fn init_repo(dir: &Path) {
+    git(dir, &["init", "-q"]);
+    fs::write(dir.join("a.txt"), "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n").unwrap();
+    git(dir, &["add", "a.txt"]);
+    git(dir, &["commit", "-q", "-m", "init"]);
+}
+
+#[test]
+fn test_git_gutter() {
+    let dir = tempfile::tempdir().unwrap();
+    init_repo(dir.path());
+    let path = dir.path().join("a.txt");
+
+    let mut gutter = git::Gutter::open(&path).unwrap();
+    // modify 2, add after 5, delete 9
+    let buf = read_buffer::read_from_string(Some("1\ntwo\n3\n4\n5\nnew\n6\n7\n8\n10\n".to_owned()));
+    gutter.update(&buf, 0);
+    assert_eq!(gutter.marker(0), None);
+    assert_eq!(gutter.marker(1), Some(Marker::Modified));
+    assert_eq!(gutter.marker(5), Some(Marker::Added));
+    assert_eq!(gutter.marker(8), Some(Marker::Deleted));
+    assert_eq!(gutter.marker(9), None);
+
+    // committed: diffed against the new HEAD
+    fs::write(&path, "1\ntwo\n3\n4\n5\n6\n7\n8\n9\n10\n").unwrap();
+    git(dir.path(), &["commit", "-q", "-am", "two"]);
+    git::head_changed();
+    gutter.update(&buf, 0);
+    assert_eq!(gutter.marker(1), None);
+    assert_eq!(gutter.marker(5), Some(Marker::Added));
+
+    // not in HEAD
+    fs::write(dir.path().join("b.txt"), "b\n").unwrap();
+    assert!(git::Gutter::open(&dir.path().join("b.txt")).is_none());
+}
+
+#[test]
+fn test_git_status_stage_hunk() {
+    let dir = tempfile::tempdir().unwrap();
+    init_repo(dir.path());
+    fs::write(dir.path().join("a.txt"), "one\n2\n3\n4\n5\n6\n7\n8\n9\nten\n").unwrap();
+    fs::write(dir.path().join("b.txt"), "b\n").unwrap();
+
+    let navigator = Rc::new(RefCell::new(Navigator::new()));
+    let repo = Repo::discover(dir.path()).unwrap();
+    let x = Rc::new(RefCell::new(GitStatus::open(repo, navigator.clone())));
+    let page = status::Page::new(x.clone());
+    let lines = |x: &Rc<RefCell<GitStatus>>| -> Vec<String> {
+        x.borrow().rb.buf.to_vec().iter().map(|l| {
+            l.iter().filter_map(|e| match e {
+                read_buffer::BufElem::Char(c) => Some(*c),
+                read_buffer::BufElem::Eol => None,
+            }).collect()
+        }).collect()
+    };
+    assert_eq!(lines(&x), vec![
+        " M a.txt",
+        "    unstaged @@ -1 +1 @@",
+        "    unstaged @@ -10 +10 @@",
+        "?? b.txt",
+    ]);
+
+    // stage the second hunk
+    x.borrow_mut().rb.cursor.row = 2;
+    page.controller().receive(Key::Char('s'));
+    assert_eq!(git(dir.path(), &["diff", "--cached", "--numstat"]), "1\t1\ta.txt\n");
+    assert!(git(dir.path(), &["diff", "--cached"]).contains("+ten"));
+    assert_eq!(lines(&x)[1..3], ["    unstaged @@ -1 +1 @@", "    staged   @@ -10 +10 @@"]);
+
+    // stage the untracked file
+    x.borrow_mut().rb.cursor.row = 3;
+    page.controller().receive(Key::Char('s'));
+    assert_eq!(lines(&x)[3], "A  b.txt");
+
+    // unstage the hunk again
+    x.borrow_mut().rb.cursor.row = 2;
+    page.controller().receive(Key::Char('u'));
+    assert_eq!(git(dir.path(), &["diff", "--cached", "--name-only"]), "b.txt\n");
+
+    // open the file at the hunk
+    x.borrow_mut().rb.cursor.row = 2;
+    page.controller().receive(Key::Char('\n'));
+    let current = navigator.borrow().current_page();
+    assert!(current.borrow().kind() == navigator::PageKind::Buffer);
+    assert!(current.borrow().id().ends_with("a.txt"));
+}
@@ -0,0 +1,30 @@
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+use ijk::Key;
+use ijk::directory::{self, Directory};
+use ijk::navigator::{self, Navigator, Page};
+
+#[test]
+fn test_grep_from_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "one\ntwo three\n").unwrap();
+    fs::write(dir.path().join(".b.txt"), "three\n").unwrap();
+
+    let navigator = Rc::new(RefCell::new(Navigator::new()));
+    let x = Rc::new(RefCell::new(Directory::open(dir.path(), navigator.clone())));
+    let page = directory::Page::new(x, dir.path().to_owned());
+    for k in "fthree\n".chars() {
+        page.controller().receive(Key::Char(k));
+    }
+    let current = navigator.borrow().current_page();
+    assert!(current.borrow().kind() == navigator::PageKind::Grep);
+    // the hidden file is skipped
+    assert!(current.borrow().status().contains("(1 hits)"));
+
+    // jump to the hit
+    current.borrow().controller().receive(Key::Char('\n'));
+    let current = navigator.borrow().current_page();
+    assert!(current.borrow().kind() == navigator::PageKind::Buffer);
+    assert!(current.borrow().id().ends_with("a.txt"));
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use ijk::large_file::{self, LargeFile};
+use ijk::navigator::Page;
+use ijk::read_buffer::Cursor;
+use common::{file, input};
+
+#[test]
+fn test_text() {
+    let s: String = (0..1000).map(|i| format!("line {}\n", i)).collect();
+    let (_dir, path) = file("a.log", s);
+    // a small text file goes to the edit buffer
+    assert!(large_file::open(&path, 0).is_none());
+
+    let x = Rc::new(RefCell::new(LargeFile::open(&path).unwrap()));
+    let page = RefCell::new(large_file::Page::new(x.clone()));
+    input(&page, "G");
+    assert_eq!(x.borrow().cursor, Cursor { row: 999, col: 0 });
+    assert!(page.borrow().status().contains("[text, 1000 rows, read-only]"));
+    input(&page, "/line 5\n");
+    assert_eq!(x.borrow().cursor, Cursor { row: 5, col: 0 });
+    input(&page, "nl");
+    assert_eq!(x.borrow().cursor, Cursor { row: 50, col: 1 });
+
+    // the cursor stays on the byte
+    input(&page, "x");
+    assert!(page.borrow().status().contains("hex"));
+    let offset = (0..50).map(|i| format!("line {}\n", i).len()).sum::<usize>() + 1;
+    assert_eq!(x.borrow().cursor, Cursor { row: offset / 16, col: 10 + offset % 16 * 3 });
+    input(&page, "x");
+    assert_eq!(x.borrow().cursor, Cursor { row: 50, col: 1 });
+
+    input(&page, "10");
+    assert!(page.borrow().status().starts_with("[LargeFile -10-]"));
+    input(&page, "G");
+    assert_eq!(x.borrow().cursor, Cursor { row: 9, col: 0 });
+    assert!(page.borrow().status().starts_with("[LargeFile -*-]"));
+    input(&page, "/");
+    assert!(page.borrow().status().starts_with("[LargeFile -/-]"));
+}
+
+#[test]
+fn test_binary() {
+    let (_dir, path) = file("a.bin", b"\x7fELF\x00\x01\x02");
+    let page = large_file::open(&path, 0).unwrap();
+    assert!(page.borrow().status().contains("[hex, 1 rows, read-only]"));
+}
@@ -0,0 +1,79 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use ijk::edit_buffer::change_log::ChangeLog;
+use ijk::lsp::{Client, Document, Severity};
+use ijk::read_buffer::{self, BufElem, Cursor};
+
+fn start_fake_server(init_delay_ms: u64) -> Arc<Client> {
+    let command = vec![env!("CARGO_BIN_EXE_ijk-fake-lsp").to_owned(), init_delay_ms.to_string()];
+    Client::start(&command, &std::env::current_dir().unwrap()).unwrap()
+}
+
+fn to_elems(s: &str) -> Vec<BufElem> {
+    s.chars().map(|c| if c == '\n' { BufElem::Eol } else { BufElem::Char(c) }).collect()
+}
+
+// diagnostics are published asynchronously
+fn wait_for(doc: &Document, expected: &[(usize, Severity)]) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let actual: Vec<(usize, Severity)> = doc.diagnostics().iter().map(|d| (d.row, d.severity)).collect();
+        if actual == expected {
+            return
+        }
+        if Instant::now() > deadline {
+            panic!("diagnostics {:?} != {:?}", actual, expected);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn test_lsp_sync_and_diagnostics() {
+    let client = start_fake_server(0);
+    let buf = read_buffer::read_from_string(Some("fn main() {\n    error\n}\n".to_owned()));
+    let mut doc = Document::open(client.clone(), Path::new("fake.rs"), "rust", &buf);
+    wait_for(&doc, &[(1, Severity::Error)]);
+
+    // "    error" -> "    ok"
+    let log = ChangeLog::new(Cursor { row: 1, col: 4 }, to_elems("error"), to_elems("ok"));
+    doc.did_change(&log, &to_elems("    "));
+    wait_for(&doc, &[]);
+
+    // a change across lines: "{\n    ok\n}" -> "{\nwarning\n}"
+    let log = ChangeLog::new(Cursor { row: 0, col: 11 }, to_elems("\n    ok"), to_elems("\nwarning"));
+    doc.did_change(&log, &to_elems("fn main() {"));
+    wait_for(&doc, &[(1, Severity::Warning)]);
+
+    let log = ChangeLog::new(Cursor { row: 1, col: 0 }, to_elems("warning"), to_elems("error"));
+    doc.did_change(&log, &[]);
+    wait_for(&doc, &[(1, Severity::Error)]);
+
+    // the column is sent in UTF-16 code units where the emoji counts two
+    let log = ChangeLog::new(Cursor { row: 1, col: 0 }, vec![], to_elems("\u{1F600} "));
+    doc.did_change(&log, &[]);
+    let log = ChangeLog::new(Cursor { row: 1, col: 2 }, to_elems("e"), vec![]);
+    doc.did_change(&log, &to_elems("\u{1F600} "));
+    wait_for(&doc, &[]);
+
+    drop(doc);
+    client.shutdown();
+}
+
+#[test]
+fn test_lsp_slow_initialize() {
+    let t = Instant::now();
+    let client = start_fake_server(1000);
+    let buf = read_buffer::read_from_string(Some("error\n".to_owned()));
+    let mut doc = Document::open(client.clone(), Path::new("slow.rs"), "rust", &buf);
+    let log = ChangeLog::new(Cursor { row: 0, col: 0 }, vec![], to_elems("\n"));
+    doc.did_change(&log, &[]);
+    // not blocked by the server
+    assert!(t.elapsed() < Duration::from_millis(500));
+    // sent after initialized
+    wait_for(&doc, &[(1, Severity::Error)]);
+
+    drop(doc);
+    client.shutdown();
+}
@@ -0,0 +1,38 @@
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+use ijk::Key;
+use ijk::edit_buffer::{self, EditBuffer};
+use ijk::navigator::{self, Navigator, Page};
+use ijk::read_buffer::BufElem;
+use ijk::registers;
+
+#[test]
+fn test_paste_from_registers_page() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.txt");
+    fs::write(&path, "a\nb\n").unwrap();
+
+    let navigator = Rc::new(RefCell::new(Navigator::new()));
+    let eb = Rc::new(RefCell::new(EditBuffer::open(&path, navigator.clone())));
+    let page = Rc::new(RefCell::new(edit_buffer::Page::new(eb.clone())));
+    navigator.borrow_mut().push(page.clone());
+    // "ryy then dd
+    for k in "\"ryydd".chars() {
+        page.borrow().controller().receive(Key::Char(k));
+    }
+
+    registers::open(navigator.clone());
+    let current = navigator.borrow().current_page();
+    assert!(current.borrow().kind() == navigator::PageKind::Registers);
+    // the unnamed register, "1 and "r
+    for k in "jj\n".chars() {
+        current.borrow().controller().receive(Key::Char(k));
+    }
+    assert!(navigator.borrow().current_page().borrow().kind() == navigator::PageKind::Buffer);
+    let text: String = eb.borrow().rb.buf.to_vec().concat().into_iter().map(|e| match e {
+        BufElem::Char(c) => c,
+        BufElem::Eol => '\n',
+    }).collect();
+    assert_eq!(text, "b\na\n");
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+use ijk::directory::{self, Directory};
+use ijk::navigator::{Navigator, Page};
+use ijk::read_buffer::INIT;
+use common::{edit, input, text};
+
+#[test]
+fn test_reload_buffer() {
+    let f = edit("a.txt", "a\nb\nc\n");
+    let (path, eb, page) = (&f.path, &f.eb, &f.page);
+    input(page, "j");
+
+    // clean: reloaded keeping the cursor
+    fs::write(path, "x\ny\nz\nw\n").unwrap();
+    page.borrow().changed_on_disk();
+    assert_eq!(text(eb), "x\ny\nz\nw\n");
+    assert_eq!(eb.borrow().rb.cursor.row, 1);
+
+    // dirty: asks first
+    input(page, "x");
+    fs::write(path, "p\n").unwrap();
+    page.borrow().changed_on_disk();
+    assert_eq!(page.borrow().controller().state(), "ReloadConfirm");
+    assert_eq!(text(eb), "x\n\nz\nw\n");
+    input(page, "y");
+    assert_eq!(page.borrow().controller().state(), INIT);
+    assert_eq!(text(eb), "p\n");
+
+    // the local changes are back by undo
+    input(page, "u");
+    assert_eq!(text(eb), "x\n\nz\nw\n");
+}
+
+#[test]
+fn test_refresh_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "").unwrap();
+
+    let navigator = Rc::new(RefCell::new(Navigator::new()));
+    let d = Rc::new(RefCell::new(Directory::open(dir.path(), navigator.clone())));
+    let page = directory::Page::new(d.clone(), fs::canonicalize(dir.path()).unwrap());
+    let n = d.borrow().rb.buf.len();
+    fs::write(dir.path().join("b.txt"), "").unwrap();
+    page.changed_on_disk();
+    assert_eq!(d.borrow().rb.buf.len(), n + 1);
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+use ijk::navigator::{Navigator, Page};
+use common::{edit, input, open};
+
+#[test]
+fn test_refuse_to_clobber() {
+    let f = edit("a.txt", "a\nb\n");
+
+    input(&f.page, "x");
+    fs::write(&f.path, "changed\n").unwrap();
+    input(&f.page, ":w\n");
+    assert_eq!(fs::read_to_string(&f.path).unwrap(), "changed\n");
+    input(&f.page, ":w!\n");
+    assert_eq!(fs::read_to_string(&f.path).unwrap(), "\nb\n");
+    // no temporary file is left
+    assert_eq!(fs::read_dir(f.dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_report_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let sub = dir.path().join("sub");
+    fs::create_dir(&sub).unwrap();
+    let path = sub.join("a.txt");
+    fs::write(&path, "a\n").unwrap();
+    let navigator = Rc::new(RefCell::new(Navigator::new()));
+    let (_, page) = open(&navigator, &path);
+
+    // the temporary file can't be created
+    fs::remove_dir_all(&sub).unwrap();
+    input(&page, "x:w\n");
+    assert!(!sub.exists());
+    // still dirty
+    assert!(page.borrow().status().contains("[D]"));
+}
@@ -0,0 +1,54 @@
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+use ijk::Key;
+use ijk::directory::{self, Directory};
+use ijk::edit_buffer::{self, EditBuffer};
+use ijk::navigator::{self, Navigator, Page};
+use ijk::session;
+
+#[test]
+fn test_save_and_restore() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.txt");
+    fs::write(&path, "a\nb\nc\n").unwrap();
+    let session_path = dir.path().join("sessions").join("s.json");
+
+    let navigator = Rc::new(RefCell::new(Navigator::new()));
+    let d = Rc::new(RefCell::new(Directory::open(dir.path(), navigator.clone())));
+    let page = Rc::new(RefCell::new(directory::Page::new(d, fs::canonicalize(dir.path()).unwrap())));
+    navigator.borrow_mut().push(page);
+    let eb = Rc::new(RefCell::new(EditBuffer::open(&path, navigator.clone())));
+    let page = Rc::new(RefCell::new(edit_buffer::Page::new(eb)));
+    navigator.borrow_mut().push(page.clone());
+    for k in "jjl".chars() {
+        page.borrow().controller().receive(Key::Char(k));
+    }
+    session::save(&session_path, &navigator.borrow()).unwrap();
+
+    let navigator = Rc::new(RefCell::new(Navigator::new()));
+    assert!(session::restore(&session_path, navigator.clone()).unwrap());
+    let (workspaces, _) = navigator.borrow().workspace_pages();
+    let kinds: Vec<_> = workspaces[0].1.iter().map(|p| p.borrow().kind()).collect();
+    assert!(kinds == vec![navigator::PageKind::Buffer, navigator::PageKind::Directory]);
+    let current = navigator.borrow().current_page();
+    assert_eq!(current.borrow().id(), fs::canonicalize(&path).unwrap().to_str().unwrap());
+
+    // the cursor is back
+    session::save(&session_path, &navigator.borrow()).unwrap();
+    let s: serde_json::Value = serde_json::from_str(&fs::read_to_string(&session_path).unwrap()).unwrap();
+    let cursor = &s["workspaces"][0]["pages"][0]["cursor"];
+    assert_eq!((cursor["row"].as_u64(), cursor["col"].as_u64()), (Some(2), Some(1)));
+
+    assert!(!session::restore(&dir.path().join("none.json"), Rc::new(RefCell::new(Navigator::new()))).unwrap());
+}
+
+#[test]
+fn test_session_name() {
+    let path = session::session_path("work").unwrap();
+    assert!(path.ends_with("sessions/work.json"));
+    assert!(session::session_path(session::DEFAULT_SESSION).is_ok());
+    for name in &["", "../x", "../../x", "a/b", ".."] {
+        assert!(session::session_path(name).is_err(), "{}", name);
+    }
+}
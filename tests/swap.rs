@@ -0,0 +1,61 @@
+mod common;
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use ijk::edit_buffer;
+use ijk::edit_buffer::change_log::{self, ChangeLog};
+use ijk::edit_buffer::swap::swap_file_path;
+use ijk::navigator::{Navigator, Page};
+use ijk::read_buffer::{BufElem, Cursor, INIT};
+use common::{file, input, open, text};
+
+// as if ijk crashed while editing the file
+fn leave_swap_file(path: &Path) {
+    let fingerprint = change_log::fingerprint(&edit_buffer::read_buffer(path));
+    let log = ChangeLog::new(Cursor { row: 0, col: 0 }, vec![], vec![BufElem::Char('x')]);
+    let s = format!("{{\"fingerprint\":{},\"pid\":0}}\n{}\n", fingerprint, serde_json::to_string(&log).unwrap());
+    let swap_path = swap_file_path(path);
+    fs::create_dir_all(swap_path.parent().unwrap()).unwrap();
+    fs::write(swap_path, s).unwrap();
+}
+
+#[test]
+fn test_recover() {
+    let (_dir, path) = file("a.txt", "a\nb\n");
+    // not in ~/.ijk
+    assert!(swap_file_path(&path).starts_with(env!("CARGO_TARGET_TMPDIR")));
+    leave_swap_file(&path);
+    let navigator = Rc::new(RefCell::new(Navigator::new()));
+
+    let (eb, page) = open(&navigator, &path);
+    assert_eq!(page.borrow().controller().state(), "RecoverConfirm");
+    input(&page, "y");
+    assert_eq!(page.borrow().controller().state(), INIT);
+    assert_eq!(text(&eb), "xa\nb\n");
+    assert!(page.borrow().status().contains("[D]"));
+
+    // still unsaved
+    page.borrow().quit();
+    assert_eq!(fs::read_to_string(swap_file_path(&path)).unwrap().lines().count(), 2);
+    input(&page, "u");
+    page.borrow().quit();
+    assert!(!swap_file_path(&path).exists());
+}
+
+#[test]
+fn test_discard() {
+    let (_dir, path) = file("a.txt", "a\nb\n");
+    leave_swap_file(&path);
+    let navigator = Rc::new(RefCell::new(Navigator::new()));
+
+    let (eb, page) = open(&navigator, &path);
+    input(&page, "n");
+    assert_eq!(text(&eb), "a\nb\n");
+    assert!(!swap_file_path(&path).exists());
+
+    // no prompt next time
+    let (_, page) = open(&navigator, &path);
+    assert_eq!(page.borrow().controller().state(), INIT);
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+use ijk::navigator::{Navigator, NavigatorPage, Page};
+use ijk::view::Area;
+use common::{file, open};
+
+fn input(page: &Rc<RefCell<dyn Page>>, keys: &str) {
+    // refreshes the list
+    page.borrow_mut().view_gen().gen(Area { col: 0, row: 0, width: 80, height: 20 });
+    common::input(page, keys);
+}
+
+#[test]
+fn test_switch_workspaces() {
+    let (dir, a) = file("a.txt", "a\n");
+    let b = dir.path().join("b.txt");
+    fs::write(&b, "b\n").unwrap();
+
+    let navigator = Rc::new(RefCell::new(Navigator::new()));
+    let navi_page: Rc<RefCell<dyn Page>> = Rc::new(RefCell::new(NavigatorPage::new(navigator.clone())));
+    let pa: Rc<RefCell<dyn Page>> = open(&navigator, &a).1;
+
+    // the new workspace starts with the current page
+    navigator.borrow_mut().set(navi_page.clone());
+    input(&navi_page, "wfront\n");
+    assert_eq!(navigator.borrow().workspaces(), (vec!["default".to_owned(), "front".to_owned()], 1));
+    let pb: Rc<RefCell<dyn Page>> = open(&navigator, &b).1;
+    assert!(Rc::ptr_eq(&navigator.borrow().current_page(), &pb));
+
+    // the first line is the default workspace
+    navigator.borrow_mut().set(navi_page.clone());
+    input(&navi_page, "kk\n");
+    assert_eq!(navigator.borrow().workspaces().1, 0);
+    // b isn't in the default workspace
+    input(&navi_page, "\n");
+    assert!(Rc::ptr_eq(&navigator.borrow().current_page(), &pa));
+
+    // the active workspace can't be closed
+    navigator.borrow_mut().set(navi_page.clone());
+    input(&navi_page, "kkd");
+    assert_eq!(navigator.borrow().workspaces().0.len(), 2);
+    input(&navi_page, "jd");
+    assert_eq!(navigator.borrow().workspaces(), (vec!["default".to_owned()], 0));
+}
+
+#[test]
+fn test_delete_dirty_workspace() {
+    let (dir, a) = file("a.txt", "a\n");
+    let b = dir.path().join("b.txt");
+    fs::write(&b, "b\n").unwrap();
+
+    let navigator = Rc::new(RefCell::new(Navigator::new()));
+    let navi_page: Rc<RefCell<dyn Page>> = Rc::new(RefCell::new(NavigatorPage::new(navigator.clone())));
+    open(&navigator, &a);
+    navigator.borrow_mut().set(navi_page.clone());
+    input(&navi_page, "wfront\n");
+    let pb: Rc<RefCell<dyn Page>> = open(&navigator, &b).1;
+    input(&pb, "x");
+
+    // back to the default workspace
+    navigator.borrow_mut().set(navi_page.clone());
+    input(&navi_page, "kk\n");
+    assert_eq!(navigator.borrow().workspaces().1, 0);
+
+    // b isn't saved
+    navigator.borrow_mut().set(navi_page.clone());
+    input(&navi_page, "kd");
+    assert_eq!(navigator.borrow().workspaces().0.len(), 2);
+    assert!(pb.borrow().dirty());
+
+    common::input(&pb, ":w\n");
+    input(&navi_page, "d");
+    assert_eq!(navigator.borrow().workspaces(), (vec!["default".to_owned()], 0));
+    assert_eq!(fs::read_to_string(&b).unwrap(), "\n");
+}